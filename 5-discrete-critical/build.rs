@@ -0,0 +1,31 @@
+#![allow(non_snake_case)]
+
+use std::process::Command;
+
+/**
+ * Record the commit the binary is built from and whether the tree had uncommitted changes,
+ * read back with option_env! for the provenance of each run
+ */
+fn main() {
+    let git = |args: &[&str]| Command::new("git").args(args).output().ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    if let Some(commit) = git(&["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=SANDPILE_GIT_COMMIT={}", commit);
+    }
+    if let Some(status) = git(&["status", "--porcelain", "--untracked-files=no"]) {
+        println!("cargo:rustc-env=SANDPILE_GIT_DIRTY={}", !status.is_empty());
+    }
+
+    // build again when the commit moves or the sources change
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=build.rs");
+    if let Some(gitDir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", gitDir);
+        println!("cargo:rerun-if-changed={}/index", gitDir);
+        if let Some(reference) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", gitDir, reference);
+        }
+    }
+}
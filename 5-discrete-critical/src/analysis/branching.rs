@@ -0,0 +1,202 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput};
+
+// constants
+use crate::util::constants::{BRANCHING_WINDOW, BRANCHING_GENERATIONS, CRITICALITY_TOLERANCE, POWER_LAW_CONFIDENCE_Z};
+
+
+/**
+ * Mean branching ratio σ of a set of avalanches, the grains released per grain set moving,
+ * so σ < 1 dies out, σ > 1 grows and σ = 1 is critical
+ *
+ * σ is the ratio of the grains in generation g + 1 to those in generation g over the first
+ * BRANCHING_GENERATIONS generations of the causal trees, counting the generations after a tree
+ * dies out as empty. Over whole trees the ratio is always 1 - avalanches / grains set moving,
+ * below 1 however large the avalanches, as the finite pile ends every tree
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct BranchingEstimate {
+    // grains set moving in the generations the ratio is taken over
+    pub nodes: u64,
+    // grains released by those grains, in the generation after each
+    pub children: u64,
+    pub sigma: f64,
+    // standard error of sigma from the spread of the generation sizes about sigma times the previous generation
+    pub sigmaError: f64,
+}
+
+impl BranchingEstimate {
+    /**
+     * Estimate σ from the generation sizes of the avalanches, None when they have no trees
+     */
+    pub fn estimate(avalanches: &[Avalanche]) -> Option<Self> {
+        let mut nodes: u64 = 0;
+        let mut children: u64 = 0;
+        // sum of children^2 / nodes and count of the generations with grains, for the offspring variance
+        let mut sumOfSquares: f64 = 0.0;
+        let mut generations: u64 = 0;
+        for avalanche in avalanches {
+            let sizes = avalanche.generationSizes();
+            for g in 0..BRANCHING_GENERATIONS.max(1) {
+                let size = sizes.get(g).copied().unwrap_or(0) as u64;
+                if size == 0 {
+                    break;
+                }
+                let next = sizes.get(g + 1).copied().unwrap_or(0) as u64;
+                nodes += size;
+                children += next;
+                sumOfSquares += (next * next) as f64 / size as f64;
+                generations += 1;
+            }
+        }
+        if nodes == 0 {
+            return None;
+        }
+
+        // each grain of a generation releases grains with variance v, so generation g + 1 varies by v times generation g
+        let sigma = children as f64 / nodes as f64;
+        let offspringVariance = ((sumOfSquares - 2.0 * sigma * children as f64 + sigma * sigma * nodes as f64) / generations as f64).max(0.0);
+        Some(BranchingEstimate { nodes, children, sigma, sigmaError: (offspringVariance / nodes as f64).sqrt() })
+    }
+
+    /**
+     * sub-critical, critical or super-critical, critical when the confidence interval of σ at
+     * POWER_LAW_CONFIDENCE_Z reaches within CRITICALITY_TOLERANCE of 1
+     */
+    pub fn regime(&self) -> &'static str {
+        criticalityRegime(self.sigma, self.sigmaError)
+    }
+}
+
+/**
+ * Regime of a branching ratio and its standard error, see BranchingEstimate::regime
+ */
+pub fn criticalityRegime(sigma: f64, sigmaError: f64) -> &'static str {
+    let margin = POWER_LAW_CONFIDENCE_Z * sigmaError + CRITICALITY_TOLERANCE;
+    if sigma + margin < 1.0 {
+        "sub-critical"
+    } else if sigma - margin > 1.0 {
+        "super-critical"
+    } else {
+        "critical"
+    }
+}
+
+/**
+ * Estimate σ over the run, from the grain the pile became stationary at when it did as the
+ * pile is still building before then
+ */
+pub fn runBranchingEstimate(avalanches: &[Avalanche], stationaryAtGrain: Option<usize>) -> Option<BranchingEstimate> {
+    let start = stationaryAtGrain.unwrap_or(0).min(avalanches.len());
+    BranchingEstimate::estimate(&avalanches[start..])
+}
+
+/**
+ * Export σ over each window of BRANCHING_WINDOW avalanches to branching-ratio.csv and the
+ * estimate for the run with its regime to criticality.txt
+ */
+pub fn displayBranchingRatio(avalanches: &[Avalanche], stationaryAtGrain: Option<usize>, folder_path: String) -> io::Result<()> {
+    let schema = CsvSchema::new("branching-ratio", "branching-ratio.csv", "Mean branching ratio of the avalanches over each window of BRANCHING_WINDOW grains", vec![
+        CsvColumn::new("grain_index_start", "integer", "grain index", "First grain of the window"),
+        CsvColumn::new("grain_index_end", "integer", "grain index", "Last grain of the window"),
+        CsvColumn::new("nodes", "integer", "grains", "Grains set moving in the first BRANCHING_GENERATIONS generations of the avalanches of the window"),
+        CsvColumn::new("children", "integer", "grains", "Grains released by an impact of those grains"),
+        CsvColumn::new("sigma", "float", "grains per grain", "Mean grains released per grain set moving in those generations"),
+        CsvColumn::new("sigma_error", "float", "grains per grain", "Standard error of sigma"),
+        CsvColumn::new("regime", "string", "", "sub-critical, critical or super-critical"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "branching-ratio.csv", &schema)?;
+
+    for (w, window) in avalanches.chunks(BRANCHING_WINDOW.max(1)).enumerate() {
+        let Some(estimate) = BranchingEstimate::estimate(window) else {
+            continue;
+        };
+        let start = w * BRANCHING_WINDOW.max(1);
+        writer.writeRow(&[
+            start.to_string(),
+            (start + window.len() - 1).to_string(),
+            estimate.nodes.to_string(),
+            estimate.children.to_string(),
+            format!("{:.6}", estimate.sigma),
+            format!("{:.6}", estimate.sigmaError),
+            estimate.regime().to_string(),
+        ])?;
+    }
+    writer.finish()?;
+
+    // Create a file and wrap it in a BufWriter for efficient writing
+    let file = File::create(folder_path.clone() + "/criticality.txt")?;
+    let mut writer = BufWriter::new(file);
+    registerOutput(&folder_path, "criticality.txt", "text", "Mean branching ratio of the run and whether the pile is sub-critical, critical or super-critical", None);
+
+    writeln!( writer, "Criticality")?;
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    match stationaryAtGrain {
+        Some(grainIndex) => writeln!( writer, "Avalanches: from grain {}, where the pile became stationary, to grain {}", grainIndex, avalanches.len().saturating_sub(1))?,
+        None => writeln!( writer, "Avalanches: all {}, the pile did not become stationary", avalanches.len())?,
+    }
+    match runBranchingEstimate(avalanches, stationaryAtGrain) {
+        Some(estimate) => {
+            writeln!( writer, "Grains set moving in the first {} generations: {}", BRANCHING_GENERATIONS, estimate.nodes)?;
+            writeln!( writer, "Grains they released by impacts: {}", estimate.children)?;
+            writeln!( writer, "Branching ratio sigma: {:.4} ± {:.4} (confidence interval at z={}: [{:.4}, {:.4}])", estimate.sigma, estimate.sigmaError,
+                POWER_LAW_CONFIDENCE_Z, estimate.sigma - POWER_LAW_CONFIDENCE_Z * estimate.sigmaError, estimate.sigma + POWER_LAW_CONFIDENCE_Z * estimate.sigmaError)?;
+            writeln!( writer, "Regime (critical within {} of 1): {}", CRITICALITY_TOLERANCE, estimate.regime())?;
+        },
+        None => writeln!( writer, "Branching ratio sigma: none, no avalanches were recorded")?,
+    }
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+
+    // flush the writer to ensure all data is written to the file
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimateOf(generationSizes: &[&[usize]]) -> Option<BranchingEstimate> {
+        let avalanches: Vec<Avalanche> = generationSizes.iter().enumerate()
+            .map(|(i, sizes)| Avalanche::withGenerationSizes(i as u32, sizes.to_vec()))
+            .collect();
+        BranchingEstimate::estimate(&avalanches)
+    }
+
+    #[test]
+    fn estimateOfAChainIsCritical() {
+        let estimate = estimateOf(&[&[1, 1, 1, 1, 1], &[1, 1, 1, 1, 1]]).unwrap();
+        assert_eq!((estimate.nodes, estimate.children), (8, 8));
+        assert_eq!(estimate.sigma, 1.0);
+        assert_eq!(estimate.sigmaError, 0.0);
+        assert_eq!(estimate.regime(), "critical");
+    }
+
+    #[test]
+    fn estimateStopsAtTheGenerationLimit() {
+        // the generations from BRANCHING_GENERATIONS on are left out, as are those after a tree dies out
+        let mut sizes = vec![1; BRANCHING_GENERATIONS + 1];
+        sizes.push(1000);
+        let estimate = estimateOf(&[&sizes, &[2, 1]]).unwrap();
+        assert_eq!(estimate.nodes, BRANCHING_GENERATIONS as u64 + 3);
+        assert_eq!(estimate.children, BRANCHING_GENERATIONS as u64 + 1);
+
+        let growing = estimateOf(&[&[1, 3, 9, 27, 81, 243]]).unwrap();
+        assert_eq!(growing.sigma, 3.0);
+        assert_eq!(growing.regime(), "super-critical");
+        assert!(estimateOf(&[&[], &[]]).is_none());
+    }
+
+    #[test]
+    fn regimeAllowsForTheError() {
+        assert_eq!(criticalityRegime(1.0 - CRITICALITY_TOLERANCE / 2.0, 0.0), "critical");
+        assert_eq!(criticalityRegime(0.8, 0.0), "sub-critical");
+        assert_eq!(criticalityRegime(0.8, 0.2 / POWER_LAW_CONFIDENCE_Z), "critical");
+        assert_eq!(criticalityRegime(1.2, 0.0), "super-critical");
+    }
+}
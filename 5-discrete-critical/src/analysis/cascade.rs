@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+
+/**
+ * Branching ratio of each generation of a cascade, the grains released in the next generation for
+ * each grain in the generation, the last generation has a ratio of 0 as its grains released none
+ */
+pub fn branchingRatios(generationSizes: &[usize]) -> Vec<f64> {
+    generationSizes.iter().enumerate()
+        .map(|(g, &size)| {
+            let children = generationSizes.get(g + 1).copied().unwrap_or(0);
+            if size > 0 { children as f64 / size as f64 } else { 0.0 }
+        })
+        .collect()
+}
+
+/**
+ * Export the causal tree of every avalanche that kept one, those involving at least
+ * CASCADE_MIN_AVALANCHE_GRAINS grains, to the cascades folder of the run
+ *  cascades/cascade-tree.csv - every grain set moving, the impact that released it and its parent in the tree
+ *  cascades/cascade-generations.csv - grains and branching ratio of each generation of each avalanche
+ */
+pub fn displayAvalancheCascades(avalanches: &[Avalanche], folder_path: String) -> io::Result<()> {
+    fs::create_dir_all(folder_path.clone() + "/cascades")?;
+
+    let schema = CsvSchema::new("cascade-tree", "cascades/cascade-tree.csv", "Causal tree of each avalanche, one row for each grain set moving", vec![
+        CsvColumn::new("grain_index", "integer", "grain index", "Index of the grain dropped to start the avalanche"),
+        CsvColumn::new("node", "integer", "node", "Index of the node in the tree of the avalanche, 0 for the dropped grain"),
+        CsvColumn::new("grain_id", "integer", "grain id", "Grain set moving"),
+        CsvColumn::new("parent_node", "integer", "node", "Node of the grain whose impact released the grain, empty for the dropped grain"),
+        CsvColumn::new("generation", "integer", "generation", "Impacts between the dropped grain and the grain"),
+        CsvColumn::new("impact_x", "integer", "location", "x of the location hit by the impact that released the grain, empty for the dropped grain"),
+        CsvColumn::new("impact_y", "integer", "location", "y of the location hit by the impact that released the grain, empty for the dropped grain"),
+        CsvColumn::new("impact_z", "integer", "location", "z of the location hit by the impact that released the grain, empty for the dropped grain"),
+        CsvColumn::new("x", "integer", "location", "x the grain was released from"),
+        CsvColumn::new("y", "integer", "location", "y the grain was released from"),
+        CsvColumn::new("z", "integer", "location", "z the grain was released from"),
+        CsvColumn::new("step", "integer", "steps", "Time step of the avalanche the grain was set moving in"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "cascades/cascade-tree.csv", &schema)?;
+
+    for (i, avalanche) in avalanches.iter().enumerate() {
+        for (node, cascadeNode) in avalanche.cascade.iter().enumerate() {
+            let impact = |coordinate: fn(&(i32, i32, i32)) -> i32| cascadeNode.impactLocation.as_ref().map(|location| coordinate(location).to_string()).unwrap_or_default();
+            writer.writeRow(&[
+                i.to_string(),
+                node.to_string(),
+                cascadeNode.grainId.to_string(),
+                cascadeNode.parent.map(|parent| parent.to_string()).unwrap_or_default(),
+                cascadeNode.generation.to_string(),
+                impact(|location| location.0),
+                impact(|location| location.1),
+                impact(|location| location.2),
+                cascadeNode.location.0.to_string(),
+                cascadeNode.location.1.to_string(),
+                cascadeNode.location.2.to_string(),
+                cascadeNode.step.to_string(),
+            ])?;
+        }
+    }
+    writer.finish()?;
+
+    let schema = CsvSchema::new("cascade-generations", "cascades/cascade-generations.csv", "Grains and branching ratio of each generation of the causal tree of each avalanche", vec![
+        CsvColumn::new("grain_index", "integer", "grain index", "Index of the grain dropped to start the avalanche"),
+        CsvColumn::new("generation", "integer", "generation", "Generation of the tree, 0 for the dropped grain"),
+        CsvColumn::new("grains", "integer", "grains", "Grains set moving in the generation"),
+        CsvColumn::new("children", "integer", "grains", "Grains released by the impacts of the generation"),
+        CsvColumn::new("branching_ratio", "float", "grains per grain", "Grains released for each grain in the generation"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "cascades/cascade-generations.csv", &schema)?;
+
+    for (i, avalanche) in avalanches.iter().enumerate() {
+        if avalanche.cascade.is_empty() {
+            continue;
+        }
+        let sizes = avalanche.generationSizes();
+        for (generation, ratio) in branchingRatios(sizes).iter().enumerate() {
+            let children = sizes.get(generation + 1).copied().unwrap_or(0);
+            writer.writeRow(&[i.to_string(), generation.to_string(), sizes[generation].to_string(), children.to_string(), format!("{:.4}", ratio)])?;
+        }
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn branchingRatiosOfEachGeneration() {
+        assert_eq!(branchingRatios(&[1, 3, 6, 3]), vec![3.0, 2.0, 0.5, 0.0]);
+        // a generation without grains releases none rather than dividing by zero
+        assert_eq!(branchingRatios(&[2, 0, 1]), vec![0.0, 0.0, 0.0]);
+        assert!(branchingRatios(&[]).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+// constants
+use crate::util::constants::LOG_BINS_PER_DECADE;
+
+
+// name, unit and how to measure an avalanche
+pub type AvalancheMeasure = (&'static str, &'static str, fn(&Avalanche) -> usize);
+
+// every avalanche of the run with its measures, see displayAvalancheSeries
+pub const AVALANCHE_SERIES_FILE: &str = "avalanches.csv";
+// columns of the avalanche series by name, as read back from avalanches.csv
+pub type AvalancheSeries = Vec<(String, Vec<u64>)>;
+
+/**
+ * Avalanche measures exported as distributions
+ */
+pub const AVALANCHE_MEASURES: [AvalancheMeasure; 5] = [
+    ("movement", "moves", |avalanche| avalanche.totalMovement),
+    ("grains", "grains", |avalanche| avalanche.totalGrainsInvolved),
+    ("magnitude", "grain moves", |avalanche| avalanche.totalGrainsInvolved * avalanche.totalMovement),
+    ("area", "locations", |avalanche| avalanche.locationIds.len()),
+    ("duration", "time steps", |avalanche| avalanche.duration),
+];
+
+/**
+ * A logarithmic bin of a discrete distribution covering the integers start to end inclusive
+ */
+#[derive(Clone, Debug)]
+pub struct LogBin {
+    pub start: u64,
+    pub end: u64,
+    // geometric center of the bin, where the density is plotted
+    pub center: f64,
+    pub count: u64,
+    // probability of each integer in the bin, count / (total * integers in the bin)
+    pub density: f64,
+}
+
+/**
+ * Histogram of (value, number of avalanches) of one of the AVALANCHE_MEASURES in value order
+ */
+pub fn avalancheHistogram(avalanches: &[Avalanche], measure: fn(&Avalanche) -> usize) -> Vec<(u64, u64)> {
+    let mut histogram: BTreeMap<u64, u64> = BTreeMap::new();
+    for avalanche in avalanches {
+        *histogram.entry(measure(avalanche) as u64).or_insert(0) += 1;
+    }
+    histogram.into_iter().collect()
+}
+
+/**
+ * Probability density of a discrete distribution in logarithmic bins, binsPerDecade bins for
+ * each power of ten, the bin edges are rounded up to integers so every bin holds at least one
+ * integer and the density is divided by the number of integers in the bin rather than the bin width
+ * Values of zero can not be placed on a log scale, they are left out and the density is normalized
+ * over the positive values, bins without any values are left out
+ */
+pub fn logBinnedPdf(histogram: &[(u64, u64)], binsPerDecade: usize) -> Vec<LogBin> {
+    let positive: Vec<(u64, u64)> = histogram.iter().cloned().filter(|&(value, count)| value > 0 && count > 0).collect();
+    let total: u64 = positive.iter().map(|&(_, count)| count).sum();
+    let Some(&(largest, _)) = positive.iter().max_by_key(|&&(value, _)| value) else {
+        return Vec::new();
+    };
+
+    // integer bin edges 1 = e0 < e1 < ... with each bin covering e(k) to e(k+1) - 1
+    let mut edges = vec![1u64];
+    let mut k = 1;
+    while *edges.last().unwrap() <= largest {
+        let edge = 10f64.powf(k as f64 / binsPerDecade.max(1) as f64).ceil() as u64;
+        if edge > *edges.last().unwrap() {
+            edges.push(edge);
+        }
+        k += 1;
+    }
+
+    let mut bins: Vec<LogBin> = edges.windows(2)
+        .map(|edge| LogBin {
+            start: edge[0],
+            end: edge[1] - 1,
+            center: ((edge[0] * (edge[1] - 1)) as f64).sqrt(),
+            count: 0,
+            density: 0.0,
+        })
+        .collect();
+
+    for (value, count) in positive {
+        // edges are increasing so the bin is the last edge at or below the value
+        let bin = edges.partition_point(|&edge| edge <= value) - 1;
+        bins[bin].count += count;
+    }
+
+    bins.retain(|bin| bin.count != 0);
+    for bin in &mut bins {
+        bin.density = bin.count as f64 / (total as f64 * (bin.end - bin.start + 1) as f64);
+    }
+    bins
+}
+
+/**
+ * Complementary cumulative distribution P(X >= value) at each positive value in the histogram,
+ * normalized over the positive values the same way as logBinnedPdf
+ */
+pub fn ccdf(histogram: &[(u64, u64)]) -> Vec<(u64, f64)> {
+    let mut positive: Vec<(u64, u64)> = histogram.iter().cloned().filter(|&(value, count)| value > 0 && count > 0).collect();
+    positive.sort_by_key(|&(value, _)| value);
+    let total: u64 = positive.iter().map(|&(_, count)| count).sum();
+
+    let mut remaining = total;
+    let mut points = Vec::with_capacity(positive.len());
+    for (value, count) in positive {
+        points.push((value, remaining as f64 / total as f64));
+        remaining -= count;
+    }
+    points
+}
+
+/**
+ * Write the log-binned probability density and the complementary cumulative distribution of each
+ * of the AVALANCHE_MEASURES to the distributions folder of the run
+ */
+pub fn displayAvalancheDistributions(avalanches: &[Avalanche], folder_path: String) -> io::Result<()> {
+    fs::create_dir_all(folder_path.clone() + "/distributions")?;
+
+    for (name, unit, measure) in AVALANCHE_MEASURES {
+        let histogram = avalancheHistogram(avalanches, measure);
+
+        let relativePath = format!("distributions/pdf-{}.csv", name);
+        let schema = CsvSchema::new(&format!("pdf-{}", name), &relativePath, &format!("Probability density of avalanche {} in logarithmic bins, avalanches of size 0 are left out", name), vec![
+            CsvColumn::new("bin_start", "integer", unit, "Smallest size in the bin"),
+            CsvColumn::new("bin_end", "integer", unit, "Largest size in the bin"),
+            CsvColumn::new("bin_center", "float", unit, "Geometric center of the bin"),
+            CsvColumn::new("avalanche_count", "integer", "avalanches", "Number of avalanches with a size in the bin"),
+            CsvColumn::new("probability_density", "float", "probability", "Probability of an avalanche having each size in the bin"),
+        ]);
+        let mut writer = CsvWriter::create(&folder_path, &relativePath, &schema)?;
+        for bin in logBinnedPdf(&histogram, LOG_BINS_PER_DECADE) {
+            writer.writeRow(&[bin.start.to_string(), bin.end.to_string(), bin.center.to_string(), bin.count.to_string(), bin.density.to_string()])?;
+        }
+        writer.finish()?;
+
+        let relativePath = format!("distributions/ccdf-{}.csv", name);
+        let schema = CsvSchema::new(&format!("ccdf-{}", name), &relativePath, &format!("Complementary cumulative distribution of avalanche {}, avalanches of size 0 are left out", name), vec![
+            CsvColumn::new("size", "integer", unit, "Avalanche size"),
+            CsvColumn::new("ccdf", "float", "probability", "Probability of an avalanche being at least this size"),
+        ]);
+        let mut writer = CsvWriter::create(&folder_path, &relativePath, &schema)?;
+        for (value, probability) in ccdf(&histogram) {
+            writer.writeRow(&[value.to_string(), probability.to_string()])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Write every avalanche with each of the AVALANCHE_MEASURES in the order the grains were dropped,
+ * the series the distributions are built from
+ */
+pub fn displayAvalancheSeries(avalanches: &[Avalanche], folder_path: String) -> io::Result<()> {
+    let mut columns = vec![CsvColumn::new("grain_index", "integer", "grain index", "Index of the grain that started the avalanche")];
+    for (name, unit, _) in AVALANCHE_MEASURES {
+        columns.push(CsvColumn::new(name, "integer", unit, &format!("Avalanche {}", name)));
+    }
+    columns.push(CsvColumn::new("grains_off_pile", "integer", "grains", "Grains that left the pile during the avalanche"));
+
+    let schema = CsvSchema::new("avalanches", AVALANCHE_SERIES_FILE, "Size of every avalanche in the order the grains were dropped", columns);
+    let mut writer = CsvWriter::create(&folder_path, AVALANCHE_SERIES_FILE, &schema)?;
+
+    for (i, avalanche) in avalanches.iter().enumerate() {
+        let mut row = vec![i.to_string()];
+        for (_, _, measure) in AVALANCHE_MEASURES {
+            row.push(measure(avalanche).to_string());
+        }
+        row.push(avalanche.grainsOffPile.to_string());
+        writer.writeRow(&row)?;
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every size from 1 to 1000 with a count falling off as a power law, and some avalanches of size 0
+    fn histogram() -> Vec<(u64, u64)> {
+        let mut histogram = vec![(0, 40)];
+        histogram.extend((1..=1000u64).map(|value| (value, 1 + 100000 / (value * value))));
+        histogram
+    }
+
+    #[test]
+    fn logBinEdgesIncrease() {
+        let bins = logBinnedPdf(&histogram(), 10);
+
+        assert!(!bins.is_empty());
+        assert_eq!(bins[0].start, 1);
+        for bin in &bins {
+            assert!(bin.start <= bin.end);
+            assert!(bin.center >= bin.start as f64 && bin.center <= bin.end as f64);
+        }
+        for pair in bins.windows(2) {
+            // every value is present so the bins follow on from each other
+            assert_eq!(pair[1].start, pair[0].end + 1);
+        }
+        assert!(bins[bins.len() - 1].end >= 1000);
+    }
+
+    #[test]
+    fn logBinnedDensityIsNormalized() {
+        let total: f64 = logBinnedPdf(&histogram(), 10).iter()
+            .map(|bin| bin.density * (bin.end - bin.start + 1) as f64)
+            .sum();
+
+        assert!((total - 1.0).abs() < 1e-9, "density sums to {}", total);
+    }
+
+    #[test]
+    fn ccdfStartsAtOne() {
+        let points = ccdf(&histogram());
+
+        assert_eq!(points[0], (1, 1.0));
+        for pair in points.windows(2) {
+            assert!(pair[1].0 > pair[0].0 && pair[1].1 < pair[0].1);
+        }
+        assert!(points[points.len() - 1].1 > 0.0);
+    }
+}
@@ -0,0 +1,109 @@
+/**
+ * Result of a two-sample Kolmogorov-Smirnov test
+ */
+#[derive(Clone, Debug)]
+pub struct KsTestResult {
+    // largest distance between the two empirical cumulative distributions
+    pub statistic: f64,
+    // probability of a distance at least this large if both samples come from the same distribution
+    pub pValue: f64,
+    pub countA: u64,
+    pub countB: u64,
+}
+
+/**
+ * Two-sample Kolmogorov-Smirnov test between two histograms of (value, count) pairs
+ * The p-value uses the asymptotic Kolmogorov distribution, which is conservative for
+ * discrete values such as avalanche sizes (the true p-value is smaller)
+ */
+pub fn twoSampleKsTest(histogramA: &[(u64, u64)], histogramB: &[(u64, u64)]) -> Option<KsTestResult> {
+    let countA: u64 = histogramA.iter().map(|&(_, count)| count).sum();
+    let countB: u64 = histogramB.iter().map(|&(_, count)| count).sum();
+    if countA == 0 || countB == 0 {
+        return None;
+    }
+
+    let mut sortedA = histogramA.to_vec();
+    let mut sortedB = histogramB.to_vec();
+    sortedA.sort_by_key(|&(value, _)| value);
+    sortedB.sort_by_key(|&(value, _)| value);
+
+    // walk both histograms in value order comparing the cumulative distributions after each value
+    let (mut i, mut j) = (0, 0);
+    let (mut cumulativeA, mut cumulativeB) = (0u64, 0u64);
+    let mut statistic: f64 = 0.0;
+    while i < sortedA.len() || j < sortedB.len() {
+        let valueA = sortedA.get(i).map(|&(value, _)| value).unwrap_or(u64::MAX);
+        let valueB = sortedB.get(j).map(|&(value, _)| value).unwrap_or(u64::MAX);
+        let value = valueA.min(valueB);
+        if valueA == value {
+            cumulativeA += sortedA[i].1;
+            i += 1;
+        }
+        if valueB == value {
+            cumulativeB += sortedB[j].1;
+            j += 1;
+        }
+        statistic = statistic.max((cumulativeA as f64 / countA as f64 - cumulativeB as f64 / countB as f64).abs());
+    }
+
+    // effective sample size and the Stephens correction (Numerical Recipes 14.3)
+    let effective = (countA as f64 * countB as f64) / (countA + countB) as f64;
+    let lambda = (effective.sqrt() + 0.12 + 0.11 / effective.sqrt()) * statistic;
+
+    Some(KsTestResult {
+        statistic,
+        pValue: kolmogorovProbability(lambda),
+        countA,
+        countB,
+    })
+}
+
+/**
+ * Complementary cumulative Kolmogorov distribution Q(lambda) = 2 sum (-1)^(j-1) exp(-2 j^2 lambda^2)
+ */
+fn kolmogorovProbability(lambda: f64) -> f64 {
+    if lambda < 1e-3 {
+        return 1.0;
+    }
+
+    let mut sum = 0.0;
+    let mut sign = 1.0;
+    for j in 1..=100 {
+        let term = sign * 2.0 * (-2.0 * (j * j) as f64 * lambda * lambda).exp();
+        sum += term;
+        if term.abs() < 1e-12 {
+            break;
+        }
+        sign = -sign;
+    }
+    sum.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identicalSamplesDoNotDiffer() {
+        let histogram = [(1, 500), (2, 120), (5, 30), (12, 4)];
+        let result = twoSampleKsTest(&histogram, &histogram).unwrap();
+
+        assert_eq!(result.statistic, 0.0);
+        assert_eq!(result.pValue, 1.0);
+        assert_eq!(result.countA, 654);
+    }
+
+    #[test]
+    fn disjointSamplesDiffer() {
+        let result = twoSampleKsTest(&[(1, 200), (2, 100)], &[(10, 150), (20, 150)]).unwrap();
+
+        assert_eq!(result.statistic, 1.0);
+        assert!(result.pValue < 1e-6, "p-value {}", result.pValue);
+    }
+
+    #[test]
+    fn emptySampleHasNoTest() {
+        assert!(twoSampleKsTest(&[], &[(1, 10)]).is_none());
+    }
+}
@@ -0,0 +1,10 @@
+pub mod stationarity;
+pub mod residence;
+pub mod pileObservables;
+pub mod powerLaw;
+pub mod ksTest;
+pub mod distributions;
+pub mod cascade;
+pub mod branching;
+pub mod temporal;
+pub mod predictability;
@@ -0,0 +1,222 @@
+use std::fs;
+use std::io;
+
+// internal models
+use crate::models::location::Location;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+// constants
+use crate::util::constants::{X_SIZE, Y_SIZE, OBSERVABLES_INTERVAL};
+
+
+/**
+ * Computed observables describing the shape of the pile at a point in the run
+ */
+pub struct PileObservables {
+    // index of the last grain dropped before the observables were captured
+    pub grainIndex: usize,
+    // height of each column in layers (top occupied z + 1, 0 for an empty column) indexed as heightMap[x][y]
+    pub heightMap: Vec<Vec<i32>>,
+    // mean and maximum height difference between neighboring columns
+    pub meanSlope: f64,
+    pub maxSlope: i32,
+    // height of the tallest column
+    pub maxHeight: i32,
+    // mean column height by distance from the center of the pile, index is the distance rounded to the nearest location
+    pub radialProfile: Vec<f64>,
+}
+
+impl PileObservables {
+    /**
+     * Capture the observables from the current state of the locations
+     */
+    pub fn capture(grainIndex: usize) -> Self {
+        let heightMap: Vec<Vec<i32>> = Location::getHeightMap().into_iter()
+            .map(|column| column.into_iter().map(|z| z + 1).collect())
+            .collect();
+
+        let (meanSlope, maxSlope) = slopeStatistics(&heightMap);
+        let maxHeight = heightMap.iter().flatten().cloned().max().unwrap_or(0);
+        let radialProfile = radialProfile(&heightMap);
+
+        PileObservables {
+            grainIndex,
+            heightMap,
+            meanSlope,
+            maxSlope,
+            maxHeight,
+            radialProfile,
+        }
+    }
+
+    /**
+     * Write the height map as one row per column of the pile
+     */
+    pub fn displayHeightMap(&self, folder_path: &str, relativePath: &str, pathPattern: &str) -> io::Result<()> {
+        // Create the csv through the common run output writer
+        let schema = CsvSchema::new("height-map", pathPattern, "Height of each column of the pile", vec![
+            CsvColumn::new("x", "integer", "locations", "X coordinate of the column"),
+            CsvColumn::new("y", "integer", "locations", "Y coordinate of the column"),
+            CsvColumn::new("height", "integer", "layers", "Top occupied z + 1, 0 for an empty column"),
+        ]);
+        let mut writer = CsvWriter::create(folder_path, relativePath, &schema)?;
+
+        for x in 0..X_SIZE as usize {
+            for y in 0..Y_SIZE as usize {
+                writer.writeRow(&[x.to_string(), y.to_string(), self.heightMap[x][y].to_string()])?;
+            }
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()?;
+
+        Ok(())
+    }
+}
+
+/**
+ * Records the pile observables every OBSERVABLES_INTERVAL grains and at the end of the run
+ */
+pub struct PileObservablesRecorder {
+    pub samples: Vec<PileObservables>,
+}
+
+impl Default for PileObservablesRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PileObservablesRecorder {
+    pub fn new() -> Self {
+        PileObservablesRecorder {
+            samples: Vec::new(),
+        }
+    }
+
+    /**
+     * Record a completed avalanche, on each interval the observables are captured and
+     * the height map at that point is written to the observables folder of the run
+     */
+    pub fn recordAvalanche(&mut self, grainIndex: usize, folder_path: String) -> io::Result<()> {
+        if OBSERVABLES_INTERVAL == 0 || !(grainIndex + 1).is_multiple_of(OBSERVABLES_INTERVAL) {
+            return Ok(());
+        }
+
+        let observables = PileObservables::capture(grainIndex);
+
+        fs::create_dir_all(folder_path.clone() + "/observables")?;
+        observables.displayHeightMap(&folder_path, &format!("observables/height-map-{:010}.csv", grainIndex + 1), "observables/height-map-<n>.csv")?;
+
+        self.samples.push(observables);
+        Ok(())
+    }
+
+    /**
+     * Capture the final observables of the run and export the final height map, the final
+     * radial height profile and the slope and height of the pile at each interval
+     */
+    pub fn displayPileObservables(&mut self, grainIndex: usize, folder_path: String) -> io::Result<()> {
+
+        // capture the end of the run unless the last interval already did
+        if self.samples.last().map(|sample| sample.grainIndex) != Some(grainIndex) {
+            self.samples.push(PileObservables::capture(grainIndex));
+        }
+        let last = self.samples.last().unwrap();
+
+        last.displayHeightMap(&folder_path, "height-map.csv", "height-map.csv")?;
+
+        // radial height profile at the end of the run
+        let schema = CsvSchema::new("radial-profile", "radial-profile.csv", "Mean column height by distance from the center of the pile at the end of the run", vec![
+            CsvColumn::new("distance", "integer", "locations", "Distance from the center rounded to the nearest location"),
+            CsvColumn::new("mean_height", "float", "layers", "Mean height of the columns at that distance"),
+        ]);
+        let mut writer = CsvWriter::create(&folder_path, "radial-profile.csv", &schema)?;
+
+        for (distance, height) in last.radialProfile.iter().enumerate() {
+            writer.writeRow(&[distance.to_string(), format!("{:.4}", height)])?;
+        }
+        writer.finish()?;
+
+        // slope, height and radial profile at each interval
+        let mut columns = vec![
+            CsvColumn::new("grain_index", "integer", "grain index", "Index of the last grain dropped before the observables were captured"),
+            CsvColumn::new("mean_slope", "float", "layers", "Mean height difference between neighboring columns"),
+            CsvColumn::new("max_slope", "integer", "layers", "Largest height difference between neighboring columns"),
+            CsvColumn::new("max_height", "integer", "layers", "Height of the tallest column"),
+        ];
+        for distance in 0..last.radialProfile.len() {
+            columns.push(CsvColumn::new(&format!("radius_{}_mean_height", distance), "float", "layers", &format!("Mean height of the columns {} locations from the center", distance)));
+        }
+        let schema = CsvSchema::new("pile-observables", "pile-observables.csv", "Slope, height and radial profile of the pile on each observables interval", columns);
+        let mut writer = CsvWriter::create(&folder_path, "pile-observables.csv", &schema)?;
+
+        for sample in &self.samples {
+            let mut row = vec![
+                sample.grainIndex.to_string(),
+                format!("{:.4}", sample.meanSlope),
+                sample.maxSlope.to_string(),
+                sample.maxHeight.to_string(),
+            ];
+            row.extend(sample.radialProfile.iter().map(|height| format!("{:.4}", height)));
+            writer.writeRow(&row)?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()?;
+
+        Ok(())
+    }
+}
+
+/**
+ * Mean and maximum absolute height difference between each column and its x and y neighbors
+ */
+pub fn slopeStatistics(heightMap: &[Vec<i32>]) -> (f64, i32) {
+    let mut totalDifference = 0;
+    let mut maxDifference = 0;
+    let mut pairs = 0;
+    for x in 0..X_SIZE as usize {
+        for y in 0..Y_SIZE as usize {
+            let mut neighbors = Vec::with_capacity(2);
+            if x + 1 < X_SIZE as usize {
+                neighbors.push(heightMap[x + 1][y]);
+            }
+            if y + 1 < Y_SIZE as usize {
+                neighbors.push(heightMap[x][y + 1]);
+            }
+            for neighbor in neighbors {
+                let difference = (heightMap[x][y] - neighbor).abs();
+                totalDifference += difference;
+                maxDifference = maxDifference.max(difference);
+                pairs += 1;
+            }
+        }
+    }
+    let meanDifference = if pairs == 0 { 0.0 } else { totalDifference as f64 / pairs as f64 };
+    (meanDifference, maxDifference)
+}
+
+/**
+ * Mean column height by distance from the center of the pile
+ */
+pub fn radialProfile(heightMap: &[Vec<i32>]) -> Vec<f64> {
+    let centerX = (X_SIZE / 2) as f64;
+    let centerY = (Y_SIZE / 2) as f64;
+
+    let mut totals: Vec<(i64, usize)> = Vec::new();
+    for (x, column) in heightMap.iter().enumerate() {
+        for (y, &height) in column.iter().enumerate() {
+            let distance = ((x as f64 - centerX).powi(2) + (y as f64 - centerY).powi(2)).sqrt().round() as usize;
+            if totals.len() <= distance {
+                totals.resize(distance + 1, (0, 0));
+            }
+            totals[distance].0 += height as i64;
+            totals[distance].1 += 1;
+        }
+    }
+
+    totals.iter()
+        .map(|&(total, count)| if count > 0 { total as f64 / count as f64 } else { 0.0 })
+        .collect()
+}
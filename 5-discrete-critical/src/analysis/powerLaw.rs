@@ -0,0 +1,140 @@
+// constants
+use crate::util::constants::{POWER_LAW_MIN_TAIL, POWER_LAW_XMIN_RATIO};
+
+
+/**
+ * Result of fitting a discrete power law p(x) ~ x^-alpha to the tail x >= xMin of a distribution
+ */
+#[derive(Clone, Debug)]
+pub struct PowerLawFit {
+    pub alpha: f64,
+    // standard error of alpha
+    pub alphaError: f64,
+    pub xMin: u64,
+    // number of observations in the tail
+    pub tailCount: u64,
+    // Kolmogorov-Smirnov distance between the tail and the fitted power law
+    pub ksDistance: f64,
+}
+
+/**
+ * Fit a discrete power law to a histogram of (value, count) pairs, choosing xMin as the value
+ * that minimizes the Kolmogorov-Smirnov distance between the tail and the fit (Clauset, Shalizi
+ * and Newman 2009), tails with fewer than POWER_LAW_MIN_TAIL observations are not considered and
+ * xMin values closer than POWER_LAW_XMIN_RATIO to the last one tried are skipped
+ */
+pub fn fitPowerLaw(histogram: &[(u64, u64)]) -> Option<PowerLawFit> {
+    let histogram = sortedPositive(histogram);
+
+    let mut best: Option<PowerLawFit> = None;
+    let mut lastXMin = 0.0;
+    for &(xMin, _) in &histogram {
+        if (xMin as f64) < lastXMin * POWER_LAW_XMIN_RATIO {
+            continue;
+        }
+        lastXMin = xMin as f64;
+
+        let fit = match fitSorted(&histogram, xMin) {
+            Some(fit) => fit,
+            None => continue,
+        };
+        if fit.tailCount < POWER_LAW_MIN_TAIL {
+            // the tail only gets smaller as xMin increases
+            break;
+        }
+        if best.as_ref().is_none_or(|best| fit.ksDistance < best.ksDistance) {
+            best = Some(fit);
+        }
+    }
+    best
+}
+
+/**
+ * Fit a discrete power law to the tail x >= xMin of a histogram of (value, count) pairs
+ * using the approximate maximum likelihood estimator alpha = 1 + n / sum(ln(x / (xMin - 1/2)))
+ */
+pub fn fitPowerLawWithXMin(histogram: &[(u64, u64)], xMin: u64) -> Option<PowerLawFit> {
+    fitSorted(&sortedPositive(histogram), xMin)
+}
+
+/**
+ * Complementary cumulative distribution P(X >= x) of the fitted power law
+ */
+pub fn powerLawCcdf(fit: &PowerLawFit, x: f64) -> f64 {
+    ((x - 0.5) / (fit.xMin as f64 - 0.5)).powf(1.0 - fit.alpha).min(1.0)
+}
+
+fn fitSorted(histogram: &[(u64, u64)], xMin: u64) -> Option<PowerLawFit> {
+    if xMin == 0 {
+        return None;
+    }
+
+    let tail: Vec<(u64, u64)> = histogram.iter().cloned().filter(|&(x, _)| x >= xMin).collect();
+    let tailCount: u64 = tail.iter().map(|&(_, count)| count).sum();
+    if tailCount < 2 {
+        return None;
+    }
+
+    let shift = xMin as f64 - 0.5;
+    let logSum: f64 = tail.iter().map(|&(x, count)| count as f64 * (x as f64 / shift).ln()).sum();
+    if logSum <= 0.0 {
+        return None;
+    }
+
+    let alpha = 1.0 + tailCount as f64 / logSum;
+    let mut fit = PowerLawFit {
+        alpha,
+        alphaError: (alpha - 1.0) / (tailCount as f64).sqrt(),
+        xMin,
+        tailCount,
+        ksDistance: 0.0,
+    };
+
+    // largest distance between the empirical and fitted cumulative distributions over the tail
+    let mut cumulative = 0u64;
+    let mut ksDistance: f64 = 0.0;
+    for &(x, count) in &tail {
+        let empiricalBelow = cumulative as f64 / tailCount as f64;
+        cumulative += count;
+        let empiricalAt = cumulative as f64 / tailCount as f64;
+        let fittedBelow = 1.0 - powerLawCcdf(&fit, x as f64);
+        let fittedAt = 1.0 - powerLawCcdf(&fit, x as f64 + 1.0);
+        ksDistance = ksDistance.max((empiricalBelow - fittedBelow).abs()).max((empiricalAt - fittedAt).abs());
+    }
+    fit.ksDistance = ksDistance;
+
+    Some(fit)
+}
+
+fn sortedPositive(histogram: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut sorted: Vec<(u64, u64)> = histogram.iter().cloned().filter(|&(x, count)| x > 0 && count > 0).collect();
+    sorted.sort_by_key(|&(x, _)| x);
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::util::constants::POWER_LAW_CONFIDENCE_Z;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn fitRecoversParetoExponent() {
+        // discrete power law with alpha 2.5 from x = 10, rounding continuous Pareto samples about xMin - 1/2
+        let alpha = 2.5;
+        let mut rnd = StdRng::seed_from_u64(1);
+        let mut counts = BTreeMap::new();
+        for _ in 0..20000 {
+            let x = (9.5 * (1.0 - rnd.gen::<f64>()).powf(-1.0 / (alpha - 1.0)) + 0.5).floor() as u64;
+            *counts.entry(x).or_insert(0u64) += 1;
+        }
+        let histogram: Vec<(u64, u64)> = counts.into_iter().collect();
+
+        let fit = fitPowerLaw(&histogram).unwrap();
+        assert!((fit.alpha - alpha).abs() <= POWER_LAW_CONFIDENCE_Z * fit.alphaError,
+            "alpha {} ± {} does not cover {}", fit.alpha, fit.alphaError, alpha);
+        assert!(fit.tailCount >= POWER_LAW_MIN_TAIL);
+    }
+}
@@ -0,0 +1,364 @@
+use std::io;
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::models::grain::Grain;
+use crate::models::location::Location;
+use crate::analysis::distributions::AVALANCHE_MEASURES;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+// constants
+use crate::util::constants::{PRE_DROP_INTERVAL, PRE_DROP_RADIUS, NEAR_THRESHOLD_MARGIN};
+
+
+// pile state before each drop alongside the avalanche it started, written to the run folder
+pub const PRE_DROP_STATE_FILE: &str = "pre-drop-state.csv";
+// columns of pre-drop-state.csv describing the pile, the predictors of the avalanche size
+pub const PRE_DROP_PREDICTORS: [&str; 5] = ["grains_in_pile", "drop_column_height", "local_occupancy", "locations_at_capacity", "near_threshold_fraction"];
+// columns of pre-drop-state.csv by name, as read back from the run folder
+pub type PreDropSeries = Vec<(String, Vec<f64>)>;
+
+/**
+ * Summary of the pile just before a grain is dropped
+ */
+#[derive(Clone, Debug)]
+pub struct PreDropState {
+    pub grainIndex: usize,
+    // column the grain is dropped into
+    pub dropX: i32,
+    pub dropY: i32,
+    pub grainsInPile: usize,
+    // top occupied z of the drop column, -1 when the column is empty
+    pub dropColumnHeight: i32,
+    // capacity taken up over the columns within PRE_DROP_RADIUS of the drop column
+    pub localOccupancy: f64,
+    // locations holding as much as their capacity
+    pub locationsAtCapacity: usize,
+    // occupied locations within NEAR_THRESHOLD_MARGIN of their capacity
+    pub nearThresholdFraction: f64,
+}
+
+impl PreDropState {
+    /**
+     * Capture the state of the pile for the grain about to be dropped at grainIndex
+     */
+    pub fn capture(grainIndex: usize, dropX: i32, dropY: i32) -> Self {
+        let mut grainsInPile = 0;
+        let mut dropColumnHeight = -1;
+        let mut localOccupancy = 0;
+        let mut localCapacity = 0;
+        let mut locationsAtCapacity = 0;
+        let mut occupiedLocations = 0;
+        let mut nearThreshold = 0;
+
+        Location::visitLocations(|location| {
+            grainsInPile += location.grainIds.len();
+            // empty space has no capacity and is not part of the pile
+            if location.capacity == 0 {
+                return;
+            }
+
+            let occupancy = location.getOccupancy();
+            if occupancy >= location.capacity {
+                locationsAtCapacity += 1;
+            }
+            if occupancy > 0 {
+                occupiedLocations += 1;
+                if occupancy + NEAR_THRESHOLD_MARGIN >= location.capacity {
+                    nearThreshold += 1;
+                }
+            }
+            if (location.x - dropX).abs() <= PRE_DROP_RADIUS && (location.y - dropY).abs() <= PRE_DROP_RADIUS {
+                localOccupancy += occupancy;
+                localCapacity += location.capacity;
+            }
+            if location.x == dropX && location.y == dropY && occupancy > 0 {
+                dropColumnHeight = dropColumnHeight.max(location.z);
+            }
+        });
+
+        PreDropState {
+            grainIndex,
+            dropX,
+            dropY,
+            grainsInPile,
+            dropColumnHeight,
+            localOccupancy: if localCapacity > 0 { localOccupancy as f64 / localCapacity as f64 } else { 0.0 },
+            locationsAtCapacity,
+            nearThresholdFraction: if occupiedLocations > 0 { nearThreshold as f64 / occupiedLocations as f64 } else { 0.0 },
+        }
+    }
+}
+
+/**
+ * Records the state of the pile before every PRE_DROP_INTERVAL grains are dropped and writes it
+ * to pre-drop-state.csv with the size of the avalanche the grain started once it has finished
+ */
+pub struct PreDropRecorder {
+    // state captured before the current drop, waiting for its avalanche to finish
+    pending: Option<PreDropState>,
+    // open pre-drop state file, None when recording is off
+    writer: Option<CsvWriter>,
+    // number of rows written so far
+    pub rowCount: usize,
+}
+
+impl PreDropRecorder {
+    pub fn new(folder_path: &str) -> io::Result<Self> {
+        let writer = if PRE_DROP_INTERVAL != 0 {
+            let mut columns = vec![
+                CsvColumn::new("grain_index", "integer", "grain index", "Index of the grain dropped"),
+                CsvColumn::new("drop_x", "integer", "location", "x of the column the grain was dropped into"),
+                CsvColumn::new("drop_y", "integer", "location", "y of the column the grain was dropped into"),
+                CsvColumn::new("grains_in_pile", "integer", "grains", "Grains resting in the pile before the drop"),
+                CsvColumn::new("drop_column_height", "integer", "location", "Top occupied z of the drop column before the drop, -1 when empty"),
+                CsvColumn::new("local_occupancy", "float", "fraction", "Capacity taken up over the columns within PRE_DROP_RADIUS of the drop column"),
+                CsvColumn::new("locations_at_capacity", "integer", "locations", "Locations holding as much as their capacity"),
+                CsvColumn::new("near_threshold_fraction", "float", "fraction", "Occupied locations within NEAR_THRESHOLD_MARGIN of their capacity"),
+            ];
+            for (name, unit, _) in AVALANCHE_MEASURES {
+                columns.push(CsvColumn::new(name, "integer", unit, &format!("{} of the avalanche the grain started", name)));
+            }
+            let schema = CsvSchema::new("pre-drop-state", PRE_DROP_STATE_FILE, "State of the pile just before each recorded drop and the size of the avalanche it started", columns);
+            Some(CsvWriter::create(folder_path, PRE_DROP_STATE_FILE, &schema)?)
+        } else {
+            None
+        };
+
+        Ok(PreDropRecorder { pending: None, writer, rowCount: 0 })
+    }
+
+    /**
+     * Capture the pile before the grain at grainIndex is dropped, on each PRE_DROP_INTERVAL
+     */
+    pub fn recordDrop(&mut self, grainIndex: usize, grainId: u32) {
+        if self.writer.is_none() || !grainIndex.is_multiple_of(PRE_DROP_INTERVAL) {
+            return;
+        }
+        let Some(grain) = Grain::getGrainById(grainId) else {
+            return;
+        };
+        self.pending = Some(PreDropState::capture(grainIndex, grain.x, grain.y));
+    }
+
+    /**
+     * Write the state captured before the drop with the size of the avalanche it started
+     */
+    pub fn recordAvalanche(&mut self, avalanche: &Avalanche) -> io::Result<()> {
+        let (Some(writer), Some(state)) = (self.writer.as_mut(), self.pending.take()) else {
+            return Ok(());
+        };
+
+        let mut row = vec![
+            state.grainIndex.to_string(),
+            state.dropX.to_string(),
+            state.dropY.to_string(),
+            state.grainsInPile.to_string(),
+            state.dropColumnHeight.to_string(),
+            format!("{:.6}", state.localOccupancy),
+            state.locationsAtCapacity.to_string(),
+            format!("{:.6}", state.nearThresholdFraction),
+        ];
+        for (_, _, measure) in AVALANCHE_MEASURES {
+            row.push(measure(avalanche).to_string());
+        }
+        writer.writeRow(&row)?;
+        self.rowCount += 1;
+
+        Ok(())
+    }
+
+    /**
+     * Flush the pre-drop state file at the end of the run
+     */
+    pub fn finish(self) -> io::Result<()> {
+        match self.writer {
+            // flush the writer to ensure all data is written to the file
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/**
+ * Pearson correlation of two series, None when either does not vary
+ */
+pub fn pearsonCorrelation(x: &[f64], y: &[f64]) -> Option<f64> {
+    let n = x.len().min(y.len());
+    if n < 2 {
+        return None;
+    }
+    let meanX = x[..n].iter().sum::<f64>() / n as f64;
+    let meanY = y[..n].iter().sum::<f64>() / n as f64;
+    let mut sxx = 0.0;
+    let mut syy = 0.0;
+    let mut sxy = 0.0;
+    for (a, b) in x.iter().zip(y) {
+        sxx += (a - meanX) * (a - meanX);
+        syy += (b - meanY) * (b - meanY);
+        sxy += (a - meanX) * (b - meanY);
+    }
+    if sxx == 0.0 || syy == 0.0 {
+        return None;
+    }
+    Some(sxy / (sxx * syy).sqrt())
+}
+
+/**
+ * Rank of each value from 1, tied values share the mean of their ranks
+ */
+pub fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start;
+        while end + 1 < order.len() && values[order[end + 1]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end) as f64 / 2.0 + 1.0;
+        for &i in &order[start..=end] {
+            ranks[i] = rank;
+        }
+        start = end + 1;
+    }
+    ranks
+}
+
+/**
+ * Spearman rank correlation of two series, None when either does not vary
+ */
+pub fn spearmanCorrelation(x: &[f64], y: &[f64]) -> Option<f64> {
+    pearsonCorrelation(&ranks(x), &ranks(y))
+}
+
+/**
+ * Area under the ROC curve of a score picking out the large avalanches, the chance a large avalanche
+ * scores higher than a small one, 0.5 for a score with no skill and 1 for one that separates them,
+ * None when every avalanche is large or none are
+ */
+pub fn largeAvalancheAuc(scores: &[f64], large: &[bool]) -> Option<f64> {
+    let positives = large.iter().filter(|&&isLarge| isLarge).count();
+    let negatives = large.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+    let rankSum: f64 = ranks(scores).iter().zip(large).filter(|&(_, &isLarge)| isLarge).map(|(rank, _)| rank).sum();
+    Some((rankSum - (positives * (positives + 1)) as f64 / 2.0) / (positives * negatives) as f64)
+}
+
+/**
+ * Least squares fit of the target on all the predictors together over the first trainingRows rows,
+ * returns the predicted value of every row, predictors that do not vary in the training rows are left out
+ */
+pub fn linearPrediction(predictors: &[&[f64]], target: &[f64], trainingRows: usize) -> Vec<f64> {
+    let n = trainingRows.min(target.len());
+    let meanTarget = target[..n].iter().sum::<f64>() / n.max(1) as f64;
+
+    // standardize the predictors so the normal equations are well conditioned
+    let mut columns: Vec<Vec<f64>> = Vec::new();
+    for predictor in predictors {
+        let mean = predictor[..n].iter().sum::<f64>() / n.max(1) as f64;
+        let deviation = (predictor[..n].iter().map(|value| (value - mean).powi(2)).sum::<f64>() / n.max(1) as f64).sqrt();
+        if deviation > 0.0 {
+            columns.push(predictor.iter().map(|value| (value - mean) / deviation).collect());
+        }
+    }
+
+    // normal equations of the centered target, the intercept is the mean of the target
+    let k = columns.len();
+    let mut matrix = vec![vec![0.0; k + 1]; k];
+    for a in 0..k {
+        for b in 0..k {
+            matrix[a][b] = columns[a][..n].iter().zip(&columns[b][..n]).map(|(x, y)| x * y).sum();
+        }
+        matrix[a][k] = columns[a][..n].iter().zip(&target[..n]).map(|(x, y)| x * (y - meanTarget)).sum();
+    }
+    let coefficients = solveLinearSystem(matrix);
+
+    (0..target.len()).map(|row| meanTarget + coefficients.iter().zip(&columns).map(|(coefficient, column)| coefficient * column[row]).sum::<f64>()).collect()
+}
+
+/**
+ * Solve the augmented system by Gaussian elimination with partial pivoting, unknowns without a
+ * pivot, from predictors that depend on the others, are set to 0
+ */
+fn solveLinearSystem(mut matrix: Vec<Vec<f64>>) -> Vec<f64> {
+    let k = matrix.len();
+    let mut pivotRows: Vec<Option<usize>> = vec![None; k];
+    let mut row = 0;
+    for column in 0..k {
+        let Some(pivot) = (row..k).max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs())) else {
+            break;
+        };
+        if matrix[pivot][column].abs() < 1e-9 {
+            continue;
+        }
+        matrix.swap(row, pivot);
+        let pivotRow = matrix[row].clone();
+        for (other, values) in matrix.iter_mut().enumerate() {
+            if other != row {
+                let factor = values[column] / pivotRow[column];
+                for (value, pivotValue) in values[column..].iter_mut().zip(&pivotRow[column..]) {
+                    *value -= factor * pivotValue;
+                }
+            }
+        }
+        pivotRows[column] = Some(row);
+        row += 1;
+    }
+    pivotRows.iter().enumerate()
+        .map(|(column, pivotRow)| pivotRow.map(|r| matrix[r][k] / matrix[r][column]).unwrap_or(0.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiedValuesShareTheirMeanRank() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0, 3.0]), vec![4.0, 1.0, 4.0, 2.0, 4.0]);
+        assert!(ranks(&[]).is_empty());
+    }
+
+    #[test]
+    fn separatingScoreHasFullAuc() {
+        let large = [false, true, false, true, false];
+        assert_eq!(largeAvalancheAuc(&[0.1, 0.9, 0.2, 0.8, 0.3], &large), Some(1.0));
+        assert_eq!(largeAvalancheAuc(&[0.9, 0.1, 0.8, 0.2, 0.7], &large), Some(0.0));
+        assert_eq!(largeAvalancheAuc(&[0.5; 5], &large), Some(0.5));
+        assert_eq!(largeAvalancheAuc(&[0.1, 0.2], &[true, true]), None);
+    }
+
+    #[test]
+    fn linearPredictionRecoversLinearData() {
+        let x1: Vec<f64> = (0..15).map(|i| i as f64).collect();
+        let x2: Vec<f64> = (0..15).map(|i| ((i * i) % 7) as f64).collect();
+        let target: Vec<f64> = x1.iter().zip(&x2).map(|(a, b)| 2.0 + 3.0 * a - 0.5 * b).collect();
+
+        // fitted on the first rows, the rows after them are predicted exactly as well
+        let predictions = linearPrediction(&[&x1, &x2], &target, 10);
+        for (prediction, value) in predictions.iter().zip(&target) {
+            assert!((prediction - value).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn collinearPredictorsGiveNoNan() {
+        let x1: Vec<f64> = (0..12).map(|i| i as f64).collect();
+        let x2: Vec<f64> = x1.iter().map(|value| 2.0 * value).collect();
+        let constant = vec![4.0; 12];
+        let target: Vec<f64> = x1.iter().map(|value| 1.0 + value).collect();
+
+        let predictions = linearPrediction(&[&x1, &x2, &constant], &target, 12);
+        for (prediction, value) in predictions.iter().zip(&target) {
+            assert!((prediction - value).abs() < 1e-9);
+        }
+
+        // the second unknown has no pivot and is set to 0
+        assert_eq!(solveLinearSystem(vec![vec![1.0, 2.0, 3.0], vec![2.0, 4.0, 6.0]]), vec![3.0, 0.0]);
+    }
+}
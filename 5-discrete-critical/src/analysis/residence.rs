@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::io;
+
+// internal models
+use crate::models::grain::{Grain, GrainState};
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+// constants
+use crate::util::constants::{RESIDENCE_RECORDING, Z_SIZE};
+
+
+/**
+ * Bookkeeping of one grain, times are grain indexes, the number of grains dropped before the event
+ */
+#[derive(Clone, Default)]
+pub struct GrainResidence {
+    // grain index the grain was dropped at
+    pub dropTime: Option<usize>,
+    // (grain index, layer) of every time the grain came to rest
+    pub rests: Vec<(usize, i32)>,
+    // grain index of every time a resting grain was set moving again by an avalanche
+    pub mobilizations: Vec<usize>,
+    // grain index the grain left the pile at, None while it is in the pile
+    pub exitTime: Option<usize>,
+}
+
+impl GrainResidence {
+    /**
+     * Whether the grain is resting, it came to rest more times than it was set moving
+     */
+    pub fn isResting(&self) -> bool {
+        self.rests.len() > self.mobilizations.len()
+    }
+}
+
+/**
+ * Records when every grain is dropped, comes to rest, is re-mobilized and leaves the pile by
+ * watching the state of each grain after its updates, a grain that was resting and is updated
+ * in an avalanche was re-mobilized by a perturbation of its location
+ *
+ * Exports the distribution of the time grains rest between stopping and being re-mobilized,
+ * the time grains take to pass through the pile and the turnover of each layer of the pile
+ */
+pub struct ResidenceRecorder {
+    // bookkeeping of each grain by id
+    pub grains: Vec<GrainResidence>,
+}
+
+impl ResidenceRecorder {
+    /**
+     * Recorder with the bookkeeping of every grain, none when RESIDENCE_RECORDING is off
+     */
+    pub fn new(totalGrains: usize) -> Self {
+        ResidenceRecorder {
+            grains: if RESIDENCE_RECORDING { vec![GrainResidence::default(); totalGrains] } else { Vec::new() },
+        }
+    }
+
+    /**
+     * Record the state of a grain after it was updated in the avalanche started by grainIndex
+     */
+    pub fn recordUpdate(&mut self, grainId: u32, grainIndex: usize) {
+        let Some(residence) = self.grains.get_mut(grainId as usize) else {
+            return;
+        };
+        let Some(grain) = Grain::getGrainById(grainId) else {
+            return;
+        };
+
+        if residence.dropTime.is_none() {
+            residence.dropTime = Some(grainIndex);
+        }
+
+        match grain.state {
+            GrainState::Stationary => {
+                if !residence.isResting() {
+                    residence.rests.push((grainIndex, grain.z));
+                }
+            },
+            GrainState::OffPile => {
+                if residence.isResting() {
+                    residence.mobilizations.push(grainIndex);
+                }
+                if residence.exitTime.is_none() {
+                    residence.exitTime = Some(grainIndex);
+                }
+            },
+            _ => {
+                if residence.isResting() {
+                    residence.mobilizations.push(grainIndex);
+                }
+            },
+        }
+    }
+
+    /**
+     * Export the residence times, transit times, layer turnover and the bookkeeping of every grain
+     * endTime is the number of grains dropped in the run, grains still resting are counted up to it
+     */
+    pub fn displayResidence(&self, endTime: usize, folder_path: String) -> io::Result<()> {
+        self.displayResidenceTimes(endTime, &folder_path)?;
+        self.displayTransitTimes(&folder_path)?;
+        self.displayLayerTurnover(endTime, &folder_path)?;
+        self.displayGrainResidence(endTime, &folder_path)?;
+        Ok(())
+    }
+
+    /**
+     * Histogram of the time grains rested before being re-mobilized, with the rests still going
+     * at the end of the run counted separately by their length so far
+     */
+    fn displayResidenceTimes(&self, endTime: usize, folder_path: &str) -> io::Result<()> {
+        let schema = CsvSchema::new("residence-times", "residence-times.csv", "Histogram of the grains dropped while a grain rested in the pile before an avalanche moved it again", vec![
+            CsvColumn::new("residence_time", "integer", "grains dropped", "Grains dropped between the grain coming to rest and being moved again"),
+            CsvColumn::new("rest_count", "integer", "rests", "Rests that ended in a re-mobilization after that long"),
+            CsvColumn::new("censored_count", "integer", "rests", "Rests still going at the end of the run that had lasted that long"),
+        ]);
+        let mut writer = CsvWriter::create(folder_path, "residence-times.csv", &schema)?;
+
+        let mut histogram: BTreeMap<usize, (u64, u64)> = BTreeMap::new();
+        for residence in &self.grains {
+            for (i, (restTime, _)) in residence.rests.iter().enumerate() {
+                match residence.mobilizations.get(i) {
+                    Some(mobilizationTime) => histogram.entry(mobilizationTime - restTime).or_default().0 += 1,
+                    None => histogram.entry(endTime.saturating_sub(*restTime)).or_default().1 += 1,
+                }
+            }
+        }
+
+        for (residenceTime, (restCount, censoredCount)) in histogram {
+            writer.writeRow(&[residenceTime.to_string(), restCount.to_string(), censoredCount.to_string()])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()
+    }
+
+    /**
+     * Histogram of the time from dropping a grain to it leaving the pile
+     */
+    fn displayTransitTimes(&self, folder_path: &str) -> io::Result<()> {
+        let schema = CsvSchema::new("transit-times", "transit-times.csv", "Histogram of the grains dropped between a grain being dropped and leaving the pile", vec![
+            CsvColumn::new("transit_time", "integer", "grains dropped", "Grains dropped between the grain being dropped and leaving the pile"),
+            CsvColumn::new("grain_count", "integer", "grains", "Grains that left the pile after that long"),
+        ]);
+        let mut writer = CsvWriter::create(folder_path, "transit-times.csv", &schema)?;
+
+        let mut histogram: BTreeMap<usize, u64> = BTreeMap::new();
+        for residence in &self.grains {
+            if let (Some(dropTime), Some(exitTime)) = (residence.dropTime, residence.exitTime) {
+                *histogram.entry(exitTime - dropTime).or_insert(0) += 1;
+            }
+        }
+
+        for (transitTime, count) in histogram {
+            writer.writeRow(&[transitTime.to_string(), count.to_string()])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()
+    }
+
+    /**
+     * Rests and re-mobilizations in each layer of the pile with the turnover rate, the
+     * re-mobilizations per resting grain per grain dropped, and its inverse the mean residence time
+     */
+    fn displayLayerTurnover(&self, endTime: usize, folder_path: &str) -> io::Result<()> {
+        let schema = CsvSchema::new("layer-turnover", "layer-turnover.csv", "Rests, re-mobilizations and turnover rate of the grains in each layer of the pile", vec![
+            CsvColumn::new("z", "integer", "layer", "Layer of the pile"),
+            CsvColumn::new("rests", "integer", "rests", "Times a grain came to rest in the layer"),
+            CsvColumn::new("mobilizations", "integer", "mobilizations", "Times a grain resting in the layer was moved again"),
+            CsvColumn::new("mean_grains_resting", "float", "grains", "Grains resting in the layer averaged over the run"),
+            CsvColumn::new("turnover_rate", "float", "1/grains dropped", "Re-mobilizations per resting grain per grain dropped"),
+            CsvColumn::new("mean_residence_time", "float", "grains dropped", "Grains dropped per re-mobilization of each resting grain, the inverse of the turnover rate"),
+        ]);
+        let mut writer = CsvWriter::create(folder_path, "layer-turnover.csv", &schema)?;
+
+        // rests, mobilizations and total time grains spent resting for each layer
+        let mut layers = vec![(0u64, 0u64, 0usize); Z_SIZE as usize];
+        for residence in &self.grains {
+            for (i, (restTime, z)) in residence.rests.iter().enumerate() {
+                let Some(layer) = layers.get_mut(*z as usize) else {
+                    continue;
+                };
+                layer.0 += 1;
+                match residence.mobilizations.get(i) {
+                    Some(mobilizationTime) => {
+                        layer.1 += 1;
+                        layer.2 += mobilizationTime - restTime;
+                    },
+                    None => layer.2 += endTime.saturating_sub(*restTime),
+                }
+            }
+        }
+
+        for (z, (rests, mobilizations, restTime)) in layers.iter().enumerate() {
+            let meanGrainsResting = if endTime > 0 { *restTime as f64 / endTime as f64 } else { 0.0 };
+            let turnoverRate = if *restTime > 0 { *mobilizations as f64 / *restTime as f64 } else { 0.0 };
+            let meanResidenceTime = if *mobilizations > 0 { *restTime as f64 / *mobilizations as f64 } else { 0.0 };
+            writer.writeRow(&[
+                z.to_string(),
+                rests.to_string(),
+                mobilizations.to_string(),
+                format!("{:.4}", meanGrainsResting),
+                format!("{:.6e}", turnoverRate),
+                format!("{:.2}", meanResidenceTime),
+            ])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()
+    }
+
+    /**
+     * Bookkeeping of every grain that was dropped
+     */
+    fn displayGrainResidence(&self, endTime: usize, folder_path: &str) -> io::Result<()> {
+        let schema = CsvSchema::new("grain-residence", "grain-residence.csv", "Drop, rest, re-mobilization and exit of every grain dropped", vec![
+            CsvColumn::new("grain_id", "integer", "grain id", "Id of the grain"),
+            CsvColumn::new("drop_time", "integer", "grain index", "Grain index the grain was dropped at"),
+            CsvColumn::new("rests", "integer", "rests", "Times the grain came to rest"),
+            CsvColumn::new("mobilizations", "integer", "mobilizations", "Times the grain was moved again after resting"),
+            CsvColumn::new("time_resting", "integer", "grains dropped", "Grains dropped while the grain was resting, up to the end of the run"),
+            CsvColumn::new("first_rest_z", "integer", "layer", "Layer the grain first came to rest in, empty if it never rested"),
+            CsvColumn::new("last_rest_z", "integer", "layer", "Layer the grain last came to rest in, empty if it never rested"),
+            CsvColumn::new("exit_time", "integer", "grain index", "Grain index the grain left the pile at, empty if it is in the pile"),
+        ]);
+        let mut writer = CsvWriter::create(folder_path, "grain-residence.csv", &schema)?;
+
+        for (grainId, residence) in self.grains.iter().enumerate() {
+            let Some(dropTime) = residence.dropTime else {
+                continue;
+            };
+            let timeResting: usize = residence.rests.iter().enumerate()
+                .map(|(i, (restTime, _))| residence.mobilizations.get(i).copied().unwrap_or(endTime).saturating_sub(*restTime))
+                .sum();
+            let layer = |rest: Option<&(usize, i32)>| rest.map(|(_, z)| z.to_string()).unwrap_or_default();
+
+            writer.writeRow(&[
+                grainId.to_string(),
+                dropTime.to_string(),
+                residence.rests.len().to_string(),
+                residence.mobilizations.len().to_string(),
+                timeResting.to_string(),
+                layer(residence.rests.first()),
+                layer(residence.rests.last()),
+                residence.exitTime.map(|exitTime| exitTime.to_string()).unwrap_or_default(),
+            ])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()
+    }
+}
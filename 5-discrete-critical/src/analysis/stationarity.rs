@@ -0,0 +1,208 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::models::location::Location;
+use crate::analysis::pileObservables::slopeStatistics;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput};
+
+// constants
+use crate::util::constants::{DEBUG, Z_SIZE};
+use crate::util::constants::{STATIONARITY_SAMPLE_INTERVAL, STATIONARITY_WINDOW, STATIONARITY_TOLERANCE};
+use crate::util::constants::{STATIONARITY_MIN_OUTFLOW_RATIO, STATIONARY_AVALANCHE_LIMIT};
+
+
+/**
+ * Observables of the pile recorded at a single point in the run
+ */
+pub struct StationaritySample {
+    // index of the last grain dropped before the sample was taken
+    pub grainIndex: usize,
+    // total grains resting in the pile
+    pub grainsInPile: usize,
+    // mean grains per pile location for each layer
+    pub layerOccupancy: Vec<f64>,
+    // mean height difference between neighboring columns
+    pub meanSlope: f64,
+    // grains that left the pile divided by grains dropped over the rolling window
+    pub outflowRatio: f64,
+    // cumulative grains dropped and grains off the pile at the time of the sample
+    grainsDropped: usize,
+    grainsOffPile: usize,
+}
+
+/**
+ * Tracks running observables of the pile during a run and declares when the
+ * pile has reached a statistically stationary state, the grains in the pile and
+ * the slope of the pile stop drifting and the outflow of grains balances the input
+ */
+pub struct StationarityDetector {
+    pub samples: Vec<StationaritySample>,
+    // grain index where the pile was first found to be stationary
+    pub stationaryAtGrain: Option<usize>,
+    // cumulative grain counts for the run
+    grainsDropped: usize,
+    grainsOffPile: usize,
+}
+
+impl Default for StationarityDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StationarityDetector {
+    pub fn new() -> Self {
+        StationarityDetector {
+            samples: Vec::new(),
+            stationaryAtGrain: None,
+            grainsDropped: 0,
+            grainsOffPile: 0,
+        }
+    }
+
+    /**
+     * Record a completed avalanche, sampling the pile observables every
+     * STATIONARITY_SAMPLE_INTERVAL grains
+     */
+    pub fn recordAvalanche(&mut self, grainIndex: usize, avalanche: &Avalanche) {
+        self.grainsDropped += 1;
+        self.grainsOffPile += avalanche.grainsOffPile;
+
+        if self.grainsDropped.is_multiple_of(STATIONARITY_SAMPLE_INTERVAL) {
+            self.sample(grainIndex);
+        }
+    }
+
+    /**
+     * Determine if the run should stop, STATIONARY_AVALANCHE_LIMIT avalanches
+     * have completed since the pile became stationary
+     */
+    pub fn shouldStop(&self, grainIndex: usize) -> bool {
+        match self.stationaryAtGrain {
+            Some(stationaryAtGrain) => STATIONARY_AVALANCHE_LIMIT != 0 && grainIndex - stationaryAtGrain == STATIONARY_AVALANCHE_LIMIT,
+            None => false,
+        }
+    }
+
+    fn sample(&mut self, grainIndex: usize) {
+
+        // find the outflow ratio over the rolling window, or since the start of the run if the window is not yet full
+        let (droppedAtStart, offPileAtStart) = if self.samples.len() >= STATIONARITY_WINDOW {
+            let start = &self.samples[self.samples.len() - STATIONARITY_WINDOW];
+            (start.grainsDropped, start.grainsOffPile)
+        } else {
+            (0, 0)
+        };
+        let outflowRatio = (self.grainsOffPile - offPileAtStart) as f64 / (self.grainsDropped - droppedAtStart) as f64;
+
+        self.samples.push(StationaritySample {
+            grainIndex,
+            grainsInPile: Location::getTotalGrainsInPile(),
+            layerOccupancy: Location::getLayerOccupancy(),
+            meanSlope: slopeStatistics(&Location::getHeightMap()).0,
+            outflowRatio,
+            grainsDropped: self.grainsDropped,
+            grainsOffPile: self.grainsOffPile,
+        });
+
+        if self.stationaryAtGrain.is_none() && self.isWindowStationary() {
+            self.stationaryAtGrain = Some(grainIndex);
+            if DEBUG { println!("---------------- Pile is stationary at grain: {} ----------------", grainIndex) };
+        }
+    }
+
+    /**
+     * The pile is stationary when, over the last STATIONARITY_WINDOW samples, the mean grains
+     * in the pile and the mean slope of the first and second half of the window are within
+     * STATIONARITY_TOLERANCE of each other and the outflow ratio is at least STATIONARITY_MIN_OUTFLOW_RATIO
+     */
+    fn isWindowStationary(&self) -> bool {
+        if self.samples.len() < STATIONARITY_WINDOW || STATIONARITY_WINDOW < 2 {
+            return false;
+        }
+
+        let window = &self.samples[self.samples.len() - STATIONARITY_WINDOW..];
+        let (firstHalf, secondHalf) = window.split_at(STATIONARITY_WINDOW / 2);
+
+        let grainsDrift = relativeDrift(
+            mean(firstHalf.iter().map(|s| s.grainsInPile as f64)),
+            mean(secondHalf.iter().map(|s| s.grainsInPile as f64)),
+        );
+        let slopeDrift = relativeDrift(
+            mean(firstHalf.iter().map(|s| s.meanSlope)),
+            mean(secondHalf.iter().map(|s| s.meanSlope)),
+        );
+        let outflowRatio = window.last().unwrap().outflowRatio;
+
+        grainsDrift <= STATIONARITY_TOLERANCE && slopeDrift <= STATIONARITY_TOLERANCE && outflowRatio >= STATIONARITY_MIN_OUTFLOW_RATIO
+    }
+
+    /**
+     * Export the sampled observables and the point where the pile became stationary
+     */
+    pub fn displayStationarity(&self, folder_path: String) -> io::Result<()> {
+        // Create the csv through the common run output writer
+        let mut columns = vec![
+            CsvColumn::new("grain_index", "integer", "grain index", "Index of the last grain dropped before the sample"),
+            CsvColumn::new("grains_in_pile", "integer", "grains", "Total grains resting in the pile"),
+            CsvColumn::new("mean_slope", "float", "layers", "Mean height difference between neighboring columns"),
+            CsvColumn::new("outflow_ratio", "float", "ratio", "Grains that left the pile divided by grains dropped over the rolling window"),
+        ];
+        for z in 0..Z_SIZE {
+            columns.push(CsvColumn::new(&format!("layer_{}_occupancy", z), "float", "grains per location", &format!("Mean grains per pile location in layer {}", z)));
+        }
+        let schema = CsvSchema::new("stationarity", "stationarity.csv", "Running observables of the pile sampled every STATIONARITY_SAMPLE_INTERVAL grains", columns);
+        let mut writer = CsvWriter::create(&folder_path, "stationarity.csv", &schema)?;
+
+        for sample in &self.samples {
+            let mut row = vec![
+                sample.grainIndex.to_string(),
+                sample.grainsInPile.to_string(),
+                format!("{:.4}", sample.meanSlope),
+                format!("{:.4}", sample.outflowRatio),
+            ];
+            row.extend(sample.layerOccupancy.iter().map(|occupancy| format!("{:.4}", occupancy)));
+            writer.writeRow(&row)?;
+        }
+        writer.finish()?;
+
+        // write the summary of the stationarity detection
+        let file = File::create(folder_path.clone() + "/stationarity-summary.txt")?;
+        let mut writer = BufWriter::new(file);
+        registerOutput(&folder_path, "stationarity-summary.txt", "text", "Stationarity detection settings and the grain index where the pile became stationary", None);
+
+        writeln!( writer, "Stationarity summary")?;
+        writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+        writeln!( writer, "Sample Interval (grains): {}", STATIONARITY_SAMPLE_INTERVAL)?;
+        writeln!( writer, "Window (samples): {}", STATIONARITY_WINDOW)?;
+        writeln!( writer, "Tolerance: {}", STATIONARITY_TOLERANCE)?;
+        writeln!( writer, "Minimum Outflow Ratio: {}", STATIONARITY_MIN_OUTFLOW_RATIO)?;
+        writeln!( writer, "Stationary Avalanche Limit: {}", STATIONARY_AVALANCHE_LIMIT)?;
+        match self.stationaryAtGrain {
+            Some(grainIndex) => writeln!( writer, "Stationary At Grain: {}", grainIndex)?,
+            None => writeln!( writer, "Stationary At Grain: not reached")?,
+        }
+        writeln!( writer, "Grains Dropped: {}", self.grainsDropped)?;
+        writeln!( writer, "Grains Off Pile: {}", self.grainsOffPile)?;
+        writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (total, count) = values.fold((0.0, 0), |(total, count), value| (total + value, count + 1));
+    if count == 0 { 0.0 } else { total / count as f64 }
+}
+
+fn relativeDrift(first: f64, second: f64) -> f64 {
+    if first == 0.0 {
+        if second == 0.0 { 0.0 } else { f64::INFINITY }
+    } else {
+        ((second - first) / first).abs()
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::analysis::distributions::AVALANCHE_MEASURES;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput};
+
+// constants
+use crate::util::constants::{TEMPORAL_MEASURE, WAITING_TIME_THRESHOLDS, AUTOCORRELATION_MAX_LAG, DFA_MIN_SCALE};
+use crate::util::constants::{OMORI_MAINSHOCK_QUANTILE, OMORI_AFTERSHOCK_QUANTILE, OMORI_MAX_LAG};
+
+
+// window sizes of the detrended fluctuation analysis and lag bins of the aftershock rate for each power of ten
+const SCALES_PER_DECADE: usize = 10;
+
+/**
+ * Fluctuation of the detrended profile of the series over windows of one size
+ */
+#[derive(Clone, Debug)]
+pub struct DfaScale {
+    // avalanches in each window
+    pub scale: usize,
+    pub segments: usize,
+    // root mean square of the profile around the linear trend of each window
+    pub fluctuation: f64,
+}
+
+/**
+ * Aftershocks counted over a bin of lags after the mainshocks
+ */
+#[derive(Clone, Debug)]
+pub struct AftershockBin {
+    pub lagStart: usize,
+    pub lagEnd: usize,
+    // geometric center of the bin, where the rate is fitted
+    pub lagCenter: f64,
+    pub aftershocks: u64,
+    // mainshocks followed by enough avalanches to count each lag in the bin, summed over the lags
+    pub exposure: u64,
+    // aftershocks per mainshock per avalanche over the bin
+    pub rate: f64,
+}
+
+/**
+ * Avalanches between consecutive events, the avalanches reaching the threshold size
+ */
+pub fn waitingTimes(sizes: &[u64], threshold: u64) -> Vec<u64> {
+    let events: Vec<usize> = sizes.iter().enumerate().filter(|&(_, &size)| size >= threshold).map(|(i, _)| i).collect();
+    events.windows(2).map(|pair| (pair[1] - pair[0]) as u64).collect()
+}
+
+/**
+ * Autocorrelation of the series at lags 1 to maxLag, empty when the series does not vary
+ */
+pub fn autocorrelation(sizes: &[u64], maxLag: usize) -> Vec<f64> {
+    let mean = sizes.iter().sum::<u64>() as f64 / sizes.len().max(1) as f64;
+    let deviations: Vec<f64> = sizes.iter().map(|&size| size as f64 - mean).collect();
+    let variance: f64 = deviations.iter().map(|deviation| deviation * deviation).sum();
+    if variance == 0.0 {
+        return Vec::new();
+    }
+
+    (1..=maxLag.min(deviations.len().saturating_sub(1)))
+        .map(|lag| deviations.iter().zip(&deviations[lag..]).map(|(a, b)| a * b).sum::<f64>() / variance)
+        .collect()
+}
+
+/**
+ * Detrended fluctuation analysis of the series, the profile of the series around its mean is split
+ * into windows of each scale from minScale to a quarter of the series and the fluctuation around the
+ * linear trend of each window is measured, the fluctuation grows as scale^alpha where alpha is 0.5
+ * for an uncorrelated series, above 0.5 for a persistent one and estimates the Hurst exponent
+ */
+pub fn detrendedFluctuation(sizes: &[u64], minScale: usize) -> Vec<DfaScale> {
+    let mean = sizes.iter().sum::<u64>() as f64 / sizes.len().max(1) as f64;
+    let profile: Vec<f64> = sizes.iter()
+        .scan(0.0, |sum, &size| {
+            *sum += size as f64 - mean;
+            Some(*sum)
+        })
+        .collect();
+
+    let mut scales: Vec<usize> = Vec::new();
+    let mut k = 0;
+    loop {
+        let scale = (minScale.max(3) as f64 * 10f64.powf(k as f64 / SCALES_PER_DECADE as f64)).round() as usize;
+        if scale > profile.len() / 4 {
+            break;
+        }
+        if scales.last() != Some(&scale) {
+            scales.push(scale);
+        }
+        k += 1;
+    }
+
+    scales.into_iter()
+        .map(|scale| {
+            let segments = profile.len() / scale;
+            let squares: f64 = profile.chunks_exact(scale).map(detrendedSquares).sum();
+            DfaScale { scale, segments, fluctuation: (squares / (segments * scale) as f64).sqrt() }
+        })
+        .collect()
+}
+
+/**
+ * Sum of the squared residuals of a window around its least squares line
+ */
+fn detrendedSquares(window: &[f64]) -> f64 {
+    let points: Vec<(f64, f64)> = window.iter().enumerate().map(|(i, &value)| (i as f64, value)).collect();
+    let (slope, intercept) = leastSquares(&points);
+    points.iter().map(|&(x, y)| (y - slope * x - intercept).powi(2)).sum()
+}
+
+/**
+ * Rate of aftershocks, the avalanches reaching aftershockSize, at each lag after a mainshock, the
+ * avalanches reaching mainshockSize, in logarithmic bins of lag up to maxLag
+ */
+pub fn aftershockRates(sizes: &[u64], mainshockSize: u64, aftershockSize: u64, maxLag: usize) -> (usize, Vec<AftershockBin>) {
+    let mut aftershocks = vec![0u64; maxLag + 1];
+    let mut exposure = vec![0u64; maxLag + 1];
+    let mut mainshocks = 0;
+    for (i, &size) in sizes.iter().enumerate() {
+        if size < mainshockSize {
+            continue;
+        }
+        mainshocks += 1;
+        for (lag, &after) in sizes[i + 1..].iter().take(maxLag).enumerate() {
+            exposure[lag + 1] += 1;
+            if after >= aftershockSize {
+                aftershocks[lag + 1] += 1;
+            }
+        }
+    }
+
+    let mut bins = Vec::new();
+    let mut lagStart = 1;
+    let mut k = 1;
+    while lagStart <= maxLag {
+        let edge = 10f64.powf(k as f64 / SCALES_PER_DECADE as f64).ceil() as usize;
+        k += 1;
+        if edge <= lagStart {
+            continue;
+        }
+        let lagEnd = (edge - 1).min(maxLag);
+        let binAftershocks: u64 = aftershocks[lagStart..=lagEnd].iter().sum();
+        let binExposure: u64 = exposure[lagStart..=lagEnd].iter().sum();
+        if binExposure > 0 {
+            bins.push(AftershockBin {
+                lagStart,
+                lagEnd,
+                lagCenter: ((lagStart * lagEnd) as f64).sqrt(),
+                aftershocks: binAftershocks,
+                exposure: binExposure,
+                rate: binAftershocks as f64 / binExposure as f64,
+            });
+        }
+        lagStart = lagEnd + 1;
+    }
+    (mainshocks, bins)
+}
+
+/**
+ * Slope and intercept of the least squares line through the points
+ */
+fn leastSquares(points: &[(f64, f64)]) -> (f64, f64) {
+    let n = points.len() as f64;
+    let meanX = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let meanY = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|&(x, _)| (x - meanX).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|&(x, y)| (x - meanX) * (y - meanY)).sum();
+    let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    (slope, meanY - slope * meanX)
+}
+
+/**
+ * Slope of the least squares line through the points on log-log axes with its standard error,
+ * None with fewer than three points
+ */
+pub fn logLogSlope(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let points: Vec<(f64, f64)> = points.iter().filter(|&&(x, y)| x > 0.0 && y > 0.0).map(|&(x, y)| (x.ln(), y.ln())).collect();
+    if points.len() < 3 {
+        return None;
+    }
+    let (slope, intercept) = leastSquares(&points);
+    let meanX = points.iter().map(|&(x, _)| x).sum::<f64>() / points.len() as f64;
+    let sxx: f64 = points.iter().map(|&(x, _)| (x - meanX).powi(2)).sum();
+    let residuals: f64 = points.iter().map(|&(x, y)| (y - slope * x - intercept).powi(2)).sum();
+    Some((slope, (residuals / (points.len() - 2) as f64 / sxx).sqrt()))
+}
+
+/**
+ * Size at a quantile of the series, at least 1 so avalanches that moved nothing are never events
+ */
+fn quantileSize(sizes: &[u64], quantile: f64) -> u64 {
+    let mut sorted = sizes.to_vec();
+    sorted.sort_unstable();
+    let index = ((sorted.len().saturating_sub(1)) as f64 * quantile.clamp(0.0, 1.0)).round() as usize;
+    sorted.get(index).copied().unwrap_or(0).max(1)
+}
+
+/**
+ * Export the temporal statistics of the avalanche sizes in the order the grains were dropped, from
+ * the grain the pile became stationary at when it did, to the temporal folder of the run
+ *  temporal/waiting-times.csv - avalanches between events reaching each of WAITING_TIME_THRESHOLDS
+ *  temporal/size-autocorrelation.csv - autocorrelation of the sizes up to AUTOCORRELATION_MAX_LAG
+ *  temporal/dfa.csv - detrended fluctuation of the sizes at each window size
+ *  temporal/omori.csv - rate of aftershocks after the largest avalanches
+ *  temporal/temporal-statistics.txt - summary with the Hurst and Omori exponents
+ */
+pub fn displayTemporalStatistics(avalanches: &[Avalanche], stationaryAtGrain: Option<usize>, folder_path: String) -> io::Result<()> {
+    let Some((_, unit, measure)) = AVALANCHE_MEASURES.iter().find(|(name, _, _)| *name == TEMPORAL_MEASURE) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown temporal measure {}", TEMPORAL_MEASURE)));
+    };
+    let start = stationaryAtGrain.unwrap_or(0).min(avalanches.len());
+    let sizes: Vec<u64> = avalanches[start..].iter().map(|avalanche| measure(avalanche) as u64).collect();
+
+    fs::create_dir_all(folder_path.clone() + "/temporal")?;
+
+    let schema = CsvSchema::new("waiting-times", "temporal/waiting-times.csv", &format!("Histogram of the avalanches between consecutive avalanches with {} of at least each threshold", TEMPORAL_MEASURE), vec![
+        CsvColumn::new("threshold", "integer", unit, "Size an avalanche has to reach to be an event"),
+        CsvColumn::new("waiting_time", "integer", "avalanches", "Avalanches from one event to the next"),
+        CsvColumn::new("count", "integer", "events", "Events followed by the next after the waiting time"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "temporal/waiting-times.csv", &schema)?;
+    let mut waitingSummaries = Vec::new();
+    for threshold in WAITING_TIME_THRESHOLDS {
+        let waits = waitingTimes(&sizes, threshold as u64);
+        let mut histogram: BTreeMap<u64, u64> = BTreeMap::new();
+        for &wait in &waits {
+            *histogram.entry(wait).or_insert(0) += 1;
+        }
+        for (wait, count) in histogram {
+            writer.writeRow(&[threshold.to_string(), wait.to_string(), count.to_string()])?;
+        }
+
+        // the coefficient of variation is 1 for events arriving at random, above 1 when they cluster
+        let mean = waits.iter().sum::<u64>() as f64 / waits.len().max(1) as f64;
+        let variance = waits.iter().map(|&wait| (wait as f64 - mean).powi(2)).sum::<f64>() / waits.len().max(1) as f64;
+        waitingSummaries.push((threshold, waits.len(), mean, if mean > 0.0 { variance.sqrt() / mean } else { 0.0 }));
+    }
+    writer.finish()?;
+
+    let schema = CsvSchema::new("size-autocorrelation", "temporal/size-autocorrelation.csv", &format!("Autocorrelation of avalanche {} between avalanches a lag apart", TEMPORAL_MEASURE), vec![
+        CsvColumn::new("lag", "integer", "avalanches", "Avalanches between the pair"),
+        CsvColumn::new("autocorrelation", "float", "", "Correlation of the sizes of avalanches the lag apart"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "temporal/size-autocorrelation.csv", &schema)?;
+    let correlations = autocorrelation(&sizes, AUTOCORRELATION_MAX_LAG);
+    for (lag, correlation) in correlations.iter().enumerate() {
+        writer.writeRow(&[(lag + 1).to_string(), correlation.to_string()])?;
+    }
+    writer.finish()?;
+
+    let schema = CsvSchema::new("dfa", "temporal/dfa.csv", &format!("Detrended fluctuation of the profile of avalanche {} at each window size", TEMPORAL_MEASURE), vec![
+        CsvColumn::new("scale", "integer", "avalanches", "Avalanches in each window"),
+        CsvColumn::new("segments", "integer", "windows", "Windows the series was split into"),
+        CsvColumn::new("fluctuation", "float", unit, "Root mean square of the profile around the trend of each window"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "temporal/dfa.csv", &schema)?;
+    let dfa = detrendedFluctuation(&sizes, DFA_MIN_SCALE);
+    for scale in &dfa {
+        writer.writeRow(&[scale.scale.to_string(), scale.segments.to_string(), scale.fluctuation.to_string()])?;
+    }
+    writer.finish()?;
+    let hurst = logLogSlope(&dfa.iter().map(|scale| (scale.scale as f64, scale.fluctuation)).collect::<Vec<_>>());
+
+    let mainshockSize = quantileSize(&sizes, OMORI_MAINSHOCK_QUANTILE);
+    let aftershockSize = quantileSize(&sizes, OMORI_AFTERSHOCK_QUANTILE);
+    // rate of aftershock sized avalanches anywhere in the series, the rate after mainshocks decays toward it
+    let backgroundRate = sizes.iter().filter(|&&size| size >= aftershockSize).count() as f64 / sizes.len().max(1) as f64;
+    let (mainshocks, bins) = aftershockRates(&sizes, mainshockSize, aftershockSize, OMORI_MAX_LAG);
+
+    let schema = CsvSchema::new("omori", "temporal/omori.csv", &format!("Rate of aftershocks at each lag after a mainshock in logarithmic bins of lag, by avalanche {}", TEMPORAL_MEASURE), vec![
+        CsvColumn::new("lag_start", "integer", "avalanches", "Smallest lag after the mainshock in the bin"),
+        CsvColumn::new("lag_end", "integer", "avalanches", "Largest lag after the mainshock in the bin"),
+        CsvColumn::new("lag_center", "float", "avalanches", "Geometric center of the bin"),
+        CsvColumn::new("aftershocks", "integer", "avalanches", "Aftershocks at the lags of the bin after all the mainshocks"),
+        CsvColumn::new("exposure", "integer", "avalanches", "Mainshocks followed by each lag of the bin, summed over the lags"),
+        CsvColumn::new("rate", "float", "aftershocks per avalanche", "Aftershocks per mainshock per avalanche over the bin"),
+        CsvColumn::new("excess_rate", "float", "aftershocks per avalanche", "Rate above the background rate of aftershock sized avalanches"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "temporal/omori.csv", &schema)?;
+    for bin in &bins {
+        writer.writeRow(&[
+            bin.lagStart.to_string(),
+            bin.lagEnd.to_string(),
+            format!("{:.3}", bin.lagCenter),
+            bin.aftershocks.to_string(),
+            bin.exposure.to_string(),
+            bin.rate.to_string(),
+            (bin.rate - backgroundRate).to_string(),
+        ])?;
+    }
+    writer.finish()?;
+    // the excess rate decays as lag^-p, only the lags from the mainshock up to where the rate first falls to the background are fitted
+    let omori = logLogSlope(&bins.iter().map(|bin| (bin.lagCenter, bin.rate - backgroundRate)).take_while(|&(_, excess)| excess > 0.0).collect::<Vec<_>>());
+
+    // Create a file and wrap it in a BufWriter for efficient writing
+    let file = File::create(folder_path.clone() + "/temporal/temporal-statistics.txt")?;
+    let mut writer = BufWriter::new(file);
+    registerOutput(&folder_path, "temporal/temporal-statistics.txt", "text", "Waiting times, size autocorrelation, Hurst exponent and Omori exponent of the avalanche series", None);
+
+    writeln!( writer, "Temporal statistics of avalanche {}", TEMPORAL_MEASURE)?;
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Avalanches: {} from grain {}", sizes.len(), start)?;
+    for (threshold, waits, mean, variation) in waitingSummaries {
+        if waits == 0 {
+            writeln!( writer, "Waiting time (events reaching {} {}): none, fewer than two events", threshold, unit)?;
+            continue;
+        }
+        writeln!( writer, "Waiting time (events reaching {} {}): {} waits, mean {:.3} avalanches, coefficient of variation {:.3} (1 = random, > 1 = clustered)", threshold, unit, waits, mean, variation)?;
+    }
+    match correlations.first() {
+        Some(correlation) => writeln!( writer, "Size autocorrelation at lag 1: {:.4}", correlation)?,
+        None => writeln!( writer, "Size autocorrelation at lag 1: none, the sizes do not vary")?,
+    }
+    match hurst {
+        Some((alpha, alphaError)) => writeln!( writer, "Hurst exponent (DFA alpha, 0.5 = uncorrelated, > 0.5 = persistent): {:.4} ± {:.4} over {} window sizes", alpha, alphaError, dfa.len())?,
+        None => writeln!( writer, "Hurst exponent (DFA alpha): none, too few avalanches")?,
+    }
+    writeln!( writer, "Omori mainshocks (reaching {} {}, quantile {}): {}", mainshockSize, unit, OMORI_MAINSHOCK_QUANTILE, mainshocks)?;
+    writeln!( writer, "Omori aftershock size (quantile {}): {} {}, background rate {:.4} per avalanche", OMORI_AFTERSHOCK_QUANTILE, aftershockSize, unit, backgroundRate)?;
+    match omori {
+        Some((slope, slopeError)) => writeln!( writer, "Omori exponent p (excess aftershock rate ~ lag^-p): {:.4} ± {:.4}", -slope, slopeError)?,
+        None => writeln!( writer, "Omori exponent p: none, the aftershock rate is not above the background at enough of the first lags")?,
+    }
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+
+    // flush the writer to ensure all data is written to the file
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn waitingTimesBetweenEvents() {
+        let sizes = [5, 0, 7, 1, 1, 9, 0];
+        assert_eq!(waitingTimes(&sizes, 5), vec![2, 3]);
+        assert_eq!(waitingTimes(&sizes, 8), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn alternatingSeriesIsAnticorrelated() {
+        let sizes: Vec<u64> = (0..1000).map(|i| (i % 2) * 2).collect();
+        let correlations = autocorrelation(&sizes, 2);
+        assert!((correlations[0] + 1.0).abs() < 0.01);
+        assert!((correlations[1] - 1.0).abs() < 0.01);
+        assert!(autocorrelation(&[3, 3, 3], 2).is_empty());
+    }
+
+    #[test]
+    fn whiteNoiseHasHalfDfaSlope() {
+        let mut rnd = StdRng::seed_from_u64(1);
+        let sizes: Vec<u64> = (0..20000).map(|_| rnd.gen_range(0..100)).collect();
+        let points: Vec<(f64, f64)> = detrendedFluctuation(&sizes, 10).iter().map(|scale| (scale.scale as f64, scale.fluctuation)).collect();
+        let (slope, _) = logLogSlope(&points).unwrap();
+        assert!((slope - 0.5).abs() < 0.05, "slope {}", slope);
+    }
+
+    #[test]
+    fn aftershockExposureStopsAtTheEndOfTheSeries() {
+        // the second mainshock is one avalanche from the end so only lag 1 is counted after it
+        let (mainshocks, bins) = aftershockRates(&[10, 0, 0, 1, 10, 1], 10, 1, 3);
+        assert_eq!(mainshocks, 2);
+        let counts: Vec<(usize, usize, u64, u64)> = bins.iter().map(|bin| (bin.lagStart, bin.lagEnd, bin.aftershocks, bin.exposure)).collect();
+        assert_eq!(counts, vec![(1, 1, 1, 2), (2, 2, 0, 1), (3, 3, 1, 1)]);
+        assert_eq!(bins[0].rate, 0.5);
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+// internal models
+use crate::analysis::powerLaw::{PowerLawFit, fitPowerLaw};
+use crate::util::runFolder::{RUN_DISTRIBUTIONS, RunFolder};
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+
+// index of all the runs written to the data directory
+pub const CATALOGUE_FILE: &str = "catalogue.csv";
+
+pub const CATALOGUE_USAGE: &str = "catalogue [--data <folder>] [--sort <key>] [--columns <key,key,...>] [<key><op><value> ...]
+    List the runs in the data folder (default ./data) with their configuration and fitted exponents
+    and write the index to <data folder>/catalogue.csv
+    filters compare a configuration constant (e.g. ALPHA_EXTRA_ENERGY), label, run_id or a fitted
+    exponent (e.g. movement_alpha) or branching_ratio with one of = != < <= > >=, e.g. ALPHA_EXTRA_ENERGY>=1.2 label=extra
+    columns shown default to the configuration constants that differ between the listed runs";
+
+/**
+ * A run in the catalogue with the power law fitted to each of its avalanche distributions
+ */
+struct CatalogueEntry {
+    run: RunFolder,
+    // fit for each of RUN_DISTRIBUTIONS, None if the histogram is missing or too small to fit
+    fits: Vec<Option<PowerLawFit>>,
+}
+
+impl CatalogueEntry {
+    /**
+     * Value of a configuration constant or fitted exponent field such as movement_alpha
+     */
+    fn getValue(&self, key: &str) -> Option<f64> {
+        for ((distribution, _), fit) in RUN_DISTRIBUTIONS.iter().zip(&self.fits) {
+            if let Some(field) = key.strip_prefix(distribution).and_then(|field| field.strip_prefix('_')) {
+                let fit = fit.as_ref()?;
+                return match field {
+                    "alpha" => Some(fit.alpha),
+                    "alpha_error" => Some(fit.alphaError),
+                    "x_min" => Some(fit.xMin as f64),
+                    "tail_count" => Some(fit.tailCount as f64),
+                    "ks_distance" => Some(fit.ksDistance),
+                    _ => None,
+                };
+            }
+        }
+        match key {
+            "branching_ratio" => self.run.branchingRatio.map(|(sigma, _)| sigma),
+            "branching_ratio_error" => self.run.branchingRatio.map(|(_, sigmaError)| sigmaError),
+            _ => self.run.getConfig(key),
+        }
+    }
+
+    fn getText(&self, key: &str) -> Option<String> {
+        match key {
+            "run_id" => Some(self.run.runId.clone()),
+            "label" => self.run.label.clone(),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Condition on a catalogue key such as ALPHA_EXTRA_ENERGY>=1.2
+ */
+struct CatalogueFilter {
+    key: String,
+    operator: &'static str,
+    value: String,
+}
+
+impl CatalogueFilter {
+    fn parse(argument: &str) -> Option<Self> {
+        // two character operators first so <= is not read as <
+        for operator in ["!=", "<=", ">=", "=", "<", ">"] {
+            if let Some((key, value)) = argument.split_once(operator) {
+                return Some(CatalogueFilter {
+                    key: key.trim().to_string(),
+                    operator,
+                    value: value.trim().to_string(),
+                });
+            }
+        }
+        None
+    }
+
+    fn matches(&self, entry: &CatalogueEntry) -> bool {
+        // text keys match when they contain the value
+        if let Some(text) = entry.getText(&self.key) {
+            let contains = text.to_lowercase().contains(&self.value.to_lowercase());
+            return if self.operator == "!=" { !contains } else { contains };
+        }
+
+        let (Some(actual), Ok(value)) = (entry.getValue(&self.key), self.value.parse::<f64>()) else {
+            return false;
+        };
+        let equal = (actual - value).abs() <= 1e-9 * value.abs().max(1.0);
+        match self.operator {
+            "=" => equal,
+            "!=" => !equal,
+            "<" => actual < value && !equal,
+            "<=" => actual < value || equal,
+            ">" => actual > value && !equal,
+            _ => actual > value || equal,
+        }
+    }
+}
+
+/**
+ * Scan the run folders in the data directory, write the catalogue index and print the runs
+ * matching the filters as a table of their configuration and fitted exponents
+ */
+pub fn runCatalogue(args: &[String]) -> io::Result<()> {
+    let mut dataPath = "./data".to_string();
+    let mut sortKey: Option<String> = None;
+    let mut columns: Option<Vec<String>> = None;
+    let mut filters = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data" => dataPath = args.next().ok_or_else(|| invalidInput("--data needs a folder"))?.clone(),
+            "--sort" => sortKey = Some(args.next().ok_or_else(|| invalidInput("--sort needs a key"))?.clone()),
+            "--columns" => columns = Some(args.next().ok_or_else(|| invalidInput("--columns needs a list of keys"))?
+                .split(',').map(|key| key.trim().to_string()).collect()),
+            _ => filters.push(CatalogueFilter::parse(arg).ok_or_else(|| invalidInput(&format!("invalid filter {}", arg)))?),
+        }
+    }
+
+    let mut entries = Vec::new();
+    for run in RunFolder::scan(Path::new(&dataPath))? {
+        let fits = RUN_DISTRIBUTIONS.iter()
+            .map(|(distribution, _)| run.readDistribution(distribution).ok().and_then(|histogram| fitPowerLaw(&histogram)))
+            .collect();
+        entries.push(CatalogueEntry { run, fits });
+    }
+
+    // the index always covers every run in the data folder
+    displayCatalogue(&entries, &dataPath)?;
+    let totalRuns = entries.len();
+
+    let mut entries: Vec<&CatalogueEntry> = entries.iter().filter(|entry| filters.iter().all(|filter| filter.matches(entry))).collect();
+    if let Some(sortKey) = &sortKey {
+        entries.sort_by(|a, b| {
+            match (a.getText(sortKey), b.getText(sortKey)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => a.getValue(sortKey).unwrap_or(f64::NAN).total_cmp(&b.getValue(sortKey).unwrap_or(f64::NAN)),
+            }
+        });
+    }
+
+    // show the configuration that varies between the listed runs unless the columns are given
+    let columns = columns.unwrap_or_else(|| {
+        configurationKeys(entries.iter().map(|entry| &entry.run)).into_iter()
+            .filter(|key| {
+                let values: BTreeSet<String> = entries.iter().map(|entry| formatValue(entry.getValue(key))).collect();
+                values.len() > 1
+            })
+            .collect()
+    });
+
+    printCatalogue(&entries, &columns);
+    println!("{} of {} runs, index written to {}/{}", entries.len(), totalRuns, dataPath, CATALOGUE_FILE);
+
+    Ok(())
+}
+
+/**
+ * Write the catalogue index, one row per run with every configuration constant, fitted exponent and the branching ratio
+ */
+fn displayCatalogue(entries: &[CatalogueEntry], dataPath: &str) -> io::Result<()> {
+    let keys = configurationKeys(entries.iter().map(|entry| &entry.run));
+
+    let mut schemaColumns = vec![
+        CsvColumn::new("run_id", "string", "", "Name of the run folder"),
+        CsvColumn::new("start_time", "string", "", "Timestamp the run was started at"),
+        CsvColumn::new("label", "string", "", "Free text at the end of the run folder name"),
+        CsvColumn::new("config_source", "string", "", "Where the configuration was read from, manifest, run-configuration or folder-name"),
+        CsvColumn::new("seed", "integer", "", "Seed the run was started with, empty for runs before seeding"),
+    ];
+    for key in &keys {
+        schemaColumns.push(CsvColumn::new(key, "float", "", "Configuration constant of the run, empty if the run did not record it"));
+    }
+    for (distribution, fileName) in RUN_DISTRIBUTIONS {
+        schemaColumns.push(CsvColumn::new(&format!("{}_alpha", distribution), "float", "", &format!("Power-law exponent fitted to {}", fileName)));
+        schemaColumns.push(CsvColumn::new(&format!("{}_alpha_error", distribution), "float", "", "Standard error of the exponent"));
+        schemaColumns.push(CsvColumn::new(&format!("{}_x_min", distribution), "integer", "", "Start of the fitted tail"));
+        schemaColumns.push(CsvColumn::new(&format!("{}_tail_count", distribution), "integer", "avalanches", "Avalanches in the fitted tail"));
+        schemaColumns.push(CsvColumn::new(&format!("{}_ks_distance", distribution), "float", "", "Kolmogorov-Smirnov distance between the tail and the fit"));
+    }
+    schemaColumns.push(CsvColumn::new("branching_ratio", "float", "grains per grain", "Mean branching ratio of the avalanches, empty for runs before it was estimated"));
+    schemaColumns.push(CsvColumn::new("branching_ratio_error", "float", "grains per grain", "Standard error of the branching ratio"));
+    let schema = CsvSchema::new("catalogue", CATALOGUE_FILE, "Index of the runs in the data folder with their configuration, fitted power-law exponents and branching ratio", schemaColumns);
+    let mut writer = CsvWriter::create(dataPath, CATALOGUE_FILE, &schema)?;
+
+    for entry in entries {
+        let mut row = vec![
+            entry.run.runId.clone(),
+            entry.run.startTime.clone().unwrap_or_default(),
+            entry.run.label.clone().unwrap_or_default(),
+            entry.run.configSource.to_string(),
+            entry.run.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+        ];
+        for key in &keys {
+            row.push(entry.run.getConfig(key).map(|value| value.to_string()).unwrap_or_default());
+        }
+        for fit in &entry.fits {
+            match fit {
+                Some(fit) => row.extend([fit.alpha.to_string(), fit.alphaError.to_string(), fit.xMin.to_string(), fit.tailCount.to_string(), fit.ksDistance.to_string()]),
+                None => row.extend(vec![String::new(); 5]),
+            }
+        }
+        match entry.run.branchingRatio {
+            Some((sigma, sigmaError)) => row.extend([sigma.to_string(), sigmaError.to_string()]),
+            None => row.extend(vec![String::new(); 2]),
+        }
+        writer.writeRow(&row)?;
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()
+}
+
+/**
+ * Print the runs as a table with the given columns followed by the fitted exponents and branching ratio
+ */
+fn printCatalogue(entries: &[&CatalogueEntry], columns: &[String]) {
+    let mut header = vec!["run_id".to_string()];
+    header.extend(columns.iter().cloned());
+    for (distribution, _) in RUN_DISTRIBUTIONS {
+        header.push(format!("{} alpha", distribution));
+    }
+    header.push("sigma".to_string());
+
+    let mut rows = vec![header];
+    for entry in entries {
+        let mut row = vec![entry.run.runId.clone()];
+        for column in columns {
+            row.push(entry.getText(column).unwrap_or_else(|| formatValue(entry.getValue(column))));
+        }
+        for fit in &entry.fits {
+            row.push(match fit {
+                Some(fit) => format!("{:.3} ± {:.3}", fit.alpha, fit.alphaError),
+                None => "-".to_string(),
+            });
+        }
+        row.push(match entry.run.branchingRatio {
+            Some((sigma, sigmaError)) => format!("{:.3} ± {:.3}", sigma, sigmaError),
+            None => "-".to_string(),
+        });
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/**
+ * Every configuration constant recorded by any of the runs
+ */
+fn configurationKeys<'a>(runs: impl Iterator<Item = &'a RunFolder>) -> Vec<String> {
+    let keys: BTreeSet<String> = runs.flat_map(|run| run.config.keys().cloned()).collect();
+    keys.into_iter().collect()
+}
+
+fn formatValue(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn entry(label: &str, alphaExtraEnergy: f64) -> CatalogueEntry {
+        let mut config = BTreeMap::new();
+        config.insert("ALPHA_EXTRA_ENERGY".to_string(), alphaExtraEnergy);
+        CatalogueEntry {
+            run: RunFolder {
+                path: PathBuf::from("data/run"),
+                runId: "run".to_string(),
+                startTime: None,
+                label: Some(label.to_string()),
+                configSource: "manifest",
+                config,
+                seed: None,
+                branchingRatio: None,
+            },
+            fits: vec![None; RUN_DISTRIBUTIONS.len()],
+        }
+    }
+
+    #[test]
+    fn parseKeepsTwoCharacterOperators() {
+        for (argument, operator) in [("ALPHA_EXTRA_ENERGY<=1.2", "<="), ("ALPHA_EXTRA_ENERGY>=1.2", ">="), ("ALPHA_EXTRA_ENERGY!=1.2", "!="), ("ALPHA_EXTRA_ENERGY<1.2", "<")] {
+            let filter = CatalogueFilter::parse(argument).unwrap();
+            assert_eq!(filter.key, "ALPHA_EXTRA_ENERGY");
+            assert_eq!(filter.operator, operator);
+            assert_eq!(filter.value, "1.2");
+        }
+        assert!(CatalogueFilter::parse("ALPHA_EXTRA_ENERGY").is_none());
+    }
+
+    #[test]
+    fn matchesNumbersAndText() {
+        let run = entry("extra-energy-2.0", 1.2);
+
+        assert!(CatalogueFilter::parse("ALPHA_EXTRA_ENERGY<=1.2").unwrap().matches(&run));
+        assert!(CatalogueFilter::parse("ALPHA_EXTRA_ENERGY>=1.2").unwrap().matches(&run));
+        assert!(!CatalogueFilter::parse("ALPHA_EXTRA_ENERGY<1.2").unwrap().matches(&run));
+        assert!(!CatalogueFilter::parse("ALPHA_EXTRA_ENERGY>1.2").unwrap().matches(&run));
+        assert!(CatalogueFilter::parse("label=Extra").unwrap().matches(&run));
+        assert!(!CatalogueFilter::parse("label!=extra").unwrap().matches(&run));
+        assert!(CatalogueFilter::parse("label!=resilience").unwrap().matches(&run));
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use chrono::Local;
+
+// internal models
+use crate::analysis::ksTest::{KsTestResult, twoSampleKsTest};
+use crate::analysis::powerLaw::{PowerLawFit, fitPowerLaw};
+use crate::util::chart::{ChartFormat, ChartSeries, displayLogLogChart};
+use crate::util::runFolder::{RUN_DISTRIBUTIONS, RunFolder};
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput, displayRunLayout};
+
+// constants
+use crate::util::constants::POWER_LAW_CONFIDENCE_Z;
+
+
+pub const COMPARE_USAGE: &str = "compare <run> <run> [<run> ...] [--output <folder>]
+    Compare two or more runs, given as run folders or run ids in ./data, and write a report to the
+    output folder (default ./reports/compare-<timestamp>) with overlaid histograms as csv and svg,
+    fitted exponents with confidence intervals, two-sample KS tests and a diff of the configurations";
+
+/**
+ * A run being compared with its histogram and power-law fit for each of RUN_DISTRIBUTIONS
+ */
+struct ComparedRun {
+    run: RunFolder,
+    histograms: Vec<Vec<(u64, u64)>>,
+    fits: Vec<Option<PowerLawFit>>,
+}
+
+/**
+ * Compare the runs given on the command line and write the comparison report
+ */
+pub fn runCompare(args: &[String]) -> io::Result<()> {
+    let mut outputPath: Option<String> = None;
+    let mut runPaths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => outputPath = Some(args.next().ok_or_else(|| invalidInput("--output needs a folder"))?.clone()),
+            _ => runPaths.push(arg.clone()),
+        }
+    }
+    if runPaths.len() < 2 {
+        return Err(invalidInput("compare needs at least two runs"));
+    }
+
+    let mut runs = Vec::new();
+    for runPath in &runPaths {
+        // run ids are looked up in the data folder
+        let path = if Path::new(runPath).is_dir() { Path::new(runPath).to_path_buf() } else { Path::new("./data").join(runPath) };
+        if !path.is_dir() {
+            return Err(invalidInput(&format!("{} is not a run folder", runPath)));
+        }
+
+        let run = RunFolder::load(&path)?;
+        let mut histograms = Vec::new();
+        for (distribution, _) in RUN_DISTRIBUTIONS {
+            // a missing histogram is compared as empty
+            histograms.push(run.readDistribution(distribution).unwrap_or_default());
+        }
+        let fits = histograms.iter().map(|histogram| fitPowerLaw(histogram)).collect();
+        runs.push(ComparedRun { run, histograms, fits });
+    }
+
+    let outputPath = outputPath.unwrap_or_else(|| format!("./reports/compare-{}", Local::now().format("%Y-%m-%d_%H-%M-%S")));
+    fs::create_dir_all(&outputPath)?;
+
+    for (d, (distribution, _)) in RUN_DISTRIBUTIONS.iter().enumerate() {
+        displayOverlaidHistogram(&runs, d, distribution, &outputPath)?;
+    }
+    displayExponents(&runs, &outputPath)?;
+    let ksTests = displayKsTests(&runs, &outputPath)?;
+    let configDiff = displayConfigDiff(&runs, &outputPath)?;
+    displayCompareReport(&runs, &ksTests, &configDiff, &outputPath)?;
+    displayRunLayout(outputPath.clone())?;
+
+    println!("Comparison of {} runs written to {}", runs.len(), outputPath);
+
+    Ok(())
+}
+
+/**
+ * Write the histograms of one distribution for every run to one csv, with the fraction of
+ * avalanches at each value so runs of different lengths line up, and draw them on one chart
+ */
+fn displayOverlaidHistogram(runs: &[ComparedRun], d: usize, distribution: &'static str, folder_path: &str) -> io::Result<()> {
+    let relativePath = format!("histogram-{}.csv", distribution);
+    let schema = CsvSchema::new(&format!("histogram-{}", distribution), &relativePath, &format!("Histogram of avalanche {} for each compared run", distribution), vec![
+        CsvColumn::new("run_id", "string", "", "Run the row belongs to"),
+        CsvColumn::new("value", "integer", distribution, "Avalanche size"),
+        CsvColumn::new("count", "integer", "avalanches", "Number of avalanches of that size"),
+        CsvColumn::new("fraction", "float", "", "Fraction of the avalanches of the run that are of that size"),
+    ]);
+    let mut writer = CsvWriter::create(folder_path, &relativePath, &schema)?;
+
+    let mut series = Vec::new();
+    for compared in runs {
+        let histogram = &compared.histograms[d];
+        let total: u64 = histogram.iter().map(|&(_, count)| count).sum();
+        let mut points = Vec::with_capacity(histogram.len());
+        for &(value, count) in histogram {
+            let fraction = count as f64 / total as f64;
+            writer.writeRow(&[compared.run.runId.clone(), value.to_string(), count.to_string(), fraction.to_string()])?;
+            points.push((value as f64, fraction));
+        }
+        series.push(ChartSeries::points(&compared.run.runId, points));
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()?;
+
+    let chartPath = format!("histogram-{}.svg", distribution);
+    displayLogLogChart(&format!("{}/{}", folder_path, chartPath), ChartFormat::Svg, &format!("Avalanche {}", distribution), distribution, "fraction of avalanches", &series)?;
+    registerOutput(folder_path, &chartPath, "svg", &format!("Log-log chart of the avalanche {} histograms of the compared runs", distribution), None);
+
+    Ok(())
+}
+
+/**
+ * Write the power-law exponent fitted to each distribution of each run with its confidence interval
+ */
+fn displayExponents(runs: &[ComparedRun], folder_path: &str) -> io::Result<()> {
+    let schema = CsvSchema::new("exponents", "exponents.csv", "Power-law exponent fitted to each distribution of each compared run", vec![
+        CsvColumn::new("run_id", "string", "", "Run the fit belongs to"),
+        CsvColumn::new("distribution", "string", "", "movement, grains or magnitude"),
+        CsvColumn::new("alpha", "float", "", "Fitted power-law exponent"),
+        CsvColumn::new("alpha_error", "float", "", "Standard error of the exponent"),
+        CsvColumn::new("ci_low", "float", "", "Lower end of the confidence interval of the exponent"),
+        CsvColumn::new("ci_high", "float", "", "Upper end of the confidence interval of the exponent"),
+        CsvColumn::new("x_min", "integer", "", "Start of the fitted tail"),
+        CsvColumn::new("tail_count", "integer", "avalanches", "Avalanches in the fitted tail"),
+        CsvColumn::new("ks_distance", "float", "", "Kolmogorov-Smirnov distance between the tail and the fit"),
+    ]);
+    let mut writer = CsvWriter::create(folder_path, "exponents.csv", &schema)?;
+
+    for compared in runs {
+        for ((distribution, _), fit) in RUN_DISTRIBUTIONS.iter().zip(&compared.fits) {
+            let Some(fit) = fit else {
+                continue;
+            };
+            writer.writeRow(&[
+                compared.run.runId.clone(),
+                distribution.to_string(),
+                fit.alpha.to_string(),
+                fit.alphaError.to_string(),
+                (fit.alpha - POWER_LAW_CONFIDENCE_Z * fit.alphaError).to_string(),
+                (fit.alpha + POWER_LAW_CONFIDENCE_Z * fit.alphaError).to_string(),
+                fit.xMin.to_string(),
+                fit.tailCount.to_string(),
+                fit.ksDistance.to_string(),
+            ])?;
+        }
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()
+}
+
+/**
+ * Two-sample KS test of each distribution between every pair of runs
+ */
+fn displayKsTests(runs: &[ComparedRun], folder_path: &str) -> io::Result<Vec<(String, usize, usize, KsTestResult)>> {
+    let schema = CsvSchema::new("ks-tests", "ks-tests.csv", "Two-sample Kolmogorov-Smirnov test of each distribution between every pair of compared runs", vec![
+        CsvColumn::new("distribution", "string", "", "movement, grains or magnitude"),
+        CsvColumn::new("run_a", "string", "", "First run of the pair"),
+        CsvColumn::new("run_b", "string", "", "Second run of the pair"),
+        CsvColumn::new("statistic", "float", "", "Largest distance between the cumulative distributions of the two runs"),
+        CsvColumn::new("p_value", "float", "", "Asymptotic probability of the statistic if both runs have the same distribution"),
+        CsvColumn::new("count_a", "integer", "avalanches", "Avalanches in the first run"),
+        CsvColumn::new("count_b", "integer", "avalanches", "Avalanches in the second run"),
+    ]);
+    let mut writer = CsvWriter::create(folder_path, "ks-tests.csv", &schema)?;
+
+    let mut results = Vec::new();
+    for (d, (distribution, _)) in RUN_DISTRIBUTIONS.iter().enumerate() {
+        for a in 0..runs.len() {
+            for b in a + 1..runs.len() {
+                let Some(result) = twoSampleKsTest(&runs[a].histograms[d], &runs[b].histograms[d]) else {
+                    continue;
+                };
+                writer.writeRow(&[
+                    distribution.to_string(),
+                    runs[a].run.runId.clone(),
+                    runs[b].run.runId.clone(),
+                    result.statistic.to_string(),
+                    result.pValue.to_string(),
+                    result.countA.to_string(),
+                    result.countB.to_string(),
+                ])?;
+                results.push((distribution.to_string(), a, b, result));
+            }
+        }
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()?;
+
+    Ok(results)
+}
+
+/**
+ * Write every configuration constant that differs between the runs with its value in each run
+ */
+fn displayConfigDiff(runs: &[ComparedRun], folder_path: &str) -> io::Result<Vec<String>> {
+    let keys: BTreeSet<String> = runs.iter().flat_map(|compared| compared.run.config.keys().cloned()).collect();
+    let differing: Vec<String> = keys.into_iter()
+        .filter(|key| {
+            let values: BTreeSet<String> = runs.iter().map(|compared| formatConfig(compared.run.getConfig(key))).collect();
+            values.len() > 1
+        })
+        .collect();
+
+    let schema = CsvSchema::new("config-diff", "config-diff.csv", "Configuration constants that differ between the compared runs", vec![
+        CsvColumn::new("key", "string", "", "Name of the configuration constant"),
+        CsvColumn::new("run_id", "string", "", "Run the value belongs to"),
+        CsvColumn::new("value", "float", "", "Value of the constant in the run, empty if the run did not record it"),
+    ]);
+    let mut writer = CsvWriter::create(folder_path, "config-diff.csv", &schema)?;
+
+    for key in &differing {
+        for compared in runs {
+            writer.writeRow(&[key.clone(), compared.run.runId.clone(), compared.run.getConfig(key).map(|value| value.to_string()).unwrap_or_default()])?;
+        }
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()?;
+
+    Ok(differing)
+}
+
+/**
+ * Human readable summary of the comparison
+ */
+fn displayCompareReport(runs: &[ComparedRun], ksTests: &[(String, usize, usize, KsTestResult)], configDiff: &[String], folder_path: &str) -> io::Result<()> {
+    // Create a file and wrap it in a BufWriter for efficient writing
+    let file = File::create(format!("{}/compare-report.txt", folder_path))?;
+    let mut writer = BufWriter::new(file);
+    registerOutput(folder_path, "compare-report.txt", "text", "Human readable summary of the comparison", None);
+
+    writeln!( writer, "Run comparison")?;
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    for (i, compared) in runs.iter().enumerate() {
+        writeln!( writer, "Run {}: {} (configuration from {})", i + 1, compared.run.path.display(), compared.run.configSource)?;
+    }
+
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Configuration differences")?;
+    if configDiff.is_empty() {
+        writeln!( writer, "  none")?;
+    }
+    for key in configDiff {
+        let values: Vec<String> = runs.iter().enumerate()
+            .map(|(i, compared)| format!("run {}={}", i + 1, formatConfig(compared.run.getConfig(key))))
+            .collect();
+        writeln!( writer, "  {}: {}", key, values.join(", "))?;
+    }
+
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Fitted exponents (confidence interval at z={})", POWER_LAW_CONFIDENCE_Z)?;
+    for (d, (distribution, _)) in RUN_DISTRIBUTIONS.iter().enumerate() {
+        writeln!( writer, "  {}", distribution)?;
+        for (i, compared) in runs.iter().enumerate() {
+            match &compared.fits[d] {
+                Some(fit) => writeln!( writer, "    run {}: alpha {:.3} ± {:.3} [{:.3}, {:.3}], x min {}, tail {} avalanches, KS distance {:.4}", i + 1,
+                    fit.alpha, fit.alphaError, fit.alpha - POWER_LAW_CONFIDENCE_Z * fit.alphaError, fit.alpha + POWER_LAW_CONFIDENCE_Z * fit.alphaError,
+                    fit.xMin, fit.tailCount, fit.ksDistance)?,
+                None => writeln!( writer, "    run {}: no fit", i + 1)?,
+            }
+        }
+    }
+
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Two-sample KS tests")?;
+    for (distribution, a, b, result) in ksTests {
+        writeln!( writer, "  {} run {} vs run {}: D = {:.4}, p = {:.3e}", distribution, a + 1, b + 1, result.statistic, result.pValue)?;
+    }
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+
+    // flush the writer to ensure all data is written to the file
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn formatConfig(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use chrono::Local;
+use serde_json::Value;
+
+// internal models
+use crate::analysis::powerLaw::{PowerLawFit, fitPowerLaw};
+use crate::commands::compare;
+use crate::util::paramDistribution::{ControlKind, CONTROL_VARIABLE};
+use crate::util::runFolder::{RUN_DISTRIBUTIONS, RunFolder};
+use crate::util::runManifest::RUN_MANIFEST_FILE;
+use crate::util::runOutput::{registerOutput, displayRunLayout};
+
+// constants
+use crate::util::constants::{configurationEntries, POWER_LAW_CONFIDENCE_Z};
+
+
+pub const CONTROL_USAGE: &str = "control [<run>] [--kind exponential|constant] [--output <folder>]
+    Rerun the configuration in util/constants.rs with every power-law distribution replaced by an
+    exponential with the same variance (default) or a constant at its mean, then compare the avalanche
+    exponents with the original run, given as a run folder or run id in ./data or run first when not given,
+    a given run must have been run with the configuration of this build,
+    and write the comparison with a control report to the output folder (default ./reports/control-<timestamp>)";
+
+/**
+ * Run the control run of the command line and report whether the avalanche exponents survive
+ * without the power-law inputs
+ */
+pub fn runControl(args: &[String]) -> io::Result<()> {
+    let mut kind = ControlKind::Exponential;
+    let mut outputPath: Option<String> = None;
+    let mut runPath: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--kind" => kind = args.next().and_then(|kind| ControlKind::parse(kind))
+                .ok_or_else(|| invalidInput("--kind needs exponential or constant"))?,
+            "--output" => outputPath = Some(args.next().ok_or_else(|| invalidInput("--output needs a folder"))?.clone()),
+            _ => runPath = Some(arg.clone()),
+        }
+    }
+
+    let originalPath = match runPath {
+        Some(runPath) => {
+            // run ids are looked up in the data folder
+            let path = if Path::new(&runPath).is_dir() { Path::new(&runPath).to_path_buf() } else { Path::new("./data").join(&runPath) };
+            if !path.is_dir() {
+                return Err(invalidInput(&format!("{} is not a run folder", runPath)));
+            }
+            // the control is run with this build, so the original must have been run with the same configuration
+            let differences = configurationDifferences(&RunFolder::load(&path)?);
+            if !differences.is_empty() {
+                return Err(invalidInput(&format!("{} was not run with the configuration of this build, {}", runPath, differences.join(", "))));
+            }
+            path
+        },
+        None => {
+            println!("Running the original configuration");
+            runSimulation(None)?
+        },
+    };
+
+    println!("Running the {} control", kind.name());
+    let controlPath = runSimulation(Some(kind))?;
+
+    let outputPath = outputPath.unwrap_or_else(|| format!("./reports/control-{}", Local::now().format("%Y-%m-%d_%H-%M-%S")));
+    compare::runCompare(&[
+        originalPath.to_string_lossy().to_string(),
+        controlPath.to_string_lossy().to_string(),
+        "--output".to_string(),
+        outputPath.clone(),
+    ])?;
+
+    let original = RunFolder::load(&originalPath)?;
+    let control = RunFolder::load(&controlPath)?;
+    displayControlReport(&original, &control, kind, &outputPath)?;
+    displayRunLayout(outputPath.clone())?;
+
+    println!("Control report written to {}/control-report.txt", outputPath);
+
+    Ok(())
+}
+
+/**
+ * Configuration constants of this build that differ from those in the manifest of the run, one line each,
+ * a run without a manifest can not be checked and is reported as a difference
+ */
+fn configurationDifferences(run: &RunFolder) -> Vec<String> {
+    if run.configSource != "manifest" {
+        return vec![format!("it has no {} to check the configuration against", RUN_MANIFEST_FILE)];
+    }
+    configurationEntries().into_iter().filter_map(|(name, value)| match run.getConfig(&name) {
+        Some(runValue) if runValue == value => None,
+        Some(runValue) => Some(format!("{} is {} in the run and {} in this build", name, runValue, value)),
+        None => Some(format!("{} is not in the run and {} in this build", name, value)),
+    }).collect()
+}
+
+/**
+ * Run the simulation of this build as a child process, as a control run of the kind when given,
+ * and return the run folder it wrote to ./data
+ */
+fn runSimulation(control: Option<ControlKind>) -> io::Result<PathBuf> {
+    let before = runFolders()?;
+
+    let mut command = Command::new(env::current_exe()?);
+    match control {
+        Some(kind) => command.env(CONTROL_VARIABLE, kind.name()),
+        None => command.env_remove(CONTROL_VARIABLE),
+    };
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("the simulation failed with {}", status)));
+    }
+
+    runFolders()?.difference(&before).next().cloned()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the simulation did not write a run folder to ./data"))
+}
+
+fn runFolders() -> io::Result<BTreeSet<PathBuf>> {
+    let mut folders = BTreeSet::new();
+    if !Path::new("./data").is_dir() {
+        return Ok(folders);
+    }
+    for entry in fs::read_dir("./data")? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            folders.insert(entry.path());
+        }
+    }
+    Ok(folders)
+}
+
+/**
+ * Write the distributions that were replaced and, for each avalanche distribution, the exponents
+ * of the original and the control run with whether their confidence intervals overlap
+ */
+fn displayControlReport(original: &RunFolder, control: &RunFolder, kind: ControlKind, folder_path: &str) -> io::Result<()> {
+    // Create a file and wrap it in a BufWriter for efficient writing
+    let file = File::create(format!("{}/control-report.txt", folder_path))?;
+    let mut writer = BufWriter::new(file);
+    registerOutput(folder_path, "control-report.txt", "text", "Avalanche exponents of the original run against the run with the power-law distributions replaced", None);
+
+    writeln!( writer, "Control run ({})", kind.name())?;
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Original: {}", original.path.display())?;
+    writeln!( writer, "Control: {}", control.path.display())?;
+
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Replaced distributions")?;
+    let replacements = readReplacements(control);
+    if replacements.is_empty() {
+        writeln!( writer, "  none, the configuration has no power-law distributions")?;
+    }
+    for replacement in replacements {
+        writeln!( writer, "  {}", replacement)?;
+    }
+
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    writeln!( writer, "Fitted exponents (confidence interval at z={})", POWER_LAW_CONFIDENCE_Z)?;
+    let mut differing = Vec::new();
+    for (distribution, _) in RUN_DISTRIBUTIONS {
+        let originalFit = fitPowerLaw(&original.readDistribution(distribution).unwrap_or_default());
+        let controlFit = fitPowerLaw(&control.readDistribution(distribution).unwrap_or_default());
+        match (originalFit, controlFit) {
+            (Some(originalFit), Some(controlFit)) => {
+                let difference = controlFit.alpha - originalFit.alpha;
+                let consistent = intervalsOverlap(&originalFit, &controlFit);
+                writeln!( writer, "  {}: original alpha {:.3} ± {:.3}, control alpha {:.3} ± {:.3}, difference {:+.3}, {}", distribution,
+                    originalFit.alpha, originalFit.alphaError, controlFit.alpha, controlFit.alphaError, difference,
+                    if consistent { "consistent" } else { "different" })?;
+                if !consistent {
+                    differing.push(distribution);
+                }
+            },
+            (originalFit, controlFit) => {
+                writeln!( writer, "  {}: original {}, control {}", distribution, describeFit(&originalFit), describeFit(&controlFit))?;
+                // a power law that appears or disappears in the control is a change
+                if originalFit.is_some() != controlFit.is_some() {
+                    differing.push(distribution);
+                }
+            },
+        }
+    }
+
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+    if differing.is_empty() {
+        writeln!( writer, "The exponents survive the control, the power-law distributions are not needed to produce them")?;
+    } else {
+        writeln!( writer, "The exponents of {} change in the control, they depend on the power-law distributions", differing.join(", "))?;
+    }
+    writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
+
+    // flush the writer to ensure all data is written to the file
+    writer.flush()?;
+
+    Ok(())
+}
+
+/**
+ * Replacements recorded in the manifest of the control run, one line each
+ */
+fn readReplacements(control: &RunFolder) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(control.path.join(RUN_MANIFEST_FILE)) else {
+        return Vec::new();
+    };
+    let Ok(manifest) = serde_json::from_str::<Value>(&contents) else {
+        return Vec::new();
+    };
+
+    manifest["control"]["replacements"].as_array().map(|replacements| replacements.iter().map(|replacement| format!(
+        "{}: {} replaced by {} (mean {:.4}, variance {:.4})",
+        replacement["name"].as_str().unwrap_or("-"),
+        replacement["original"].as_str().unwrap_or("-"),
+        replacement["replacement"].as_str().unwrap_or("-"),
+        replacement["mean"].as_f64().unwrap_or(f64::NAN),
+        replacement["variance"].as_f64().unwrap_or(f64::NAN),
+    )).collect()).unwrap_or_default()
+}
+
+fn intervalsOverlap(a: &PowerLawFit, b: &PowerLawFit) -> bool {
+    (a.alpha - b.alpha).abs() <= POWER_LAW_CONFIDENCE_Z * (a.alphaError + b.alphaError)
+}
+
+fn describeFit(fit: &Option<PowerLawFit>) -> String {
+    match fit {
+        Some(fit) => format!("alpha {:.3} ± {:.3}", fit.alpha, fit.alphaError),
+        None => "no fit".to_string(),
+    }
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
@@ -0,0 +1,159 @@
+use std::io;
+use std::path::Path;
+
+// internal models
+use crate::analysis::branching::criticalityRegime;
+use crate::util::runFolder::RunFolder;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+
+pub const CRITICALITY_USAGE: &str = "criticality <parameter> [--data <folder>]
+    Show the mean branching ratio sigma of the runs in the data folder (default ./data) as a function of a
+    configuration constant, e.g. ALPHA_EXTRA_ENERGY, and find the value where sigma crosses 1 between the
+    runs either side of it, keep the runs of one sweep in their own data folder
+    runs with the same value are averaged and the table is written to <data folder>/criticality-<parameter>.csv";
+
+/**
+ * Runs with the same value of the swept parameter and their mean branching ratio
+ */
+struct SweepPoint {
+    value: f64,
+    runs: usize,
+    sigma: f64,
+    sigmaError: f64,
+}
+
+/**
+ * Locate the value of a parameter where the branching ratio of the runs in the data folder is 1
+ */
+pub fn runCriticality(args: &[String]) -> io::Result<()> {
+    let mut dataPath = "./data".to_string();
+    let mut parameter: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data" => dataPath = args.next().ok_or_else(|| invalidInput("--data needs a folder"))?.clone(),
+            _ => parameter = Some(arg.to_uppercase()),
+        }
+    }
+    let parameter = parameter.ok_or_else(|| invalidInput("criticality needs a parameter"))?;
+
+    // every run with a branching ratio and a value for the parameter, ordered by the value
+    let mut estimates: Vec<(f64, f64, f64)> = RunFolder::scan(Path::new(&dataPath))?.iter()
+        .filter_map(|run| Some((run.getConfig(&parameter)?, run.branchingRatio?.0, run.branchingRatio?.1)))
+        .collect();
+    if estimates.is_empty() {
+        return Err(invalidInput(&format!("no runs in {} have a branching ratio and {}", dataPath, parameter)));
+    }
+    estimates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let points = sweepPoints(&estimates);
+
+    let relativePath = format!("criticality-{}.csv", parameter);
+    let schema = CsvSchema::new(&format!("criticality-{}", parameter), &relativePath, &format!("Mean branching ratio of the runs for each value of {}", parameter), vec![
+        CsvColumn::new(&parameter.to_lowercase(), "float", "", "Value of the parameter"),
+        CsvColumn::new("runs", "integer", "runs", "Runs with the value"),
+        CsvColumn::new("sigma", "float", "grains per grain", "Mean branching ratio of the runs"),
+        CsvColumn::new("sigma_error", "float", "grains per grain", "Standard error of the mean branching ratio"),
+        CsvColumn::new("regime", "string", "", "sub-critical, critical or super-critical"),
+    ]);
+    let mut writer = CsvWriter::create(&dataPath, &relativePath, &schema)?;
+
+    let width = parameter.len().max(8);
+    println!("{:<width$}  {:>4}  {:<18}  regime", parameter, "runs", "sigma", width = width);
+    for point in &points {
+        let regime = criticalityRegime(point.sigma, point.sigmaError);
+        println!("{:<width$}  {:>4}  {:<18}  {}", point.value, point.runs, format!("{:.4} ± {:.4}", point.sigma, point.sigmaError), regime, width = width);
+        writer.writeRow(&[point.value.to_string(), point.runs.to_string(), point.sigma.to_string(), point.sigmaError.to_string(), regime.to_string()])?;
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()?;
+
+    let crossings = criticalCrossings(&points);
+    if crossings.is_empty() {
+        let closest = points.iter().min_by(|a, b| (a.sigma - 1.0).abs().total_cmp(&(b.sigma - 1.0).abs())).unwrap();
+        println!("sigma does not cross 1, closest at {} = {} with sigma {:.4}", parameter, closest.value, closest.sigma);
+    }
+    for value in crossings {
+        println!("sigma crosses 1 at {} ≈ {:.4}", parameter, value);
+    }
+
+    Ok(())
+}
+
+/**
+ * Average the (value, sigma, sigma error) estimates of the runs with the same value, the estimates are ordered by value
+ */
+fn sweepPoints(estimates: &[(f64, f64, f64)]) -> Vec<SweepPoint> {
+    let mut points: Vec<SweepPoint> = Vec::new();
+    for &(value, sigma, sigmaError) in estimates {
+        match points.last_mut() {
+            Some(point) if point.value == value => {
+                // running mean of sigma, the errors are combined as independent estimates
+                let runs = point.runs as f64;
+                point.sigma = (point.sigma * runs + sigma) / (runs + 1.0);
+                point.sigmaError = ((point.sigmaError * runs).powi(2) + sigmaError.powi(2)).sqrt() / (runs + 1.0);
+                point.runs += 1;
+            },
+            _ => points.push(SweepPoint { value, runs: 1, sigma, sigmaError }),
+        }
+    }
+    points
+}
+
+/**
+ * Values of the parameter where sigma crosses 1, interpolated linearly between neighboring points
+ */
+fn criticalCrossings(points: &[SweepPoint]) -> Vec<f64> {
+    let mut crossings = Vec::new();
+    for pair in points.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if a.sigma == 1.0 {
+            crossings.push(a.value);
+        } else if (a.sigma - 1.0) * (b.sigma - 1.0) < 0.0 {
+            crossings.push(a.value + (1.0 - a.sigma) * (b.value - a.value) / (b.sigma - a.sigma));
+        }
+    }
+    if points.last().is_some_and(|point| point.sigma == 1.0) {
+        crossings.push(points[points.len() - 1].value);
+    }
+    crossings
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(value: f64, sigma: f64) -> SweepPoint {
+        SweepPoint { value, runs: 1, sigma, sigmaError: 0.0 }
+    }
+
+    #[test]
+    fn sweepPointsAverageRunsWithTheSameValue() {
+        let points = sweepPoints(&[(1.0, 0.9, 0.3), (1.0, 1.0, 0.4), (1.0, 1.1, 1.2), (2.0, 1.5, 0.1)]);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].runs, 3);
+        assert!((points[0].sigma - 1.0).abs() < 1e-12);
+        // the standard error of the mean of independent estimates, sqrt(0.3^2 + 0.4^2 + 1.2^2) / 3
+        assert!((points[0].sigmaError - 1.3 / 3.0).abs() < 1e-12);
+        assert_eq!((points[1].runs, points[1].sigma, points[1].sigmaError), (1, 1.5, 0.1));
+    }
+
+    #[test]
+    fn criticalCrossingsInterpolateLinearly() {
+        let crossings = criticalCrossings(&[point(0.0, 0.8), point(1.0, 1.2), point(2.0, 1.1), point(4.0, 0.6)]);
+        assert_eq!(crossings.len(), 2);
+        assert!((crossings[0] - 0.5).abs() < 1e-12);
+        assert!((crossings[1] - 2.4).abs() < 1e-12);
+
+        // points exactly at 1 are crossings themselves, including the last
+        assert_eq!(criticalCrossings(&[point(1.0, 1.0), point(2.0, 1.3), point(3.0, 1.0)]), vec![1.0, 3.0]);
+        assert!(criticalCrossings(&[point(1.0, 0.5), point(2.0, 0.7)]).is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+pub mod catalogue;
+pub mod compare;
+pub mod control;
+pub mod criticality;
+pub mod plot;
+pub mod predictability;
+pub mod replay;
+
+use std::io;
+
+
+/**
+ * Run a command for working with finished runs, args are the command line after the program name
+ */
+pub fn runCommand(args: &[String]) -> io::Result<()> {
+    match args[0].as_str() {
+        "catalogue" => catalogue::runCatalogue(&args[1..]),
+        "compare" => compare::runCompare(&args[1..]),
+        "control" => control::runControl(&args[1..]),
+        "criticality" => criticality::runCriticality(&args[1..]),
+        "plot" => plot::runPlot(&args[1..]),
+        "predictability" => predictability::runPredictability(&args[1..]),
+        "replay" => replay::runReplay(&args[1..]),
+        _ => {
+            displayUsage();
+            Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown command {}", args[0])))
+        }
+    }
+}
+
+/**
+ * Print the commands and their arguments
+ */
+pub fn displayUsage() {
+    println!("usage: sandpile-discrete [command]");
+    println!("  with no command the sandpile is run with the configuration in util/constants.rs");
+    println!("  with --resume <pile-state.bin> the run carries on from the pile state written at the end of an earlier run");
+    println!();
+    println!("{}", catalogue::CATALOGUE_USAGE);
+    println!();
+    println!("{}", compare::COMPARE_USAGE);
+    println!();
+    println!("{}", control::CONTROL_USAGE);
+    println!();
+    println!("{}", criticality::CRITICALITY_USAGE);
+    println!();
+    println!("{}", plot::PLOT_USAGE);
+    println!();
+    println!("{}", predictability::PREDICTABILITY_USAGE);
+    println!();
+    println!("{}", replay::REPLAY_USAGE);
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+// internal models
+use crate::analysis::distributions::{AVALANCHE_MEASURES, AvalancheSeries, ccdf, logBinnedPdf};
+use crate::analysis::powerLaw::{PowerLawFit, fitPowerLaw, powerLawCcdf};
+use crate::util::chart::{ChartFormat, ChartSeries, displayHeatMap, displayLineChart, displayLogLogChart};
+use crate::util::runFolder::{RUN_DISTRIBUTIONS, RunFolder};
+
+// constants
+use crate::util::constants::LOG_BINS_PER_DECADE;
+
+
+pub const PLOT_USAGE: &str = "plot <run> [--format svg|png|both]
+    Draw the charts of a run, given as a run folder or run id in ./data, to <run>/plots (default svg)
+    log-log probability densities and CCDFs of the avalanche sizes with the fitted power law,
+    the height map of the pile and the avalanche sizes in the order the grains were dropped";
+
+/**
+ * Draw the charts of the run given on the command line
+ */
+pub fn runPlot(args: &[String]) -> io::Result<()> {
+    let mut formats = vec![ChartFormat::Svg];
+    let mut runPath: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => formats = match args.next().map(|format| format.as_str()) {
+                Some("svg") => vec![ChartFormat::Svg],
+                Some("png") => vec![ChartFormat::Png],
+                Some("both") => vec![ChartFormat::Svg, ChartFormat::Png],
+                _ => return Err(invalidInput("--format needs svg, png or both")),
+            },
+            _ => runPath = Some(arg.clone()),
+        }
+    }
+    let runPath = runPath.ok_or_else(|| invalidInput("plot needs a run"))?;
+
+    // run ids are looked up in the data folder
+    let path = if Path::new(&runPath).is_dir() { Path::new(&runPath).to_path_buf() } else { Path::new("./data").join(&runPath) };
+    if !path.is_dir() {
+        return Err(invalidInput(&format!("{} is not a run folder", runPath)));
+    }
+    let run = RunFolder::load(&path)?;
+
+    let plotFolder = path.join("plots");
+    fs::create_dir_all(&plotFolder)?;
+    let plotPath = |name: &str, format: ChartFormat| plotFolder.join(format!("{}.{}", name, format.extension())).to_string_lossy().to_string();
+
+    let series = run.readAvalancheSeries()?;
+    let mut written = 0;
+
+    for (name, unit, _) in AVALANCHE_MEASURES {
+        let Some(histogram) = measureHistogram(&run, &series, name)? else {
+            continue;
+        };
+        let (pdfSeries, ccdfSeries) = distributionSeries(&histogram);
+
+        for &format in &formats {
+            displayLogLogChart(&plotPath(&format!("pdf-{}", name), format), format, &format!("Avalanche {} probability density", name),
+                unit, "probability density", &pdfSeries)?;
+            displayLogLogChart(&plotPath(&format!("ccdf-{}", name), format), format, &format!("Avalanche {} CCDF", name),
+                unit, "P(size >= x)", &ccdfSeries)?;
+            written += 2;
+        }
+    }
+
+    if let Some(grid) = readHeightMap(&path.join("height-map.csv"))? {
+        for &format in &formats {
+            displayHeatMap(&plotPath("height-map", format), format, "Pile height map", "height", &grid)?;
+            written += 1;
+        }
+    }
+
+    if let Some(series) = &series {
+        let grainIndexes = series.iter().find(|(name, _)| name == "grain_index").map(|(_, values)| values.clone()).unwrap_or_default();
+        for (name, unit, _) in AVALANCHE_MEASURES {
+            let Some((_, values)) = series.iter().find(|(column, _)| column == name) else {
+                continue;
+            };
+            let points: Vec<(f64, f64)> = grainIndexes.iter().zip(values).map(|(&i, &value)| (i as f64, value as f64)).collect();
+            for &format in &formats {
+                displayLineChart(&plotPath(&format!("series-{}", name), format), format, &format!("Avalanche {} by grain", name),
+                    "grain index", unit, &[ChartSeries::line(name, points.clone())])?;
+                written += 1;
+            }
+        }
+    }
+
+    println!("{} charts written to {}", written, plotFolder.display());
+
+    Ok(())
+}
+
+/**
+ * Histogram of an avalanche measure, from the avalanche series when the run has one and from the
+ * histogram csv for older runs, None if the run did not record the measure
+ */
+fn measureHistogram(run: &RunFolder, series: &Option<AvalancheSeries>, name: &str) -> io::Result<Option<Vec<(u64, u64)>>> {
+    if let Some(series) = series {
+        let Some((_, values)) = series.iter().find(|(column, _)| column == name) else {
+            return Ok(None);
+        };
+        let mut histogram: BTreeMap<u64, u64> = BTreeMap::new();
+        for &value in values {
+            *histogram.entry(value).or_insert(0) += 1;
+        }
+        return Ok(Some(histogram.into_iter().collect()));
+    }
+
+    if RUN_DISTRIBUTIONS.iter().any(|(distribution, _)| *distribution == name) {
+        return run.readDistribution(name).map(Some);
+    }
+    Ok(None)
+}
+
+/**
+ * Log-binned density and CCDF of a histogram, each with the power law fitted to its tail drawn as a line
+ * both are normalized over the positive sizes so the fit is scaled by the fraction of sizes in the tail
+ */
+fn distributionSeries(histogram: &[(u64, u64)]) -> (Vec<ChartSeries>, Vec<ChartSeries>) {
+    let pdf: Vec<(f64, f64)> = logBinnedPdf(histogram, LOG_BINS_PER_DECADE).iter().map(|bin| (bin.center, bin.density)).collect();
+    let ccdfPoints: Vec<(u64, f64)> = ccdf(histogram);
+
+    let mut pdfSeries = vec![ChartSeries::points("data", pdf)];
+    let mut ccdfSeries = vec![ChartSeries::points("data", ccdfPoints.iter().map(|&(x, p)| (x as f64, p)).collect())];
+
+    if let Some(fit) = fitPowerLaw(histogram) {
+        let positive: u64 = histogram.iter().filter(|&&(value, _)| value > 0).map(|&(_, count)| count).sum();
+        let tailFraction = fit.tailCount as f64 / positive as f64;
+        let largest = ccdfPoints.last().map(|&(x, _)| x).unwrap_or(fit.xMin);
+        let name = fitName(&fit);
+
+        let xs = logSpaced(fit.xMin as f64, largest as f64);
+        pdfSeries.push(ChartSeries::line(&name, xs.iter()
+            .map(|&x| (x, tailFraction * (powerLawCcdf(&fit, x) - powerLawCcdf(&fit, x + 1.0))))
+            .collect()));
+        ccdfSeries.push(ChartSeries::line(&name, xs.iter()
+            .map(|&x| (x, tailFraction * powerLawCcdf(&fit, x)))
+            .collect()));
+    }
+
+    (pdfSeries, ccdfSeries)
+}
+
+fn fitName(fit: &PowerLawFit) -> String {
+    format!("power law alpha {:.3} ± {:.3}, x min {}", fit.alpha, fit.alphaError, fit.xMin)
+}
+
+/**
+ * Points spread evenly on a log scale from start to end for drawing a fitted line
+ */
+fn logSpaced(start: f64, end: f64) -> Vec<f64> {
+    let count = 100;
+    let ratio = (end.max(start) / start).powf(1.0 / count as f64);
+    (0..=count).map(|i| start * ratio.powi(i)).collect()
+}
+
+/**
+ * Read height-map.csv into a grid of heights by x then y, None if the run has no height map
+ */
+fn readHeightMap(path: &Path) -> io::Result<Option<Vec<Vec<f64>>>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let mut cells = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines().skip(1) {
+        let line = line?;
+        let values: Vec<f64> = line.split(',').filter_map(|value| value.trim().parse::<f64>().ok()).collect();
+        if let [x, y, height] = values[..] {
+            cells.push((x as usize, y as usize, height));
+        }
+    }
+
+    let xSize = cells.iter().map(|&(x, _, _)| x + 1).max().unwrap_or(0);
+    let ySize = cells.iter().map(|&(_, y, _)| y + 1).max().unwrap_or(0);
+    let mut grid = vec![vec![0.0; ySize]; xSize];
+    for (x, y, height) in cells {
+        grid[x][y] = height;
+    }
+    Ok(Some(grid))
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
@@ -0,0 +1,126 @@
+use std::io;
+use std::path::Path;
+
+// internal models
+use crate::analysis::distributions::AVALANCHE_MEASURES;
+use crate::analysis::predictability::{PRE_DROP_PREDICTORS, PRE_DROP_STATE_FILE, largeAvalancheAuc, linearPrediction, pearsonCorrelation, spearmanCorrelation};
+use crate::util::runFolder::RunFolder;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+// constants
+use crate::util::constants::{PREDICTABILITY_LARGE_QUANTILE, PREDICTABILITY_TRAINING_FRACTION};
+
+
+pub const PREDICTABILITY_USAGE: &str = "predictability <run> [--measure <name>] [--from <grain index>] [--quantile <q>]
+    Correlate the pile state recorded before each drop of a run, given as a run folder or run id in ./data,
+    with the size of the avalanche it started, by default the movement of the avalanches from grain 0
+    each predictor gets its Pearson and Spearman correlation with the size and its ROC AUC for picking out
+    the large avalanches, those at or above the quantile of the size (default PREDICTABILITY_LARGE_QUANTILE),
+    the predictability score is the AUC of all the predictors combined in a linear fit of log(1 + size),
+    fitted on the first PREDICTABILITY_TRAINING_FRACTION of the drops and scored on the drops after them,
+    0.5 is no better than chance, the table is written to <run>/predictability.csv
+    the pile state is recorded when PRE_DROP_INTERVAL is set";
+
+/**
+ * Correlations and score of the pile state before each drop against the avalanche size for the run given on the command line
+ */
+pub fn runPredictability(args: &[String]) -> io::Result<()> {
+    let mut measure = "movement".to_string();
+    let mut fromGrain = 0.0;
+    let mut quantile = PREDICTABILITY_LARGE_QUANTILE;
+    let mut runPath: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--measure" => measure = args.next().ok_or_else(|| invalidInput("--measure needs an avalanche measure"))?.clone(),
+            "--from" => fromGrain = args.next().and_then(|index| index.parse().ok())
+                .ok_or_else(|| invalidInput("--from needs a grain index"))?,
+            "--quantile" => quantile = args.next().and_then(|quantile| quantile.parse().ok()).filter(|quantile| (0.0..1.0).contains(quantile))
+                .ok_or_else(|| invalidInput("--quantile needs a number from 0 to 1"))?,
+            _ => runPath = Some(arg.clone()),
+        }
+    }
+    let runPath = runPath.ok_or_else(|| invalidInput("predictability needs a run"))?;
+    if !AVALANCHE_MEASURES.iter().any(|(name, _, _)| *name == measure) {
+        return Err(invalidInput(&format!("unknown avalanche measure {}", measure)));
+    }
+
+    // run ids are looked up in the data folder
+    let path = if Path::new(&runPath).is_dir() { Path::new(&runPath).to_path_buf() } else { Path::new("./data").join(&runPath) };
+    if !path.is_dir() {
+        return Err(invalidInput(&format!("{} is not a run folder", runPath)));
+    }
+    let run = RunFolder::load(&path)?;
+    let series = run.readPreDropState()?
+        .ok_or_else(|| invalidInput(&format!("{} has no {}, set PRE_DROP_INTERVAL to record the pile state", runPath, PRE_DROP_STATE_FILE)))?;
+
+    // only the drops from the starting grain, e.g. once the pile became stationary
+    let column = |name: &str| series.iter().find(|(column, _)| column == name).map(|(_, values)| values);
+    let grainIndexes = column("grain_index").ok_or_else(|| invalidInput("pre-drop state has no grain_index"))?;
+    let rows: Vec<usize> = (0..grainIndexes.len()).filter(|&row| grainIndexes[row] >= fromGrain).collect();
+    let select = |values: &Vec<f64>| -> Vec<f64> { rows.iter().map(|&row| values[row]).collect() };
+
+    let sizes = select(column(&measure).ok_or_else(|| invalidInput(&format!("pre-drop state has no {}", measure)))?);
+    if sizes.len() < 2 {
+        return Err(invalidInput(&format!("{} has fewer than two drops from grain {}", runPath, fromGrain)));
+    }
+    let mut sorted = sizes.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let largeSize = sorted[((sorted.len() - 1) as f64 * quantile).round() as usize];
+    let large: Vec<bool> = sizes.iter().map(|&size| size >= largeSize).collect();
+
+    let mut predictors: Vec<(String, Vec<f64>)> = Vec::new();
+    for name in PRE_DROP_PREDICTORS {
+        if let Some(values) = column(name) {
+            predictors.push((name.to_string(), select(values)));
+        }
+    }
+    // the sizes are heavy tailed so the combined fit is made on the log of the size, on the earlier
+    // drops, and is only scored on the later drops it was not fitted to
+    let logSizes: Vec<f64> = sizes.iter().map(|size| size.ln_1p()).collect();
+    let trainingRows = ((sizes.len() as f64 * PREDICTABILITY_TRAINING_FRACTION) as usize).clamp(1, sizes.len() - 1);
+    let fitted = linearPrediction(&predictors.iter().map(|(_, values)| values.as_slice()).collect::<Vec<_>>(), &logSizes, trainingRows);
+    let combined = fitted[trainingRows..].to_vec();
+    let (heldOutSizes, heldOutLarge) = (&sizes[trainingRows..], &large[trainingRows..]);
+    let explained = pearsonCorrelation(&combined, &logSizes[trainingRows..]).map(|correlation| correlation * correlation).unwrap_or(0.0);
+
+    let schema = CsvSchema::new("predictability", "predictability.csv", &format!("Correlation of the pile state before each drop with the avalanche {} it started", measure), vec![
+        CsvColumn::new("predictor", "string", "", "Pile state recorded before the drop, combined for the linear fit of all of them scored on the held out drops"),
+        CsvColumn::new("pearson", "float", "", "Pearson correlation with the avalanche size, empty when either does not vary"),
+        CsvColumn::new("spearman", "float", "", "Spearman rank correlation with the avalanche size, empty when either does not vary"),
+        CsvColumn::new("large_auc", "float", "", "ROC AUC for picking out the large avalanches, 0.5 = chance"),
+    ]);
+    let mut writer = CsvWriter::create(&path.to_string_lossy(), "predictability.csv", &schema)?;
+
+    let format = |value: Option<f64>| value.map(|value| format!("{:.4}", value)).unwrap_or_else(|| "-".to_string());
+    println!("{} drops from grain {}, large avalanches have {} of at least {} (quantile {})", sizes.len(), fromGrain, measure, largeSize, quantile);
+    println!("combined fit on the first {} drops, scored on the {} after them", trainingRows, heldOutSizes.len());
+    println!("{:<24}  {:>8}  {:>8}  {:>9}", "predictor", "pearson", "spearman", "large auc");
+    let rows = predictors.iter().map(|(name, values)| (name.as_str(), values.as_slice(), sizes.as_slice(), large.as_slice()))
+        .chain(std::iter::once(("combined", combined.as_slice(), heldOutSizes, heldOutLarge)));
+    for (name, values, sizes, large) in rows {
+        let pearson = pearsonCorrelation(values, sizes);
+        let spearman = spearmanCorrelation(values, sizes);
+        let auc = largeAvalancheAuc(values, large);
+        println!("{:<24}  {:>8}  {:>8}  {:>9}", name, format(pearson), format(spearman), format(auc));
+        let cell = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+        writer.writeRow(&[name.to_string(), cell(pearson), cell(spearman), cell(auc)])?;
+    }
+
+    // flush the writer to ensure all data is written to the file
+    writer.finish()?;
+
+    let score = largeAvalancheAuc(&combined, heldOutLarge);
+    println!("variance of log(1 + {}) explained by the combined fit on the held out drops: {:.4}", measure, explained);
+    match score {
+        Some(score) => println!("predictability score (AUC of the combined fit, 0.5 = chance, 1 = every large avalanche foreseen): {:.4}", score),
+        None => println!("predictability score: none, every held out avalanche is the same size"),
+    }
+
+    Ok(())
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
@@ -0,0 +1,79 @@
+use std::io;
+use std::path::Path;
+
+// internal models
+use crate::util::avalancheReplay::{AVALANCHE_REPLAY_FILE, AvalancheReplayReader, ReplayEvent};
+
+
+pub const REPLAY_USAGE: &str = "replay <run> [--avalanche <grain index>]
+    List the avalanches recorded in the avalanche replay of a run, given as a run folder or run id in ./data,
+    or step through every grain state transition of the avalanche started by the grain index
+    avalanches are recorded when REPLAY_MIN_AVALANCHE_GRAINS is set";
+
+/**
+ * List or step through the recorded avalanches of the run given on the command line
+ */
+pub fn runReplay(args: &[String]) -> io::Result<()> {
+    let mut grainIndex: Option<usize> = None;
+    let mut runPath: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--avalanche" => grainIndex = Some(args.next().and_then(|index| index.parse().ok())
+                .ok_or_else(|| invalidInput("--avalanche needs a grain index"))?),
+            _ => runPath = Some(arg.clone()),
+        }
+    }
+    let runPath = runPath.ok_or_else(|| invalidInput("replay needs a run"))?;
+
+    // run ids are looked up in the data folder
+    let path = if Path::new(&runPath).is_dir() { Path::new(&runPath).to_path_buf() } else { Path::new("./data").join(&runPath) };
+    if !path.is_dir() {
+        return Err(invalidInput(&format!("{} is not a run folder", runPath)));
+    }
+    let replayPath = path.join(AVALANCHE_REPLAY_FILE);
+    if !replayPath.is_file() {
+        return Err(invalidInput(&format!("{} has no {}, set REPLAY_MIN_AVALANCHE_GRAINS to record avalanches", runPath, AVALANCHE_REPLAY_FILE)));
+    }
+
+    let reader = AvalancheReplayReader::open(&replayPath)?;
+    let Some(grainIndex) = grainIndex else {
+        let mut count = 0;
+        for avalanche in reader {
+            let avalanche = avalanche?;
+            println!("avalanche {}: {} grains, {} movement, {} steps, {} events",
+                avalanche.grainIndex, avalanche.totalGrainsInvolved, avalanche.totalMovement, avalanche.duration, avalanche.events.len());
+            count += 1;
+        }
+        println!("{} avalanches recorded", count);
+        return Ok(());
+    };
+
+    for avalanche in reader {
+        let avalanche = avalanche?;
+        if avalanche.grainIndex != grainIndex {
+            continue;
+        }
+
+        println!("Avalanche {}: {} grains, {} movement, {} steps", avalanche.grainIndex, avalanche.totalGrainsInvolved, avalanche.totalMovement, avalanche.duration);
+        for events in avalanche.steps() {
+            println!("step {}", events[0].step);
+            for event in events {
+                println!("  {}", describeEvent(event));
+            }
+        }
+        return Ok(());
+    }
+
+    Err(invalidInput(&format!("avalanche {} was not recorded", grainIndex)))
+}
+
+fn describeEvent(event: &ReplayEvent) -> String {
+    let released = event.perturbedBy.map(|grainId| format!(" released by the impact of grain {}", grainId)).unwrap_or_default();
+    format!("grain {} {:?} at {:?} -> {:?} at {:?}, energy {}{}", event.grainId, event.fromState, event.from, event.toState, event.to, event.energy, released)
+}
+
+fn invalidInput(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
@@ -11,13 +11,47 @@
    * Density for each pile location
      * Sandpile locations have a capacity of 4 grains plus the output of the order of
      *  magnitude power-law distribution
+     * Optionally locations are compacted by impacts, weakened by avalanches and heal over time
+     *
+   * Grain types
+     * Grains are drawn from a mixture of types with a size, mass and cohesion (see GRAIN_TYPES)
      *
    * Moment
      * Gains move with a magnitude (speed) and direction
        * Initial speed starts at 1 but kinetic energy can be transferred in collisions
        * speed increases as grain falls
+       * rolling grains can lose energy to friction with the roughness of each location and on impact
+       *  with full locations, off by default, grains that run out of energy stop part way down
+       *  the slope on top of the full location they hit
      * Direction of impacted grain movement is determined by direction of impacting grain
+       * rolling grains favor their current direction, set by GRAIN_INERTIA
    * Energy from impacts radiate through surrounding grains
+     * attenuated by ENERGY_RADIATION_ATTENUATION for each location out to ENERGY_RADIATION_RANGE
+     *  when it is set, radiation is off by default
+   * Grain trajectory tracing
+     * selected grains have every step of their path written to grain-trace.csv
+   * Residence times
+     * every rest, re-mobilization and exit of each grain is recorded for residence time,
+     *  transit time and layer turnover exports when RESIDENCE_RECORDING is set
+   * Avalanche replays
+     * every grain state transition of large avalanches is written to avalanche-replay.bin
+     *  and can be stepped through with the replay command or AvalancheReplayReader
+   * Causal cascades
+     * each avalanche records which impact released which grains as a tree, the trees of avalanches
+     *  of at least CASCADE_MIN_AVALANCHE_GRAINS grains are exported to the cascades folder with the
+     *  branching ratio of each generation
+     * the mean branching ratio over time tells whether the pile is sub-critical, critical
+     *  or super-critical, the criticality command finds where it crosses 1 across runs
+   * Temporal statistics
+     * waiting times between large avalanches, size autocorrelation, the Hurst exponent from a
+     *  detrended fluctuation analysis and the Omori decay of aftershocks of the avalanche series
+   * Pre-drop pile state
+     * the load of the pile before each drop is written with the avalanche it started when
+     *  PRE_DROP_INTERVAL is set and the predictability command scores, on drops it was not
+     *  fitted to, how well it foretells the large avalanches
+   * Control runs
+     * the control command reruns the configuration with every power-law distribution replaced
+     *  by a variance-matched exponential or a constant and compares the avalanche exponents
 
 */
 
@@ -34,15 +68,31 @@ use chrono::Local;
 // internal modules
 pub mod models;
 pub mod util;
+pub mod analysis;
+pub mod commands;
 
 // external structs and functions
 use models::avalanche::Avalanche;
 use models::grain::Grain;
 use models::location::Location;
 use models::avalanche;
+use analysis::stationarity::StationarityDetector;
+use analysis::pileObservables::PileObservablesRecorder;
+use analysis::residence::ResidenceRecorder;
+use analysis::distributions::{displayAvalancheDistributions, displayAvalancheSeries};
+use analysis::cascade::displayAvalancheCascades;
+use analysis::branching::{displayBranchingRatio, runBranchingEstimate};
+use analysis::temporal::displayTemporalStatistics;
+use analysis::predictability::PreDropRecorder;
+use util::pileSnapshot::PileSnapshotRecorder;
+use util::grainTrace::GrainTraceRecorder;
+use util::avalancheReplay::AvalancheReplayRecorder;
+use util::pileState::PileState;
+use util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput, displayRunLayout};
+use util::runManifest::{RunSummary, RUN_MANIFEST_FILE, displayRunManifest};
 
 
-use util::sandpileUtil::normalizedPowerLawByOrdersOfMagnitudeWithAlpha;
+use util::sandpileUtil::{normalizedPowerLawByOrdersOfMagnitudeWithAlpha, initializeRng, seededRng};
 
 
 // Constants
@@ -64,30 +114,118 @@ use util::constants::ALPHA_EXTRA_ENERGY;
 use util::constants::ALPHA_AVALANCHE_SIZE;
 use util::constants::ALPHA_LOCATION_EXTRA_CAPACITY;
 use util::constants::ALPHA_LOCATION_EXTRA_RESILIENCE;
-use util::constants::BASE_AVALANCHE_METHOD;
 use util::constants::BASE_AVALANCHE_SIZE;
 use util::constants::BASE_AVALANCHE_SIZE_PERCENT;
+use util::constants::GRAIN_INERTIA;
+use util::constants::ENERGY_RADIATION_RANGE;
+use util::constants::ENERGY_RADIATION_ATTENUATION;
+use util::constants::ROLLING_FRICTION;
+use util::constants::RESTITUTION_COEFFICIENT;
+use util::constants::MAX_REBOUND_ENERGY;
+use util::constants::MIN_LOCATION_ROUGHNESS;
+use util::constants::MAX_LOCATION_ROUGHNESS;
+use util::constants::COMPACTION_IMPACTS;
+use util::constants::COMPACTION_CAPACITY_GAIN;
+use util::constants::COMPACTION_RESILIENCE_GAIN;
+use util::constants::MAX_COMPACTIONS;
+use util::constants::AVALANCHE_WEAKENING;
+use util::constants::HEALING_INTERVAL;
+use util::constants::GRAIN_TYPES;
+use util::constants::AVALANCHE_GRAINS_PER_ENERGY;
+use util::constants::CRITICAL_OCCUPANCY;
+use models::avalancheSizeRule::configuredAvalancheSizeRule;
+use models::avalancheSizeRule::checkAvalancheSizeRule;
+use util::constants::parameterDistributions;
+use util::constants::TRACE_GRAIN_IDS;
+use util::constants::TRACE_EVERY_NTH_GRAIN;
+use util::constants::TRACE_AVALANCHES;
+use util::constants::RESIDENCE_RECORDING;
+use util::constants::REPLAY_MIN_AVALANCHE_GRAINS;
+use util::constants::CASCADE_MIN_AVALANCHE_GRAINS;
+use util::constants::TEMPORAL_MEASURE;
+use util::constants::WAITING_TIME_THRESHOLDS;
+use util::constants::AUTOCORRELATION_MAX_LAG;
+use util::constants::DFA_MIN_SCALE;
+use util::constants::OMORI_MAINSHOCK_QUANTILE;
+use util::constants::OMORI_AFTERSHOCK_QUANTILE;
+use util::constants::OMORI_MAX_LAG;
+use util::constants::PRE_DROP_INTERVAL;
+use util::constants::PRE_DROP_RADIUS;
+use util::constants::NEAR_THRESHOLD_MARGIN;
+use util::paramDistribution::{initializeControl, controlRun, loadEmpiricalDistributions};
 
 
 
 fn main() {
+
+    // commands for working with finished runs, see commands::displayUsage
+    // --resume <pile-state.bin> carries on the run from the pile state an earlier run ended with
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut resumeState: Option<PileState> = None;
+    if args.first().is_some_and(|arg| arg == "--resume") {
+        let Some(path) = args.get(1) else {
+            eprintln!("--resume needs a pile state file");
+            std::process::exit(1);
+        };
+        match PileState::read(path.clone()) {
+            Ok(state) => resumeState = Some(state),
+            Err(e) => {
+                eprintln!("--resume: could not read {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    } else if !args.is_empty() {
+        if let Err(e) = commands::runCommand(&args) {
+            eprintln!("{}: {}", args[0], e);
+            std::process::exit(1);
+        }
+        return;
+    }
     
-    
+    // an unknown avalanche size rule would only be found at the first perturbation
+    if let Err(e) = checkAvalancheSizeRule() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    // empirical parameter distributions are read before the run so a bad file stops it here
+    if let Err(e) = loadEmpiricalDistributions() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    // a control run replaces every power-law distribution, see commands::control
+    let control = match initializeControl() {
+        Ok(control) => control,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Each run's data is stored in a folder named with the current timestamp-number of grains-size of pile
+    // control runs add control-<kind> as the label of the folder
     
     // Generate the current timestamp as a folder name
     let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
     let start_time: chrono::prelude::DateTime<Local> = Local::now(); 
     
     //let timestamp = format!("{}-{}-{}", timestamp, TOTAL_GRAINS, X_SIZE * Y_SIZE * Z_SIZE);
-    let folder_path = format!("./data/{}", timestamp + "-gs-" + &TOTAL_GRAINS.to_string() + "-ps-" + &X_SIZE.to_string() + "-" + &Y_SIZE.to_string() + "-" + &Z_SIZE.to_string());
+    let mut folder_path = format!("./data/{}", timestamp + "-gs-" + &TOTAL_GRAINS.to_string() + "-ps-" + &X_SIZE.to_string() + "-" + &Y_SIZE.to_string() + "-" + &Z_SIZE.to_string());
+    if let Some(kind) = control {
+        folder_path = folder_path + "-control-" + kind.name();
+    }
+    if resumeState.is_some() {
+        folder_path += "-resumed";
+    }
 
     // Create the directory using the path
     let _ = fs::create_dir_all(&folder_path);
 
 
-    // create a random number generator
-    let mut rnd = rand::thread_rng();
+    // seed the random number generator for the run and create a generator for the locations
+    let seed = initializeRng();
+    let mut rnd = seededRng();
 
     // initialize the locations as a static mutex hashmap
     models::location::Location::initializeLocations(&mut rnd);
@@ -97,6 +235,18 @@ fn main() {
     //let mut grains: Vec<Grain> = Vec::with_capacity(TOTAL_GRAINS);
     models::grain::Grain::initializeGrains();
 
+    // place the grains of a resumed pile and carry on dropping from the grain after the last one it was built with
+    let firstGrain = match &resumeState {
+        Some(state) => {
+            if let Err(e) = state.restore() {
+                eprintln!("--resume: {}", e);
+                std::process::exit(1);
+            }
+            (state.grainsDropped as usize).min(TOTAL_GRAINS)
+        },
+        None => 0,
+    };
+
 
     // initialize all the grains in the array
     //initializeGrains(&mut grains, &mut rnd);
@@ -113,9 +263,51 @@ fn main() {
         println!( "---------------- Avalanches created with count: {} ----------------", avalanches.len());
     }
 
+    // track the running observables of the pile to find when it becomes stationary
+    let mut stationarity = StationarityDetector::new();
 
-    // for each grain, create an avalanche
-    for i in 0..TOTAL_GRAINS {
+    // track the height map, slope and radial profile of the pile
+    let mut pileObservables = PileObservablesRecorder::new();
+
+    // write snapshots of the pile state as it evolves
+    let mut pileSnapshots = PileSnapshotRecorder::new();
+
+    // record the avalanches large enough to replay
+    let mut avalancheReplay = match AvalancheReplayRecorder::new(&folder_path) {
+        Ok(avalancheReplay) => avalancheReplay,
+        Err(e) => {
+            eprintln!("could not create the avalanche replay: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // record when each grain rests, is re-mobilized and leaves the pile
+    let mut residence = ResidenceRecorder::new(TOTAL_GRAINS);
+
+    // record the pile state before each drop alongside the avalanche it starts
+    let mut preDrop = match PreDropRecorder::new(&folder_path) {
+        Ok(preDrop) => preDrop,
+        Err(e) => {
+            eprintln!("could not create the pre-drop state: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // trace the path of the selected grains
+    let mut grainTrace = match GrainTraceRecorder::new(TOTAL_GRAINS, &folder_path) {
+        Ok(grainTrace) => grainTrace,
+        Err(e) => {
+            eprintln!("could not create the grain trace: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+
+    // for each grain, create an avalanche, the avalanches of the grains a resumed pile was built with stay empty
+    for i in firstGrain..TOTAL_GRAINS {
+
+        // capture the pile before the grain is dropped
+        preDrop.recordDrop(i, i as u32);
 
         // Add the new falling grain to the avalanche, this is grain 0
         avalanches[i].addGrain(i as u32);
@@ -136,6 +328,9 @@ fn main() {
         while totalGrains > 0 {
             // determine the number of grains in the avalanche at this point in time
             totalGrains = avalanches[i].grainIds.len();
+            if totalGrains != 0 {
+                avalanches[i].duration += 1;
+            }
 
             // for each grain currently in the avalanche, update the grain at this time period
             let previous_len = totalGrains;
@@ -151,21 +346,58 @@ fn main() {
                 // get the amount of grains in the avalanche before the update
                 let previous_len = avalanches[i].grainIds.len();
 
-                // perform the update on the grain
+                // perform the update on the grain, tracing its path if it is selected
+                let _ = grainTrace.recordStart(grainId, i);
                 avalanches[i].update( grainId );
+                let _ = grainTrace.recordStep(grainId, i, avalanches[i].duration);
+                if RESIDENCE_RECORDING {
+                    residence.recordUpdate(grainId, i);
+                }
 
             }
         }
 
         if DEBUG && DEBUG_AVALANCHE { println!( "Avalanche {} END: total movement: {}, total grains involved: {}", i, avalanches[i].totalMovement, avalanches[i].totalGrainsInvolved) };
         if DEBUG && DEBUG_AVALANCHE { println!( "/n/n----------------------------------------------------------------------------------------------") };
+
+        // only the causal trees of large avalanches are kept for the cascade export
+        avalanches[i].finishCascade();
+
+        // write the pile state before the drop with the size of the avalanche
+        let _ = preDrop.recordAvalanche(&avalanches[i]);
+
+        // keep the events of the avalanche if it is large enough to replay
+        let _ = avalancheReplay.recordAvalanche(i, &mut avalanches[i]);
+
+        // record the shape of the pile on each observables interval
+        let _ = pileObservables.recordAvalanche(i, folder_path.clone());
+
+        // weakened locations regain resilience on each healing interval
+        if HEALING_INTERVAL != 0 && (i + 1).is_multiple_of(HEALING_INTERVAL) {
+            Location::healLocations();
+        }
+
+        // snapshot the pile state on each snapshot interval
+        let _ = pileSnapshots.recordAvalanche(i, folder_path.clone());
+
+        // record the avalanche and stop early if enough avalanches have completed after the pile became stationary
+        stationarity.recordAvalanche(i, &avalanches[i]);
+        if stationarity.shouldStop(i) {
+            avalanches.truncate(i + 1);
+            break;
+        }
     }
 
+    // finish the trace of the selected grains, the avalanche replay and the pre-drop state
+    let _ = grainTrace.finish();
+    let _ = avalancheReplay.finish();
+    let _ = preDrop.finish();
+
     //draw the pile
     if DEBUG && DEBUG_DISPLAY_PILE {
 
         // output the run configuration to a file
-        let _ = displayApplicationRunConfiguration(folder_path.clone());
+        let _ = displayApplicationRunConfiguration(folder_path.clone(), seed);
         
         println!("Final breakdown of grains at all locations export --------------------------------------------------------------");
         let _ = models::location::Location::displayAllLocationFinalPositions(folder_path.clone());
@@ -184,9 +416,49 @@ fn main() {
         let _ = recordExportedData(&avalanches, folder_path.clone());
     }
 
+    // the analysis of the run is written whether or not the debug pile display is on, the recorders run either way
+    let _ = PileState::capture(avalanches.len() as u64).write(folder_path.clone() + "/pile-state.bin");
+    registerOutput(&folder_path, "pile-state.bin", "binary", "Full pile state at the end of the run in the binary pile state format", None);
+    println!("Distributions export -------------------------------------------------------------------------------------------");
+    let _ = displayAvalancheDistributions(&avalanches, folder_path.clone());
+    let _ = displayAvalancheSeries(&avalanches, folder_path.clone());
+    println!("Cascade export -------------------------------------------------------------------------------------------------");
+    let _ = displayAvalancheCascades(&avalanches, folder_path.clone());
+    println!("Branching ratio export -----------------------------------------------------------------------------------------");
+    let _ = displayBranchingRatio(&avalanches, stationarity.stationaryAtGrain, folder_path.clone());
+    println!("Temporal statistics export -------------------------------------------------------------------------------------");
+    let _ = displayTemporalStatistics(&avalanches, stationarity.stationaryAtGrain, folder_path.clone());
+    println!("Stationarity export --------------------------------------------------------------------------------------------");
+    let _ = stationarity.displayStationarity(folder_path.clone());
+    println!("Pile observables export ----------------------------------------------------------------------------------------");
+    let _ = pileObservables.displayPileObservables(avalanches.len() - 1, folder_path.clone());
+    if RESIDENCE_RECORDING {
+        println!("Residence time export ------------------------------------------------------------------------------------------");
+        let _ = residence.displayResidence(avalanches.len(), folder_path.clone());
+    }
+
+    println!("Run layout export ----------------------------------------------------------------------------------------------");
+    registerOutput(&folder_path, RUN_MANIFEST_FILE, "json", "Configuration, provenance, timing, totals and checksums of the run", None);
+    let _ = displayRunLayout(folder_path.clone());
+
     // output the total running time of the program using the start_time
     let end_time: chrono::prelude::DateTime<Local> = Local::now();
     let duration: chrono::TimeDelta = end_time.signed_duration_since(start_time);
+
+    // the manifest is written last so it can checksum every other output of the run
+    println!("Run manifest export --------------------------------------------------------------------------------------------");
+    let summary = RunSummary {
+        seed,
+        startTime: start_time,
+        endTime: end_time,
+        grainsDropped: avalanches.len(),
+        grainsInPile: models::location::Location::getTotalGrainsInPile(),
+        grainsOffPile: avalanches.iter().map(|avalanche| avalanche.grainsOffPile).sum(),
+        stationaryAtGrain: stationarity.stationaryAtGrain,
+        branching: runBranchingEstimate(&avalanches, stationarity.stationaryAtGrain),
+    };
+    let _ = displayRunManifest(folder_path.clone(), &summary);
+
     println!("Total time: {:?}", duration);
 
 }
@@ -200,10 +472,11 @@ fn initializeAvalanches(avalanches: &mut Vec<Avalanche>) {
     }
 }
 
-pub fn displayApplicationRunConfiguration(folder_path: String) -> io::Result<()> {
+pub fn displayApplicationRunConfiguration(folder_path: String, seed: u64) -> io::Result<()> {
     // Create a file and wrap it in a BufWriter for efficient writing
-    let file = File::create(folder_path + "/run-configuration.txt")?;
+    let file = File::create(folder_path.clone() + "/run-configuration.txt")?;
     let mut writer = BufWriter::new(file);
+    registerOutput(&folder_path, "run-configuration.txt", "text", "Human readable configuration of the run", None);
 
     writeln!( writer, "Run configuration")?;
     writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
@@ -212,15 +485,65 @@ pub fn displayApplicationRunConfiguration(folder_path: String) -> io::Result<()>
     writeln!( writer, "Terminal Free Fall Speed: {}", TERMINAL_FREE_FALL_SPEED)?;
     writeln!( writer, "Base Resilience: {}", BASE_RESILIENCE)?;
     writeln!( writer, "Base Capacity: {}", BASE_CAPACITY)?;
-    writeln!( writer, "Base Avalanche Method: {}", BASE_AVALANCHE_METHOD)?;
-    writeln!( writer, "Base Avalanche Size: (base for avalanche method=1) {}", BASE_AVALANCHE_SIZE)?;
-    writeln!( writer, "Base Avalanche Size Percent (for avalanche method=2): {}", BASE_AVALANCHE_SIZE_PERCENT)?;
+    writeln!( writer, "Avalanche Size Rule ({}): {}", configuredAvalancheSizeRule().name(), configuredAvalancheSizeRule().describe())?;
+    writeln!( writer, "Base Avalanche Size: (base for the fixed rule) {}", BASE_AVALANCHE_SIZE)?;
+    writeln!( writer, "Base Avalanche Size Percent (for the percent rule): {}", BASE_AVALANCHE_SIZE_PERCENT)?;
+    writeln!( writer, "Avalanche Grains Per Energy (for the energy rule): {}", AVALANCHE_GRAINS_PER_ENERGY)?;
+    writeln!( writer, "Critical Occupancy (for the threshold rule): {}", CRITICAL_OCCUPANCY)?;
+    writeln!( writer, "Grain Inertia (bias of rolling grains toward their direction of movement, 0 = random): {}", GRAIN_INERTIA)?;
+    writeln!( writer, "Energy Radiation Range (locations impact energy reaches, 0 = none): {}", ENERGY_RADIATION_RANGE)?;
+    writeln!( writer, "Energy Radiation Attenuation (fraction of energy passed on per location): {}", ENERGY_RADIATION_ATTENUATION)?;
+    writeln!( writer, "Rolling Friction (energy lost per location rolled over, times its roughness): {}", ROLLING_FRICTION)?;
+    writeln!( writer, "Restitution Coefficient (fraction of energy kept on hitting a full location): {}", RESTITUTION_COEFFICIENT)?;
+    writeln!( writer, "Max Rebound Energy (most energy kept on hitting a full location): {}", MAX_REBOUND_ENERGY)?;
+    writeln!( writer, "Min Location Roughness: {}", MIN_LOCATION_ROUGHNESS)?;
+    writeln!( writer, "Max Location Roughness: {}", MAX_LOCATION_ROUGHNESS)?;
+    writeln!( writer, "Compaction Impacts (impacts per compaction of a location, 0 = none): {}", COMPACTION_IMPACTS)?;
+    writeln!( writer, "Compaction Capacity Gain: {}", COMPACTION_CAPACITY_GAIN)?;
+    writeln!( writer, "Compaction Resilience Gain: {}", COMPACTION_RESILIENCE_GAIN)?;
+    writeln!( writer, "Max Compactions (per location): {}", MAX_COMPACTIONS)?;
+    writeln!( writer, "Avalanche Weakening (resilience lost each time a location releases grains): {}", AVALANCHE_WEAKENING)?;
+    writeln!( writer, "Healing Interval (grains dropped per unit of resilience healed, 0 = none): {}", HEALING_INTERVAL)?;
     writeln!( writer, "Alpha Main (default - not used currently): {}", ALPHA_MAIN)?;
     writeln!( writer, "Alpha Landing (variance for initial x,y deviation from center): {}", ALPHA_LANDING)?;
     writeln!( writer, "Alpha Extra Energy (amount of additional energy added to impact): {}", ALPHA_EXTRA_ENERGY)?;
     writeln!( writer, "Alpha Avalanche Size (additional grains that join avalanche added to BASE by selected Method): {}", ALPHA_AVALANCHE_SIZE)?;
     writeln!( writer, "Alpha Location Extra Capacity (additional capacity at location): {}", ALPHA_LOCATION_EXTRA_CAPACITY)?;
     writeln!( writer, "Alpha Location Extra Resilience (additional resilience of location): {}", ALPHA_LOCATION_EXTRA_RESILIENCE)?;
+    for (name, distribution) in parameterDistributions() {
+        let label: Vec<String> = name.split('_').map(|word| word[..1].to_string() + &word[1..].to_lowercase()).collect();
+        writeln!( writer, "{}: {} ({})", label.join(" "), distribution.kind(), distribution.describe())?;
+    }
+    for (i, (name, fraction, size, mass, cohesion)) in GRAIN_TYPES.iter().enumerate() {
+        writeln!( writer, "Grain Type {} Fraction ({}): {}", i + 1, name, fraction)?;
+        writeln!( writer, "Grain Type {} Size ({}): {}", i + 1, name, size)?;
+        writeln!( writer, "Grain Type {} Mass ({}): {}", i + 1, name, mass)?;
+        writeln!( writer, "Grain Type {} Cohesion ({}): {}", i + 1, name, cohesion)?;
+    }
+    if let Some((kind, replacements)) = controlRun() {
+        for replacement in replacements {
+            writeln!( writer, "Control Replacement ({} control run, {} with mean {:.4} and variance {:.4}): {}", kind.name(), replacement.name,
+                replacement.mean, replacement.variance, replacement.replacement.describe())?;
+        }
+    }
+    writeln!( writer, "Trace Grain Ids: {:?}", TRACE_GRAIN_IDS)?;
+    writeln!( writer, "Trace Every Nth Grain (0 = none): {}", TRACE_EVERY_NTH_GRAIN)?;
+    writeln!( writer, "Trace Avalanches (grain indexes): {:?}", TRACE_AVALANCHES)?;
+    writeln!( writer, "Residence Recording: {}", RESIDENCE_RECORDING)?;
+    writeln!( writer, "Replay Min Avalanche Grains (0 = no replays): {}", REPLAY_MIN_AVALANCHE_GRAINS)?;
+    writeln!( writer, "Cascade Min Avalanche Grains (0 = no causal trees kept): {}", CASCADE_MIN_AVALANCHE_GRAINS)?;
+    writeln!( writer, "Temporal Measure (avalanche measure of the temporal statistics): {}", TEMPORAL_MEASURE)?;
+    writeln!( writer, "Waiting Time Thresholds: {:?}", WAITING_TIME_THRESHOLDS)?;
+    writeln!( writer, "Autocorrelation Max Lag: {}", AUTOCORRELATION_MAX_LAG)?;
+    writeln!( writer, "DFA Min Scale: {}", DFA_MIN_SCALE)?;
+    writeln!( writer, "Omori Mainshock Quantile: {}", OMORI_MAINSHOCK_QUANTILE)?;
+    writeln!( writer, "Omori Aftershock Quantile: {}", OMORI_AFTERSHOCK_QUANTILE)?;
+    writeln!( writer, "Omori Max Lag: {}", OMORI_MAX_LAG)?;
+    writeln!( writer, "Pre-Drop Interval (grains between records of the pile state before a drop, 0 = none): {}", PRE_DROP_INTERVAL)?;
+    writeln!( writer, "Pre-Drop Radius (columns either side of the drop site in the local occupancy): {}", PRE_DROP_RADIUS)?;
+    writeln!( writer, "Near Threshold Margin (grains short of capacity counted as near threshold): {}", NEAR_THRESHOLD_MARGIN)?;
+    writeln!( writer, "Grain Drop Scheme: one grain per avalanche dropped from z={} at the center offset by the landing distribution", Z_SIZE - 1)?;
+    writeln!( writer, "RNG Seed: {}", seed)?;
     writeln!( writer, "---------------------------------------------------------------------------------------------------")?;
 
     // flush the writer to ensure all data is written to the file
@@ -231,9 +554,12 @@ pub fn displayApplicationRunConfiguration(folder_path: String) -> io::Result<()>
 
 pub fn displayAvalancheTotalGrainsStats(avalanches: &Vec<Avalanche>, folder_path: String) -> io::Result<()> {
     
-    // Create a file and wrap it in a BufWriter for efficient writing
-    let file = File::create(folder_path + "/grain-stats.csv")?;
-    let mut writer = BufWriter::new(file);
+    // Create the csv through the common run output writer
+    let schema = CsvSchema::new("grain-stats", "grain-stats.csv", "Histogram of the number of grains involved in each avalanche", vec![
+        CsvColumn::new("avalanche_grain_count", "integer", "grains", "Number of grains involved in the avalanche"),
+        CsvColumn::new("avalanche_count", "integer", "avalanches", "Number of avalanches involving that many grains"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "grain-stats.csv", &schema)?;
 
 
     // build a hashmap that will store a vector of ids of avalanches for each discrete total grain value within the avalanches vector.
@@ -253,9 +579,8 @@ pub fn displayAvalancheTotalGrainsStats(avalanches: &Vec<Avalanche>, folder_path
     let mut sortedKeys: Vec<usize> = avalancheTotalGrainsMap.keys().cloned().collect();
 
     sortedKeys.sort();
-    writeln!( writer, "Avalanche Grain Count,  Number Avalanches")?;
     for totalGrains in sortedKeys {
-        writeln!( writer, "{}, {:?}", totalGrains, avalancheTotalGrainsMap.get(&totalGrains).unwrap().len())?;
+        writer.writeRow(&[totalGrains.to_string(), avalancheTotalGrainsMap.get(&totalGrains).unwrap().len().to_string()])?;
     }
 
     // print out the total grain value and the ids of the avalanches that have that total grain value
@@ -265,23 +590,26 @@ pub fn displayAvalancheTotalGrainsStats(avalanches: &Vec<Avalanche>, folder_path
     // }
 
     // flush the writer to ensure all data is written to the file
-    writer.flush()?;
+    writer.finish()?;
 
     Ok(())
 
 }
 
 /** 
- * Create a txt file in the format that can be imported into python for powerlaw analysis using the powerlaw library
- * the file contains only the total movement, each row indicates the number of avalanches with that total movement
+ * Create a csv file in the format that can be imported into python for powerlaw analysis using the powerlaw library
+ * each row holds a total movement and the number of avalanches with that total movement
  * The first row is number of avalanches with total movement 1, the second row is number of avalanches with total movement 2, etc
  * If no avalanches of a particular movement size existed in the data that size should be included (all movement sizes 
- * should be in the data from 1-> n, n = largest movement), include these rows with the row value 0
+ * should be in the data from 1-> n, n = largest movement), include these rows with the count 0
  */
 pub fn recordExportedData(avalanches: &Vec<Avalanche>, folder_path: String) -> io::Result<()> {
-    // Create a file and wrap it in a BufWriter for efficient writing
-    let file = File::create(folder_path + "/py-powerlaw-import.txt")?;
-    let mut writer = BufWriter::new(file);
+    // Create the csv through the common run output writer
+    let schema = CsvSchema::new("py-powerlaw-import", "py-powerlaw-import.csv", "Dense histogram of avalanche movement for every movement from 1 to the largest, read by analysis/powerlaw-analysis.py", vec![
+        CsvColumn::new("avalanche_movement", "integer", "moves", "Total movement of the avalanche"),
+        CsvColumn::new("avalanche_count", "integer", "avalanches", "Number of avalanches with that total movement, 0 if none"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "py-powerlaw-import.csv", &schema)?;
 
     // build a hashmap that will store a vector of ids of avalanches for each discrete total movement value within the avalanches vector.
     let mut avalancheTotalMovementMap: HashMap<usize, Vec<u32>> = HashMap::new();
@@ -302,24 +630,24 @@ pub fn recordExportedData(avalanches: &Vec<Avalanche>, folder_path: String) -> i
 
     // loop from 1 to the largest movement size, if the movement size is in the hashmap print out the number of avalanches with that movement size, otherwise print 0
     for i in 1..largestMovement+1 {
-        if avalancheTotalMovementMap.contains_key(&i) {
-            writeln!( writer, "{}", avalancheTotalMovementMap.get(&i).unwrap().len())?;
-        } else {
-            writeln!( writer, "0")?;
-        }
+        let count = avalancheTotalMovementMap.get(&i).map_or(0, |ids| ids.len());
+        writer.writeRow(&[i.to_string(), count.to_string()])?;
     }
     
     // flush the writer to ensure all data is written to the file
-    writer.flush()?;
+    writer.finish()?;
 
     Ok(())
 }
 
 pub fn displayAvalancheTotalMovementStats(avalanches: &Vec<Avalanche>, folder_path: String) -> io::Result<()> {
 
-    // Create a file and wrap it in a BufWriter for efficient writing
-    let file = File::create(folder_path + "/avalanche-movement-stats.csv")?;
-    let mut writer = BufWriter::new(file);
+    // Create the csv through the common run output writer
+    let schema = CsvSchema::new("avalanche-movement-stats", "avalanche-movement-stats.csv", "Histogram of the total movement of each avalanche", vec![
+        CsvColumn::new("avalanche_movement", "integer", "moves", "Total movement of all grains in the avalanche"),
+        CsvColumn::new("avalanche_count", "integer", "avalanches", "Number of avalanches with that total movement"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "avalanche-movement-stats.csv", &schema)?;
     
     // build a hashmap that will store a vector of ids of avalanches for each discrete total movement value within the avalanches vector.
     let mut avalancheTotalMovementMap: HashMap<usize, Vec<u32>> = HashMap::new();
@@ -338,9 +666,8 @@ pub fn displayAvalancheTotalMovementStats(avalanches: &Vec<Avalanche>, folder_pa
     let mut sortedKeys: Vec<usize> = avalancheTotalMovementMap.keys().cloned().collect();
 
     sortedKeys.sort();
-    writeln!( writer, "Avalanche Movement, Number Avalanches")?;
     for totalMovement in sortedKeys {
-        writeln!( writer, "{}, {:?}", totalMovement, avalancheTotalMovementMap.get(&totalMovement).unwrap().len())?;
+        writer.writeRow(&[totalMovement.to_string(), avalancheTotalMovementMap.get(&totalMovement).unwrap().len().to_string()])?;
     }
 
     // print out the total movement value and the ids of the avalanches that have that total movement value
@@ -350,7 +677,7 @@ pub fn displayAvalancheTotalMovementStats(avalanches: &Vec<Avalanche>, folder_pa
     // }
 
     // flush the writer to ensure all data is written to the file
-    writer.flush()?;
+    writer.finish()?;
 
     Ok(())
 
@@ -362,9 +689,12 @@ pub fn displayAvalancheTotalMovementStats(avalanches: &Vec<Avalanche>, folder_pa
  */
 pub fn displayAvalancheTotalMagnitude(avalanches: &Vec<Avalanche>, folder_path: String) -> io::Result<()> {
 
-    // Create a file and wrap it in a BufWriter for efficient writing
-    let file = File::create(folder_path + "/avalanche-total-magnitude.csv")?;
-    let mut writer = BufWriter::new(file);
+    // Create the csv through the common run output writer
+    let schema = CsvSchema::new("avalanche-total-magnitude", "avalanche-total-magnitude.csv", "Histogram of the magnitude of each avalanche, total grains involved times total movement", vec![
+        CsvColumn::new("avalanche_magnitude", "integer", "grain moves", "Total grains involved times total movement of the avalanche"),
+        CsvColumn::new("avalanche_count", "integer", "avalanches", "Number of avalanches with that magnitude"),
+    ]);
+    let mut writer = CsvWriter::create(&folder_path, "avalanche-total-magnitude.csv", &schema)?;
 
     // build a hashmap that will store a vector of ids of avalanches for each discrete total movement value within the avalanches vector.
     let mut avalancheTotalMagnitudeMap: HashMap<usize, Vec<u32>> = HashMap::new();
@@ -383,13 +713,12 @@ pub fn displayAvalancheTotalMagnitude(avalanches: &Vec<Avalanche>, folder_path:
     let mut sortedKeys: Vec<usize> = avalancheTotalMagnitudeMap.keys().cloned().collect();
 
     sortedKeys.sort();
-    writeln!( writer, "Avalanche Magnitude, Number Avalanches")?;
     for totalMagnitude in sortedKeys {
-        writeln!( writer, "{}, {:?}", totalMagnitude, avalancheTotalMagnitudeMap.get(&totalMagnitude).unwrap().len())?;
+        writer.writeRow(&[totalMagnitude.to_string(), avalancheTotalMagnitudeMap.get(&totalMagnitude).unwrap().len().to_string()])?;
     }
     
     // flush the writer to ensure all data is written to the file
-    writer.flush()?;
+    writer.finish()?;
 
     Ok(())
 
@@ -1,13 +1,35 @@
 extern crate rand;
+use std::collections::HashMap;
 use rand::Rng;
 
 use crate::models::grain::Grain;
 use crate::models::grain::GrainState;
 use crate::models::location::Location;
 
-use crate::util::constants::{DEBUG, DEBUG_AVALANCHE};
+use crate::util::avalancheReplay::{ReplayEvent, isReplayRecording};
+use crate::util::constants::{DEBUG, DEBUG_AVALANCHE, CASCADE_MIN_AVALANCHE_GRAINS};
+use crate::util::sandpileUtil::{seededRng, stochasticRound};
 
 
+/**
+ * A grain set moving in an avalanche, a node of the causal tree of the avalanche
+ * the dropped grain is the root and each grain released by an impact is a child of the impacting grain
+ */
+#[derive(Clone, Debug)]
+pub struct CascadeNode {
+    pub grainId: u32,
+    // index of the node of the impacting grain in the cascade, None for the dropped grain
+    pub parent: Option<usize>,
+    // number of impacts between the dropped grain and this grain, 0 for the dropped grain
+    pub generation: usize,
+    // location the impacting grain hit, None for the dropped grain
+    pub impactLocation: Option<(i32, i32, i32)>,
+    // location the grain was released from, the drop position for the dropped grain
+    pub location: (i32, i32, i32),
+    // time step of the avalanche the grain was set moving in
+    pub step: usize,
+}
+
 /**
  * Model for an avalanche in the sandpile
  * An avalanche is a collection of grains that have been preturbed and are moving
@@ -24,9 +46,28 @@ pub struct Avalanche {
 
     // total count of grains involved in the avalanche
     pub totalGrainsInvolved: usize,
+
+    // total count of grains that left the pile during the avalanche
+    pub grainsOffPile: usize,
+
+    // number of time steps until all grains in the avalanche came to rest
+    pub duration: usize,
     
-    // direction of the avalanche, determines which
-    pub direction: usize,
+    // horizontal direction (dx, dy) of the impact that first perturbed a location in the avalanche
+    // (0, 0) until a location is perturbed or if the impacting grain fell straight down
+    pub direction: (i32, i32),
+
+    // state transitions of the grains in the avalanche, only collected when replays are recorded
+    pub replayEvents: Vec<ReplayEvent>,
+
+    // causal tree of the avalanche in the order the grains were set moving, a grain released
+    // more than once in the avalanche has a node for each release, only kept once the avalanche
+    // finishes when it involved at least CASCADE_MIN_AVALANCHE_GRAINS grains
+    pub cascade: Vec<CascadeNode>,
+    // latest cascade node of each grain in the avalanche, cleared when the avalanche finishes
+    cascadeNodeByGrain: HashMap<u32, usize>,
+    // number of grains set moving in each generation of the cascade, kept for every avalanche
+    generations: Vec<usize>,
 }
 
 impl Avalanche {
@@ -35,15 +76,70 @@ impl Avalanche {
             id,
             grainIds: Vec::<u32>::new(),
             locationIds: Vec::<u32>::new(),
-            direction: 0,
+            direction: (0, 0),
             totalMovement: 0,
             totalGrainsInvolved: 0,
+            grainsOffPile: 0,
+            duration: 0,
+            replayEvents: Vec::new(),
+            cascade: Vec::new(),
+            cascadeNodeByGrain: HashMap::new(),
+            generations: Vec::new(),
         }
     }
 
     pub fn addGrain(&mut self, grainId: u32) {
         self.grainIds.push(grainId);
         self.totalGrainsInvolved += 1;
+
+        // the first grain is the dropped grain at the root of the cascade
+        if self.cascade.is_empty() {
+            let location = Grain::getGrainById(grainId).map(|grain| (grain.x, grain.y, grain.z)).unwrap_or((0, 0, 0));
+            self.cascadeNodeByGrain.insert(grainId, 0);
+            self.cascade.push(CascadeNode { grainId, parent: None, generation: 0, impactLocation: None, location, step: self.duration });
+            self.generations.push(1);
+        }
+    }
+
+    /**
+     * Note a grain released by the impact of another grain as its child in the cascade
+     */
+    fn addCascadeNode(&mut self, grainId: u32, impactingGrainId: u32, impactLocation: (i32, i32, i32), location: (i32, i32, i32)) {
+        let parent = self.cascadeNodeByGrain.get(&impactingGrainId).copied();
+        let generation = parent.map(|parent| self.cascade[parent].generation + 1).unwrap_or(1);
+        self.cascadeNodeByGrain.insert(grainId, self.cascade.len());
+        self.cascade.push(CascadeNode { grainId, parent, generation, impactLocation: Some(impactLocation), location, step: self.duration });
+        if self.generations.len() <= generation {
+            self.generations.resize(generation + 1, 0);
+        }
+        self.generations[generation] += 1;
+    }
+
+    /**
+     * Number of grains set moving in each generation of the cascade
+     */
+    pub fn generationSizes(&self) -> &[usize] {
+        &self.generations
+    }
+
+    /**
+     * Avalanche with the given generation sizes and no tree, for testing the estimators built on them
+     */
+    #[cfg(test)]
+    pub fn withGenerationSizes(id: u32, generations: Vec<usize>) -> Self {
+        Avalanche { generations, ..Avalanche::new(id) }
+    }
+
+    /**
+     * Drop the lookup of cascade nodes once the avalanche has finished, and the causal tree too
+     * unless the avalanche involved at least CASCADE_MIN_AVALANCHE_GRAINS grains, so the trees of
+     * the many small avalanches do not build up over the run
+     */
+    pub fn finishCascade(&mut self) {
+        self.cascadeNodeByGrain = HashMap::new();
+        if CASCADE_MIN_AVALANCHE_GRAINS == 0 || self.totalGrainsInvolved < CASCADE_MIN_AVALANCHE_GRAINS {
+            self.cascade = Vec::new();
+        }
     }
 
     // update the movement of all the grains currently in the avalanche
@@ -55,6 +151,12 @@ impl Avalanche {
         // get the grain from the grain list
         let mut grain = crate::models::grain::Grain::getGrainById(grainId).unwrap();
 
+        // note where the grain started and the grains its impact releases for the replay
+        let recording = isReplayRecording();
+        let from = (grain.x, grain.y, grain.z);
+        let fromState = grain.state.clone();
+        let mut perturbationEvents = Vec::new();
+
 
         if DEBUG && DEBUG_AVALANCHE { println!( "\n|{:?}| START Update for Grain {} at location | x: {}, y: {}, z: {} | has energy {}", grain.state, grain.id, grain.x, grain.y, grain.z, grain.energy) };
         match grain.state {
@@ -76,22 +178,57 @@ impl Avalanche {
                 let mut location = crate::models::location::Location::getLocationByXyz(grain.x, grain.y, grain.z).unwrap();
                 if DEBUG && DEBUG_AVALANCHE { println!( "------- IMPACT Location {} is starting with {} grains which are: {:?}", location.id, location.grainIds.len(), location.grainIds) };  
 
-                // get the impact energy from the grain
-                let impactEnergy: usize = grain.energy;
+                // get the impact energy and direction from the grain, heavier grains deliver more energy
+                let mut rnd = seededRng();
+                let impactEnergy: usize = stochasticRound(grain.energy as f64 * grain.mass, &mut rnd);
+                let impactVelocity = grain.velocity;
 
-                location.incomingGrain(grain.id);
+                location.incomingGrain(grain.id, &mut rnd);
                 location.saveLocation();
 
+                // note the location as affected by the avalanche, its area is the number of distinct locations
+                if !self.locationIds.contains(&location.id) {
+                    self.locationIds.push(location.id);
+                }
+
                 
                 if DEBUG && DEBUG_AVALANCHE { println!( "------- IMPACT Location {} is ending with {} grains, avalanche now has {} grains", location.id, location.grainIds.len(), self.grainIds.len()) }; 
 
                 // if the location has more then 1 grain, check to see if the location has been perturbed by the impact
                 // call the location perturbation method
-                let mut rnd = rand::thread_rng();
                 let perturbedGrains: Vec<u32> = location.perturbation(impactEnergy, &mut rnd);
 
+                // the avalanche moves in the direction of the impact that started it
+                if !perturbedGrains.is_empty() && self.direction == (0, 0) {
+                    self.direction = (impactVelocity.0, impactVelocity.1);
+                }
+
                 // if there are grains that have been perturbed, add them to the avalanche
                 for perGrainId in perturbedGrains {
+                    // the perturbed grain moves off in the direction of the impacting grain
+                    let mut perGrain = Grain::getGrainById(perGrainId).unwrap();
+                    perGrain.velocity = impactVelocity;
+                    perGrain.saveGrain();
+
+                    // the released grain is a child of the impacting grain, an impacting grain released by its own impact carries on as the same node
+                    if perGrainId != grain.id {
+                        self.addCascadeNode(perGrainId, grain.id, (location.x, location.y, location.z), (perGrain.x, perGrain.y, perGrain.z));
+                    }
+
+                    // released grains were resting, the impacting grain records its own transition
+                    if recording && perGrainId != grain.id {
+                        perturbationEvents.push(ReplayEvent {
+                            step: self.duration,
+                            grainId: perGrainId,
+                            from: (perGrain.x, perGrain.y, perGrain.z),
+                            to: (perGrain.x, perGrain.y, perGrain.z),
+                            fromState: GrainState::Stationary,
+                            toState: perGrain.state.clone(),
+                            energy: perGrain.energy,
+                            perturbedBy: Some(grain.id),
+                        });
+                    }
+
                     // add the perturbed grain to the avalanche if it is not already in the avalanche
                     if !self.grainIds.contains(&perGrainId) {
                         self.addGrain(perGrainId);
@@ -110,13 +247,17 @@ impl Avalanche {
                 // remove the grain from the avalanche
                 toRemove.push(grain.id);
 
-                // ensure the grains energy is set to 0
+                // ensure the grains energy and momentum are set to 0
                 grain.energy = 0;
+                grain.velocity = (0, 0, 0);
                 grain.saveGrain();
             },
             GrainState::OffPile => {
                 // remove the grain from the avalanche
                 toRemove.push(grain.id);
+
+                // note the grain as outflow from the pile
+                self.grainsOffPile += 1;
             },
         }
 
@@ -124,6 +265,23 @@ impl Avalanche {
         // Remove the grains that were marked for removal
         //println!( "Removing grains {:?} avalanche contains before removal: {} grains", toRemove, self.grainIds.len());
         self.grainIds.retain(|id| !toRemove.contains(id));
+
+        // record the transition of the grain followed by the grains its impact released
+        if recording {
+            if let Some(updated) = Grain::getGrainById(grainId) {
+                self.replayEvents.push(ReplayEvent {
+                    step: self.duration,
+                    grainId,
+                    from,
+                    to: (updated.x, updated.y, updated.z),
+                    fromState,
+                    toState: updated.state,
+                    energy: updated.energy,
+                    perturbedBy: None,
+                });
+            }
+            self.replayEvents.append(&mut perturbationEvents);
+        }
         //println!( "Avalanche now has {} grains", self.grainIds.len());
         
     }
@@ -0,0 +1,176 @@
+use lazy_static::lazy_static;
+use std::io;
+
+// internal models
+use crate::models::location::Location;
+
+// constants
+use crate::util::constants::{AVALANCHE_SIZE_RULE, AVALANCHE_SIZE_RULE_NAMES, BASE_AVALANCHE_SIZE, BASE_AVALANCHE_SIZE_PERCENT};
+use crate::util::constants::{AVALANCHE_GRAINS_PER_ENERGY, CRITICAL_OCCUPANCY, AVALANCHE_SIZE_TABLE};
+
+
+lazy_static! {
+    // rule used by every location to size its avalanches, chosen by AVALANCHE_SIZE_RULE
+    static ref CONFIGURED_RULE: Box<dyn AvalancheSizeRule> = avalancheSizeRuleByName(AVALANCHE_SIZE_RULE)
+        .unwrap_or_else(|| panic!("unknown AVALANCHE_SIZE_RULE \"{}\", use one of {}", AVALANCHE_SIZE_RULE, AVALANCHE_SIZE_RULE_NAMES.join(", ")));
+}
+
+/**
+ * Decides how many grains a perturbed location releases once the energy reaching it is more than its resilience
+ *
+ * extraGrains is the ALPHA_AVALANCHE_SIZE power-law sample drawn for every avalanche, rules
+ * modelling noisy avalanches add it to their size and deterministic rules ignore it
+ * the size is capped to the grains at the location by the caller
+ */
+pub trait AvalancheSizeRule: Send + Sync {
+    // name of the rule as set in AVALANCHE_SIZE_RULE
+    fn name(&self) -> &'static str;
+
+    // description of the rule and its parameters for the run configuration and manifest
+    fn describe(&self) -> String;
+
+    // number of grains released by the location when the energy reaching it is totalEnergy
+    fn avalancheSize(&self, location: &Location, totalEnergy: usize, extraGrains: usize) -> usize;
+}
+
+/**
+ * BASE_AVALANCHE_SIZE grains plus the power-law extra, the former BASE_AVALANCHE_METHOD 1
+ */
+pub struct FixedSizeRule {
+    pub baseSize: usize,
+}
+
+impl AvalancheSizeRule for FixedSizeRule {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    fn describe(&self) -> String {
+        format!("{} grains plus the ALPHA_AVALANCHE_SIZE extra", self.baseSize)
+    }
+
+    fn avalancheSize(&self, location: &Location, totalEnergy: usize, extraGrains: usize) -> usize {
+        self.baseSize + extraGrains
+    }
+}
+
+/**
+ * A percentage of the grains at the location plus the power-law extra, the former BASE_AVALANCHE_METHOD 2
+ */
+pub struct PercentRule {
+    pub percent: f64,
+}
+
+impl AvalancheSizeRule for PercentRule {
+    fn name(&self) -> &'static str {
+        "percent"
+    }
+
+    fn describe(&self) -> String {
+        format!("{} of the grains at the location plus the ALPHA_AVALANCHE_SIZE extra", self.percent)
+    }
+
+    fn avalancheSize(&self, location: &Location, totalEnergy: usize, extraGrains: usize) -> usize {
+        (location.grainIds.len() as f64 * self.percent) as usize + extraGrains
+    }
+}
+
+/**
+ * Grains in proportion to the energy beyond the resilience of the location plus the power-law extra,
+ * harder hits release more grains
+ */
+pub struct EnergyProportionalRule {
+    pub grainsPerEnergy: f64,
+}
+
+impl AvalancheSizeRule for EnergyProportionalRule {
+    fn name(&self) -> &'static str {
+        "energy"
+    }
+
+    fn describe(&self) -> String {
+        format!("{} grains for each unit of energy beyond the resilience plus the ALPHA_AVALANCHE_SIZE extra", self.grainsPerEnergy)
+    }
+
+    fn avalancheSize(&self, location: &Location, totalEnergy: usize, extraGrains: usize) -> usize {
+        let excessEnergy = totalEnergy.saturating_sub(location.resilience);
+        (excessEnergy as f64 * self.grainsPerEnergy).ceil() as usize + extraGrains
+    }
+}
+
+/**
+ * Every grain above a critical occupancy, as toppling in the BTW sandpile, no power-law extra
+ */
+pub struct ThresholdExcessRule {
+    pub criticalOccupancy: usize,
+}
+
+impl AvalancheSizeRule for ThresholdExcessRule {
+    fn name(&self) -> &'static str {
+        "threshold"
+    }
+
+    fn describe(&self) -> String {
+        format!("the grains above an occupancy of {}", self.criticalOccupancy)
+    }
+
+    fn avalancheSize(&self, location: &Location, totalEnergy: usize, extraGrains: usize) -> usize {
+        location.getOccupancy().saturating_sub(self.criticalOccupancy)
+    }
+}
+
+/**
+ * Grains looked up by the energy beyond the resilience of the location, the table holds
+ * (smallest excess energy, grains released) rows in increasing energy order and the last row
+ * at or below the excess energy is used, no power-law extra
+ */
+pub struct LookupTableRule {
+    pub table: Vec<(usize, usize)>,
+}
+
+impl AvalancheSizeRule for LookupTableRule {
+    fn name(&self) -> &'static str {
+        "table"
+    }
+
+    fn describe(&self) -> String {
+        let rows: Vec<String> = self.table.iter().map(|(energy, grains)| format!("{} grains from an excess energy of {}", grains, energy)).collect();
+        rows.join(", ")
+    }
+
+    fn avalancheSize(&self, location: &Location, totalEnergy: usize, extraGrains: usize) -> usize {
+        let excessEnergy = totalEnergy.saturating_sub(location.resilience);
+        self.table.iter().take_while(|(energy, _)| *energy <= excessEnergy).last().map(|(_, grains)| *grains).unwrap_or(0)
+    }
+}
+
+/**
+ * Build one of the AVALANCHE_SIZE_RULE_NAMES with its parameters from the constants, None for an unknown name
+ */
+pub fn avalancheSizeRuleByName(name: &str) -> Option<Box<dyn AvalancheSizeRule>> {
+    match name {
+        "fixed" => Some(Box::new(FixedSizeRule { baseSize: BASE_AVALANCHE_SIZE })),
+        "percent" => Some(Box::new(PercentRule { percent: BASE_AVALANCHE_SIZE_PERCENT })),
+        "energy" => Some(Box::new(EnergyProportionalRule { grainsPerEnergy: AVALANCHE_GRAINS_PER_ENERGY })),
+        "threshold" => Some(Box::new(ThresholdExcessRule { criticalOccupancy: CRITICAL_OCCUPANCY })),
+        "table" => Some(Box::new(LookupTableRule { table: AVALANCHE_SIZE_TABLE.to_vec() })),
+        _ => None,
+    }
+}
+
+/**
+ * Check AVALANCHE_SIZE_RULE names a rule before the run starts, the rule is otherwise only built at the first perturbation
+ */
+pub fn checkAvalancheSizeRule() -> io::Result<()> {
+    if avalancheSizeRuleByName(AVALANCHE_SIZE_RULE).is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown AVALANCHE_SIZE_RULE \"{}\", use one of {}", AVALANCHE_SIZE_RULE, AVALANCHE_SIZE_RULE_NAMES.join(", "))));
+    }
+    Ok(())
+}
+
+/**
+ * Get the rule set by AVALANCHE_SIZE_RULE
+ */
+pub fn configuredAvalancheSizeRule() -> &'static dyn AvalancheSizeRule {
+    CONFIGURED_RULE.as_ref()
+}
@@ -1,19 +1,23 @@
 // external modules
 extern crate rand;
 use rand::Rng;
+use rand::distributions::WeightedIndex;
 use std::collections::HashMap;
 
 use std::sync::Mutex;
 use lazy_static::lazy_static;
 
 // constants
-use crate::util::constants::{ALPHA_LANDING, X_SIZE, Y_SIZE, Z_SIZE};
+use crate::util::constants::{LANDING_DISTRIBUTION, X_SIZE, Y_SIZE, Z_SIZE};
 use crate::util::constants::{DEBUG, DEBUG_LOCATION, DEBUG_INIT, DEBUG_LOCAL_NEIGHBORS, DEBUG_AVALANCHE, DEBUG_GRAIN_IMPACT};
-use crate::util::constants::{TOTAL_GRAINS, TERMINAL_FREE_FALL_SPEED};
+use crate::util::constants::{TOTAL_GRAINS, TERMINAL_FREE_FALL_SPEED, GRAIN_INERTIA, ROLLING_FRICTION, GRAIN_TYPES};
 
 
+// internal models
+use crate::models::location::Neighbor;
+
 // internal utilities
-use crate::util::sandpileUtil::{normalizedPowerLawByOrdersOfMagnitudeWithAlpha};
+use crate::util::sandpileUtil::{normalizedPowerLawByOrdersOfMagnitudeWithAlpha, seededRng, stochasticRound};
 
 #[derive(PartialEq)]
 #[derive(Clone)]
@@ -45,8 +49,19 @@ pub struct Grain {
     // 0 if stationary, > 0 if in motion
     // energy is transferred to other grains on impact
     pub energy: usize,
+    // direction of the last move of the grain (dx, dy, dz), (0, 0, 0) if stationary
+    // rolling grains favor this direction and grains perturbed by an impact inherit it
+    pub velocity: (i32, i32, i32),
     // state of the grain
     pub state: GrainState,
+    // index of the grains type in GRAIN_TYPES
+    pub grainType: usize,
+    // capacity of a location the grain takes up
+    pub size: usize,
+    // multiplier of the energy the grain delivers on impact
+    pub mass: f64,
+    // energy needed on top of the resilience of a location to release the grain
+    pub cohesion: usize,
 }
 
 /**
@@ -58,16 +73,23 @@ impl Grain {
     // Constructor to create a new Grain with a specific id
     pub fn new(id: u32) -> Grain {
         let (x, y, z) = Grain::determineInitialPosition(id);
+        let grainType = Grain::determineGrainType();
+        let (_, _, size, mass, cohesion) = GRAIN_TYPES[grainType];
         Grain { 
             id, 
             // current energy of the grain, 
             // 0 if stationary, > 0 if in motion
             // energy is transferred to other grains on impact
             energy: 0,
+            velocity: (0, 0, 0),
             x,
             y,
             z,    
             state: GrainState::Unknown,        
+            grainType,
+            size,
+            mass,
+            cohesion,
         }
     }
 
@@ -99,6 +121,14 @@ impl Grain {
         grains.get(&id).cloned()
     }
 
+    /**
+     * Get the ids of every grain in the system
+     */
+    pub fn getAllGrainIds() -> Vec<u32> {
+        let grains: std::sync::MutexGuard<'_, HashMap<u32, Grain>> = GRAINS_BY_ID.lock().unwrap();
+        grains.keys().cloned().collect()
+    }
+
     /**
      * Save the grain to the system
      * Handles adding the grain to the grains_by_location and grains_by_id HashMaps
@@ -124,10 +154,11 @@ impl Grain {
         if self.z > 0 {
             let below_location = crate::models::location::Location::getLocationByXyz(self.x, self.y, self.z-1).unwrap();
             // check to see if the location is empty space (not part of the pile) this is known because it will have a capacity and resilience of 0
-            if location.capacity == 0 && location.resilience == 0 || ( self.z > 0 && below_location.grainIds.len() < below_location.capacity ) {
-                // the grain is in free fall
+            if location.capacity == 0 && location.resilience == 0 || ( self.z > 0 && below_location.getOccupancy() + self.size <= below_location.capacity ) {
+                // the grain is in free fall, keeping any horizontal direction it had
                 self.z -= 1;
                 movement += 1;
+                self.velocity.2 = -1;
 
                 // if the grain is in free fall, increase the energy up to the terminal velocity
                 if self.energy < TERMINAL_FREE_FALL_SPEED {
@@ -152,14 +183,23 @@ impl Grain {
 
     /**
      * Roll the grain to a lower location
+     * the grain gains a unit of energy for each level it drops, at least one for every roll, and loses
+     * ROLLING_FRICTION times the roughness of the location it rolls over
      */
     pub fn roll(&mut self) -> usize {
+        self.rollWithFriction(ROLLING_FRICTION)
+    }
+
+    /**
+     * Roll the grain to a lower location losing friction times the roughness of the location it rolls over
+     */
+    pub fn rollWithFriction(&mut self, rollingFriction: f64) -> usize {
 
         // keep track of the grain movement
         let mut movement: usize = 0;
 
         // get the lower neighborhood for this location
-        let lowerNeighborhood: Vec<(i32, i32, i32)> = crate::models::location::Location::getLowerNeighborhood(self.x, self.y, self.z);
+        let lowerNeighborhood: Vec<Neighbor> = crate::models::location::Location::getLowerNeighborhoodWithDirections(self.x, self.y, self.z);
 
         // print out the lower neighborhood which contains a Vec<(i32, i32, i32)>
         // if DEBUG && DEBUG_LOCAL_NEIGHBORS {
@@ -171,15 +211,23 @@ impl Grain {
 
         // check to see if the grain can fall to a lower location
         if lowerNeighborhood.len() > 0 {
-            // pick a location at random from the lower neighborhood and fall to it.
-            let mut rnd = rand::thread_rng();
-            let locationIndex = rnd.gen_range(0..lowerNeighborhood.len());
+            // pick a location from the lower neighborhood, favoring the direction the grain is moving in, and fall to it.
+            let mut rnd = seededRng();
+            let locationIndex = self.chooseRollDirection(&lowerNeighborhood, &mut rnd);
+            let (location, direction) = lowerNeighborhood[locationIndex];
+
+            // friction with the location the grain is rolling over
+            let roughness = crate::models::location::Location::getLocationByXyz(self.x, self.y, self.z).map(|location| location.roughness).unwrap_or(1.0);
+            let friction = stochasticRound(rollingFriction * roughness, &mut rnd);
 
             // move the grain to the new location
-            self.x = lowerNeighborhood[locationIndex].0;
-            self.y = lowerNeighborhood[locationIndex].1;
-            self.z = lowerNeighborhood[locationIndex].2;
-            self.energy += 1;
+            let previousZ = self.z;
+            self.x = location.0;
+            self.y = location.1;
+            self.z = location.2;
+            self.velocity = (direction.0, direction.1, self.z - previousZ);
+            // every roll gains at least one unit of energy, rolling across the lowest level included
+            self.energy = (self.energy + (previousZ - self.z).max(1) as usize).saturating_sub(friction);
             
             movement += 1;
             
@@ -209,6 +257,35 @@ impl Grain {
 
     }
 
+    /**
+     * Choose the index of the lower neighbor the grain rolls to, each neighbor is weighted by
+     * e^(GRAIN_INERTIA * cos(angle between the neighbor and the grains horizontal direction))
+     * so a grain keeps rolling the way it is moving, neighbors straight below and grains without
+     * a horizontal direction are weighted 1, with a GRAIN_INERTIA of 0 every neighbor is equally likely
+     */
+    fn chooseRollDirection(&self, lowerNeighborhood: &[Neighbor], rnd: &mut impl Rng) -> usize {
+        let (vx, vy) = (self.velocity.0, self.velocity.1);
+        if GRAIN_INERTIA == 0.0 || (vx == 0 && vy == 0) {
+            return rnd.gen_range(0..lowerNeighborhood.len());
+        }
+
+        let speed = ((vx * vx + vy * vy) as f64).sqrt();
+        let exponents: Vec<f64> = lowerNeighborhood.iter().map(|&(_, (dx, dy))| {
+            if dx == 0 && dy == 0 {
+                return 0.0;
+            }
+            let cos = (vx * dx + vy * dy) as f64 / (speed * ((dx * dx + dy * dy) as f64).sqrt());
+            GRAIN_INERTIA * cos
+        }).collect();
+
+        // shift the exponents so the largest weight is 1, a large GRAIN_INERTIA would overflow e^x otherwise
+        let largest = exponents.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = exponents.iter().map(|exponent| (exponent - largest).exp()).collect();
+
+        let weightedIndex = WeightedIndex::new(&weights).unwrap();
+        rnd.sample(&weightedIndex)
+    }
+
     pub fn displayAllGrainsLocations() {
         let grains_by_id_guard = GRAINS_BY_ID.lock().unwrap();
         let mut grains: Vec<_> = grains_by_id_guard.iter().collect();
@@ -241,6 +318,22 @@ impl Grain {
     
 
 
+    /**
+     * Draw the type of a new grain from the GRAIN_TYPES mixture
+     *
+     * @return the index of the grain type in GRAIN_TYPES
+     */
+    fn determineGrainType() -> usize {
+        if GRAIN_TYPES.len() == 1 {
+            return 0;
+        }
+
+        let mut rnd = seededRng();
+        let fractions: Vec<f64> = GRAIN_TYPES.iter().map(|(_, fraction, _, _, _)| *fraction).collect();
+        let weightedIndex = WeightedIndex::new(&fractions).unwrap();
+        rnd.sample(&weightedIndex)
+    }
+
     /**
      * Determine the initial position of the grain
      * 
@@ -249,7 +342,7 @@ impl Grain {
      */
     fn determineInitialPosition(id: u32) -> (i32, i32, i32) {
 
-        let mut rnd = rand::thread_rng();
+        let mut rnd = seededRng();
 
         // start with center of the array
         let mut x = X_SIZE / 2;
@@ -257,8 +350,8 @@ impl Grain {
 
         // find the gains landing variance from center with more variance in the center
         // using an alpha of 1.5
-        let mut xVariance = normalizedPowerLawByOrdersOfMagnitudeWithAlpha(ALPHA_LANDING, &mut rnd);
-        let mut yVariance = normalizedPowerLawByOrdersOfMagnitudeWithAlpha(ALPHA_LANDING, &mut rnd);
+        let mut xVariance = LANDING_DISTRIBUTION.sample(&mut rnd).max(0.0);
+        let mut yVariance = LANDING_DISTRIBUTION.sample(&mut rnd).max(0.0);
 
         // check that the variance is within the bounds of the array
         if xVariance > x as f64 {
@@ -11,6 +11,8 @@ use lazy_static::lazy_static;
 // internal modules
 use crate::util::sandpileUtil::normalizedPowerLawByOrdersOfMagnitude;
 use crate::util::sandpileUtil::normalizedPowerLawByOrdersOfMagnitudeWithAlpha;
+use crate::util::sandpileUtil::stochasticRound;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput};
 
 // Constants
 use crate::util::constants::DEBUG;
@@ -22,24 +24,37 @@ use crate::util::constants::DEBUG_DISPLAY_PILE;
 use crate::util::constants::DEBUG_INIT;
 use crate::util::constants::BASE_CAPACITY;
 use crate::util::constants::BASE_RESILIENCE;
-use crate::util::constants::ALPHA_EXTRA_ENERGY;
-use crate::util::constants::ALPHA_AVALANCHE_SIZE;
-use crate::util::constants::ALPHA_LOCATION_EXTRA_CAPACITY;
-use crate::util::constants::ALPHA_LOCATION_EXTRA_RESILIENCE;
+use crate::util::constants::EXTRA_ENERGY_DISTRIBUTION;
+use crate::util::constants::AVALANCHE_SIZE_DISTRIBUTION;
+use crate::util::constants::EXTRA_CAPACITY_DISTRIBUTION;
+use crate::util::constants::EXTRA_RESILIENCE_DISTRIBUTION;
 use crate::util::constants::X_SIZE;
 use crate::util::constants::Y_SIZE;
 use crate::util::constants::Z_SIZE;
-use crate::util::constants::BASE_AVALANCHE_SIZE;
-use crate::util::constants::BASE_AVALANCHE_METHOD;
-use crate::util::constants::BASE_AVALANCHE_SIZE_PERCENT;
+use crate::util::constants::ENERGY_RADIATION_RANGE;
+use crate::util::constants::ENERGY_RADIATION_ATTENUATION;
+use crate::util::constants::RESTITUTION_COEFFICIENT;
+use crate::util::constants::MAX_REBOUND_ENERGY;
+use crate::util::constants::MIN_LOCATION_ROUGHNESS;
+use crate::util::constants::MAX_LOCATION_ROUGHNESS;
+use crate::util::constants::COMPACTION_IMPACTS;
+use crate::util::constants::COMPACTION_CAPACITY_GAIN;
+use crate::util::constants::COMPACTION_RESILIENCE_GAIN;
+use crate::util::constants::MAX_COMPACTIONS;
+use crate::util::constants::AVALANCHE_WEAKENING;
+use crate::util::constants::GRAIN_TYPES;
 
 // internal models
 use crate::models::grain::Grain;
 use crate::models::avalanche::Avalanche;
 use crate::models::grain::GrainState;
+use crate::models::avalancheSizeRule::configuredAvalancheSizeRule;
 
 use super::avalanche;
 
+// a neighboring location (x, y, z) with its horizontal direction (dx, dy) from the location
+pub type Neighbor = ((i32, i32, i32), (i32, i32));
+
 
 //Static HashMap to store all the locations in the sandpile
 lazy_static! { // Require the lazy_static crate to handle static Mutex
@@ -53,6 +68,10 @@ lazy_static! { // Require the lazy_static crate to handle static Mutex
  * Locations are static and do not move, they represent a point in the 3D space
  * They have a capacity for grains and a resilience to perturbations which is 
  * determined as a random value between 1 and 6
+ * and a roughness that takes energy from grains rolling over them
+ * Impacts compact a location, raising its capacity and resilience, avalanches weaken it
+ * and a weakened location heals back to its intact resilience over time (see COMPACTION_IMPACTS,
+ * AVALANCHE_WEAKENING and HEALING_INTERVAL)
  */
 #[derive(Clone)]
 pub struct Location {
@@ -63,14 +82,23 @@ pub struct Location {
     pub capacity: usize,
     pub grainIds: Vec::<u32>,
     pub resilience: usize,
+    // multiplier of the ROLLING_FRICTION of grains rolling over the location
+    pub roughness: f64,
+    // number of grains that have hit the location
+    pub impacts: usize,
+    // number of times the location has been compacted by impacts
+    pub compactions: usize,
+    // resilience the location heals back to after being weakened, raised by compaction
+    pub intactResilience: usize,
 }
 
 impl Location {
     pub fn new(id: u32, x: i32, y: i32, z: i32, rnd: &mut impl Rng ) -> Self {
 
-        // get the order of magnitude of a random power-law distribution
-        let additionalCap = normalizedPowerLawByOrdersOfMagnitudeWithAlpha( ALPHA_LOCATION_EXTRA_CAPACITY, rnd ) as usize;
-        let additionalRes = normalizedPowerLawByOrdersOfMagnitudeWithAlpha( ALPHA_LOCATION_EXTRA_RESILIENCE, rnd ) as usize;
+        // draw the additional capacity and resilience, by default the order of magnitude of a random power-law distribution
+        let additionalCap = EXTRA_CAPACITY_DISTRIBUTION.sample(rnd) as usize;
+        let additionalRes = EXTRA_RESILIENCE_DISTRIBUTION.sample(rnd) as usize;
+        let roughness = if MAX_LOCATION_ROUGHNESS > MIN_LOCATION_ROUGHNESS { rnd.gen_range(MIN_LOCATION_ROUGHNESS..MAX_LOCATION_ROUGHNESS) } else { MIN_LOCATION_ROUGHNESS };
         Location {
             id,
             x,
@@ -79,6 +107,10 @@ impl Location {
             capacity: BASE_CAPACITY + additionalCap,  
             grainIds: Vec::<u32>::new(),    // Initialize as empty vector
             resilience: BASE_RESILIENCE + additionalRes,  
+            roughness,
+            impacts: 0,
+            compactions: 0,
+            intactResilience: BASE_RESILIENCE + additionalRes,
         }
     }
     pub fn emptySpace(id: u32, x: i32, y: i32, z: i32) -> Self {
@@ -91,6 +123,10 @@ impl Location {
             capacity: 0,  
             grainIds: Vec::<u32>::new(),    // Initialize as empty vector
             resilience: 0,  
+            roughness: 0.0,
+            impacts: 0,
+            compactions: 0,
+            intactResilience: 0,
         }
     }
 
@@ -148,12 +184,20 @@ impl Location {
 
     /**
      * Attempt to add a grain to the location
-     * 
+     * a grain hitting a full location keeps RESTITUTION_COEFFICIENT of its energy, up to MAX_REBOUND_ENERGY, and rolls on,
+     * a grain left without energy comes to rest on top of the full location when there is room there,
+     * otherwise it keeps rolling down the slope as grains only come to rest where there is room
      */
-    pub fn incomingGrain(&mut self, grainId: u32) -> usize {
+    pub fn incomingGrain(&mut self, grainId: u32, rnd: &mut impl Rng) -> usize {
+
+        // the impact packs the location down
+        self.recordImpact();
+
+        // get the grain by its id
+        let grainSize = Grain::getGrainById(grainId).map(|grain| grain.size).unwrap_or(1);
 
         // Check if the location has capacity to add a grain
-        if self.grainIds.len() < self.capacity {
+        if self.getOccupancy() + grainSize <= self.capacity {
             if DEBUG && DEBUG_AVALANCHE { println!("Location x: {}, y: {}, z: {} has capacity to add grain {}, at impact grain total: {} and capacity {}", self.x, self.y, self.z, grainId, self.grainIds.len(), self.capacity) };
             // the location is not full, add the grain
             self.grainIds.push(grainId);
@@ -164,9 +208,10 @@ impl Location {
             // set the grain state to sationary
             grain.state = GrainState::Stationary;
 
-            // remove the grains energy
+            // remove the grains energy and momentum
             let energy = grain.energy;
             grain.energy = 0;
+            grain.velocity = (0, 0, 0);
 
             // note that the grain stoped at this location
             //println!("Grain {} stopped at location x: {}, y: {}, z: {} Grian x: {}, y: {}, z: {}", grain.id, self.x, self.y, self.z, grain.x, grain.y, grain.z);
@@ -189,9 +234,22 @@ impl Location {
 
             let energy: usize = grain.energy;
             // reduce the grains energy from the impact
-            if grain.energy > 1 {
-                grain.energy = 1;
+            grain.energy = stochasticRound(energy as f64 * RESTITUTION_COEFFICIENT, rnd).min(MAX_REBOUND_ENERGY);
+
+            if grain.energy == 0 {
+                // the grain has no energy left to roll on, it stops on the slope on top of the full location
+                if let Some(mut above) = Location::getLocationByXyz(self.x, self.y, self.z + 1) {
+                    if above.getOccupancy() + grainSize <= above.capacity {
+                        if DEBUG && DEBUG_AVALANCHE { println!("Grain {} stopped on top of full location x: {}, y: {}, z: {}", grainId, self.x, self.y, self.z) };
+                        above.grainIds.push(grainId);
+                        above.saveLocation();
+                        grain.z = above.z;
+                        grain.state = GrainState::Stationary;
+                        grain.velocity = (0, 0, 0);
+                    }
+                }
             }
+
             // save the grain state
             grain.saveGrain();
 
@@ -201,11 +259,39 @@ impl Location {
     }
 
 
+    /**
+     * Count an impact on the location, every COMPACTION_IMPACTS impacts the location is compacted
+     * and gains capacity and resilience, up to MAX_COMPACTIONS times
+     */
+    fn recordImpact(&mut self) {
+        self.impacts += 1;
+
+        if COMPACTION_IMPACTS != 0 && self.capacity > 0 && self.compactions < MAX_COMPACTIONS && self.impacts.is_multiple_of(COMPACTION_IMPACTS) {
+            self.compactions += 1;
+            self.capacity += COMPACTION_CAPACITY_GAIN;
+            self.resilience += COMPACTION_RESILIENCE_GAIN;
+            self.intactResilience += COMPACTION_RESILIENCE_GAIN;
+            if DEBUG && DEBUG_LOCATION { println!("Location x: {}, y: {}, z: {} compacted to capacity {} and resilience {}", self.x, self.y, self.z, self.capacity, self.resilience) };
+        }
+    }
+
+    /**
+     * Heal every weakened location by a unit of resilience, up to its intact resilience
+     */
+    pub fn healLocations() {
+        let mut locations = LOCATIONS.lock().unwrap();
+        for location in locations.values_mut() {
+            if location.resilience < location.intactResilience {
+                location.resilience += 1;
+            }
+        }
+    }
+
     pub fn perturbation(&mut self, incomingGrainEnergy: usize, rnd: &mut impl Rng) -> Vec<u32> {
-        // get the order of magnitude of a random power-law distribution
-        // as random additional energy representing a perturbation of the location
+        // draw random additional energy representing a perturbation of the location,
+        // by default the order of magnitude of a random power-law distribution
         // add this value to the grains current energy
-        let additionalEnergy = normalizedPowerLawByOrdersOfMagnitudeWithAlpha(ALPHA_EXTRA_ENERGY, rnd);
+        let additionalEnergy = EXTRA_ENERGY_DISTRIBUTION.sample(rnd);
         let totalEnergy = incomingGrainEnergy + additionalEnergy as usize;
 
         // determine if this perturbation will cause an avalanche
@@ -213,20 +299,66 @@ impl Location {
             println!("resilience {} < total energy: {} ({} + {}) for location {}, {}, {}", self.resilience, totalEnergy, incomingGrainEnergy, additionalEnergy, self.x, self.y, self.z); 
         }
 
+        let mut looseGrainIds = self.releaseGrains(totalEnergy, rnd);
+
+        // the energy of the impact radiates out to the surrounding locations
+        looseGrainIds.append(&mut Location::radiateEnergy(self.x, self.y, self.z, totalEnergy, rnd));
+
+        looseGrainIds
+    }
+
+    /**
+     * Spread the energy of an impact at x, y, z to the locations around it, a location at a distance
+     * of d (the largest of the x, y and z offsets) up to ENERGY_RADIATION_RANGE receives
+     * energy * ENERGY_RADIATION_ATTENUATION^d and releases grains if that is more than its resilience
+     * returns the ids of all the grains released, none when ENERGY_RADIATION_RANGE is 0
+     */
+    pub fn radiateEnergy(x: i32, y: i32, z: i32, energy: usize, rnd: &mut impl Rng) -> Vec<u32> {
+        let mut looseGrainIds: Vec<u32> = Vec::new();
+        let range = ENERGY_RADIATION_RANGE;
+
+        for dz in -range..=range {
+            for dy in -range..=range {
+                for dx in -range..=range {
+                    if dx == 0 && dy == 0 && dz == 0 {
+                        continue;
+                    }
+                    let Some(mut location) = Location::getLocationByXyz(x + dx, y + dy, z + dz) else {
+                        continue;
+                    };
+                    // only pile locations holding grains can release them
+                    if location.capacity == 0 || location.grainIds.is_empty() {
+                        continue;
+                    }
+
+                    let distance = dx.abs().max(dy.abs()).max(dz.abs());
+                    let radiatedEnergy = (energy as f64 * ENERGY_RADIATION_ATTENUATION.powi(distance)) as usize;
+                    if radiatedEnergy == 0 {
+                        continue;
+                    }
+
+                    if DEBUG && DEBUG_AVALANCHE { println!("Energy {} radiated from x: {}, y: {}, z: {} to x: {}, y: {}, z: {}", radiatedEnergy, x, y, z, location.x, location.y, location.z) };
+                    looseGrainIds.append(&mut location.releaseGrains(radiatedEnergy, rnd));
+                }
+            }
+        }
+
+        looseGrainIds
+    }
+
+    /**
+     * Release grains from the location if the energy reaching it is more than its resilience,
+     * the grains released and any grains above the location start rolling
+     * returns the ids of the released grains, empty if the location held
+     */
+    pub fn releaseGrains(&mut self, totalEnergy: usize, rnd: &mut impl Rng) -> Vec<u32> {
         if self.resilience < totalEnergy && self.z > 0 {
             // start an avalanche
             if DEBUG && DEBUG_AVALANCHE { println!("**************************!! Avalanche started at location x: {}, y: {}, z: {} location contains {} grains (before pertubation)", self.x, self.y, self.z, self.grainIds.len()) };
-            // set the size of the avalanche
-            let mut avalancheSize;
-            if BASE_AVALANCHE_METHOD == 1 {
-                // use a fixed size for the avalanche
-                avalancheSize = BASE_AVALANCHE_SIZE + normalizedPowerLawByOrdersOfMagnitudeWithAlpha(ALPHA_AVALANCHE_SIZE, rnd) as usize;
-                if DEBUG && DEBUG_AVALANCHE { println!("+++++ Avalanche size: {}", avalancheSize) };
-            } else {
-                // use a percentage of the grains at the location for the avalanche
-                avalancheSize = (self.grainIds.len() as f64 * BASE_AVALANCHE_SIZE_PERCENT) as usize + normalizedPowerLawByOrdersOfMagnitudeWithAlpha(ALPHA_AVALANCHE_SIZE, rnd) as usize;
-                if DEBUG && DEBUG_AVALANCHE { println!("+++++ Avalanche size: {}", avalancheSize) };
-            }
+            // set the size of the avalanche with the configured rule (see AVALANCHE_SIZE_RULE)
+            let extraGrains = AVALANCHE_SIZE_DISTRIBUTION.sample(rnd) as usize;
+            let mut avalancheSize = configuredAvalancheSizeRule().avalancheSize(self, totalEnergy, extraGrains);
+            if DEBUG && DEBUG_AVALANCHE { println!("+++++ Avalanche size: {} ({} rule)", avalancheSize, configuredAvalancheSizeRule().name()) };
             
             // ensure that the base avalanche size is not larger than the number of grains
             if self.grainIds.len() < avalancheSize {
@@ -236,9 +368,15 @@ impl Location {
             if DEBUG && DEBUG_AVALANCHE { println!("+++++ Avalanche size: {}", avalancheSize) };
             let mut looseGrainIds: Vec<u32> = Vec::new();
 
-            // return the grains that are part of the avalanche
-            for i in 0..avalancheSize {
-                looseGrainIds.push(self.grainIds.pop().unwrap());
+            // return the grains that are part of the avalanche, starting from the top of the location
+            // cohesive grains hold on unless the energy also overcomes their cohesion
+            let mut i = self.grainIds.len();
+            while i > 0 && looseGrainIds.len() < avalancheSize {
+                i -= 1;
+                let cohesion = Grain::getGrainById(self.grainIds[i]).map(|grain| grain.cohesion).unwrap_or(0);
+                if self.resilience + cohesion < totalEnergy {
+                    looseGrainIds.push(self.grainIds.remove(i));
+                }
             }
 
             
@@ -288,6 +426,9 @@ impl Location {
             }
             //self.grainIds.retain(|&x| x != looseGrainIds[0]);
 
+            // releasing grains weakens the location until it heals
+            self.resilience = self.resilience.saturating_sub(AVALANCHE_WEAKENING);
+
             // save the location
             self.saveLocation();
             // if (ceilingGrains.len() > 0) {
@@ -307,7 +448,16 @@ impl Location {
      * Get the lower neighborhood of a location by its x, y, z coordinates
      */
     pub fn getLowerNeighborhood( x: i32, y: i32, z: i32 ) -> Vec<(i32, i32, i32)> {
-        let mut lowerNeighborhood: Vec<(i32, i32, i32)> = Vec::with_capacity(9);
+        Location::getLowerNeighborhoodWithDirections(x, y, z).into_iter().map(|(location, _)| location).collect()
+    }
+
+    /**
+     * Get the lower neighborhood of a location with the horizontal direction (dx, dy) of each
+     * neighbor from the location, the direction is kept for the (-1, -1, -1) off pile markers
+     * so a rolling grain can carry on in the direction it is moving off the edge of the pile
+     */
+    pub fn getLowerNeighborhoodWithDirections( x: i32, y: i32, z: i32 ) -> Vec<Neighbor> {
+        let mut lowerNeighborhood: Vec<Neighbor> = Vec::with_capacity(9);
 
         let minX = if x == 0 { 0 } else { x-1 } as i32;
         let maxX = if x+1 < X_SIZE { x+1 } else { X_SIZE } as i32;
@@ -322,19 +472,19 @@ impl Location {
             for j in minY..=maxY {
                 if z > 0 {
                     // If not at the ground level, normal neighborhood logic
-                    lowerNeighborhood.push((i, j, z - 1));
+                    lowerNeighborhood.push(((i, j, z - 1), (i - x, j - y)));
                 } else {
                     // Handling edge cases where grain might "fall off"
                     if i == x && j == y {
                         // Do not add the current location itself when z is 0
-                        lowerNeighborhood.push((i, j, z - 1));
+                        lowerNeighborhood.push(((i, j, z - 1), (i - x, j - y)));
                     }
                     if i == 0 || i == X_SIZE - 1 || j == 0 || j == Y_SIZE - 1 {
                         // Marked locations indicating falling off the pile
-                        lowerNeighborhood.push((-1, -1, -1)); // Use an invalid location (-1, -1, -1) to indicate falling off
+                        lowerNeighborhood.push(((-1, -1, -1), (i - x, j - y))); // Use an invalid location (-1, -1, -1) to indicate falling off
                     } else {
                         // Add surrounding locations at the same level
-                        lowerNeighborhood.push((i, j, z));
+                        lowerNeighborhood.push(((i, j, z), (i - x, j - y)));
                     }
                 }
             }
@@ -359,9 +509,17 @@ impl Location {
      * Display the contents of the sandpile
      */
     pub fn displayPile( folder_path: String ) -> io::Result<()> {
+        registerOutput(&folder_path, "display-pile.txt", "text", "Grains at each location, one block of rows per layer from the top of the pile down", None);
+        Location::displayPileToFile(folder_path + "/display-pile.txt")
+    }
+
+    /**
+     * Display the contents of the sandpile to the given file
+     */
+    pub fn displayPileToFile( file_path: String ) -> io::Result<()> {
 
         // Create a file and wrap it in a BufWriter for efficient writing
-        let file = File::create(folder_path + "/display-pile.txt")?;
+        let file = File::create(file_path)?;
         let mut writer = BufWriter::new(file);
 
         // show the contents of all the locations in the sandpile
@@ -393,7 +551,8 @@ impl Location {
     pub fn displayAllLocationFinalPositions( folder_path: String ) -> io::Result<()> {
 
         // Create a file and wrap it in a BufWriter for efficient writing
-        let file = File::create(folder_path + "/display-all-locations.txt")?;
+        let file = File::create(folder_path.clone() + "/display-all-locations.txt")?;
+        registerOutput(&folder_path, "display-all-locations.txt", "text", "Grain ids and grain details held by every location at the end of the run", None);
         let mut writer = BufWriter::new(file);
 
         // show the contents of all the locations in the sandpile
@@ -408,7 +567,7 @@ impl Location {
                     // get all of the grains at this location and print their information
                     for grainId in &location.grainIds {
                         let grain = Grain::getGrainById(*grainId).unwrap();
-                        writeln!( writer, " Grain id: {}, x: {}, y: {}, z: {}, energy: {}", grain.id, grain.x, grain.y, grain.z, grain.energy)?;
+                        writeln!( writer, " Grain id: {}, type: {}, x: {}, y: {}, z: {}, energy: {}", grain.id, GRAIN_TYPES[grain.grainType].0, grain.x, grain.y, grain.z, grain.energy)?;
                     }
                 }
             }
@@ -420,7 +579,197 @@ impl Location {
         Ok(())
     }
 
+    /**
+     * Display the state of every pile location, capacity, resilience, how it has been compacted and weakened and the ids of the grains it holds
+     * empty space locations that hold no grains are not included
+     */
+    pub fn displayLocationStates( folder_path: &str, relativePath: &str, pathPattern: &str ) -> io::Result<()> {
+
+        // Create the csv through the common run output writer
+        let schema = CsvSchema::new("locations", pathPattern, "Capacity, resilience and grains held by every pile location", vec![
+            CsvColumn::new("x", "integer", "locations", "X coordinate of the location"),
+            CsvColumn::new("y", "integer", "locations", "Y coordinate of the location"),
+            CsvColumn::new("z", "integer", "layers", "Z coordinate of the location"),
+            CsvColumn::new("capacity", "integer", "grains", "Number of grains the location can hold"),
+            CsvColumn::new("resilience", "integer", "energy", "Energy the location absorbs before it releases grains"),
+            CsvColumn::new("roughness", "float", "multiplier", "Multiplier of the rolling friction of grains rolling over the location"),
+            CsvColumn::new("impacts", "integer", "grains", "Number of grains that have hit the location"),
+            CsvColumn::new("compactions", "integer", "compactions", "Number of times the location has been compacted by impacts"),
+            CsvColumn::new("intact_resilience", "integer", "energy", "Resilience the location heals back to after being weakened by avalanches"),
+            CsvColumn::new("grain_ids", "string", "grain ids", "Space separated ids of the grains held by the location"),
+        ]);
+        let mut writer = CsvWriter::create(folder_path, relativePath, &schema)?;
+
+        for location in Location::getAllLocations() {
+            if location.capacity == 0 && location.grainIds.is_empty() {
+                continue;
+            }
+            let grainIds: Vec<String> = location.grainIds.iter().map(|id| id.to_string()).collect();
+            writer.writeRow(&[
+                location.x.to_string(),
+                location.y.to_string(),
+                location.z.to_string(),
+                location.capacity.to_string(),
+                location.resilience.to_string(),
+                location.roughness.to_string(),
+                location.impacts.to_string(),
+                location.compactions.to_string(),
+                location.intactResilience.to_string(),
+                grainIds.join(" "),
+            ])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /**
+     * Get a copy of all the locations ordered by z, y, x
+     */
+    pub fn getAllLocations() -> Vec<Location> {
+        let locations = LOCATIONS.lock().unwrap();
+        let mut allLocations: Vec<Location> = locations.values().cloned().collect();
+        allLocations.sort_by_key(|location| (location.z, location.y, location.x));
+        allLocations
+    }
+
     pub fn getNumberOfGrains(&self) -> usize {
         return self.grainIds.len();
     }
-}
\ No newline at end of file
+
+    /**
+     * Get the capacity taken up by the grains at the location, the sum of their sizes
+     */
+    pub fn getOccupancy(&self) -> usize {
+        // every grain takes up one unit of capacity unless a grain type is larger
+        if GRAIN_TYPES.iter().all(|(_, _, size, _, _)| *size == 1) {
+            return self.grainIds.len();
+        }
+        self.grainIds.iter().map(|grainId| Grain::getGrainById(*grainId).map(|grain| grain.size).unwrap_or(1)).sum()
+    }
+
+    /**
+     * Visit every location in the pile without copying it
+     */
+    pub fn visitLocations(mut visit: impl FnMut(&Location)) {
+        let locations = LOCATIONS.lock().unwrap();
+        for location in locations.values() {
+            visit(location);
+        }
+    }
+
+    /**
+     * Get the total number of grains currently resting in the pile
+     */
+    pub fn getTotalGrainsInPile() -> usize {
+        let locations = LOCATIONS.lock().unwrap();
+        locations.values().map(|location| location.grainIds.len()).sum()
+    }
+
+    /**
+     * Get the mean number of grains per pile location for each layer of the pile
+     * indexed by z, empty space locations (capacity 0) are not counted
+     */
+    pub fn getLayerOccupancy() -> Vec<f64> {
+        let locations = LOCATIONS.lock().unwrap();
+
+        let mut grainsPerLayer = vec![0usize; Z_SIZE as usize];
+        let mut locationsPerLayer = vec![0usize; Z_SIZE as usize];
+        for location in locations.values() {
+            if location.capacity > 0 {
+                grainsPerLayer[location.z as usize] += location.grainIds.len();
+                locationsPerLayer[location.z as usize] += 1;
+            }
+        }
+
+        grainsPerLayer.iter().zip(locationsPerLayer.iter())
+            .map(|(&grains, &count)| if count > 0 { grains as f64 / count as f64 } else { 0.0 })
+            .collect()
+    }
+
+    /**
+     * Get the height map of the pile, the top occupied z for each x, y column
+     * indexed as heightMap[x][y], columns without any grains have a height of -1
+     */
+    pub fn getHeightMap() -> Vec<Vec<i32>> {
+        let locations = LOCATIONS.lock().unwrap();
+
+        let mut heightMap = vec![vec![-1; Y_SIZE as usize]; X_SIZE as usize];
+        for location in locations.values() {
+            let height = &mut heightMap[location.x as usize][location.y as usize];
+            if !location.grainIds.is_empty() && location.z > *height {
+                *height = location.z;
+            }
+        }
+        heightMap
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    // a layer of full locations under the slope location x, y, z with room in every location of the layer above
+    fn buildSlope(x: i32, y: i32, z: i32, rnd: &mut StdRng) {
+        for i in (x - 1)..=(x + 1) {
+            for j in (y - 1)..=(y + 1) {
+                let mut full = Location::new(0, i, j, z - 1, rnd);
+                full.capacity = 1;
+                full.grainIds = vec![3_999_999_999];
+                full.saveLocation();
+
+                let mut open = Location::new(0, i, j, z, rnd);
+                open.capacity = 4;
+                open.grainIds = Vec::new();
+                open.roughness = 1.0;
+                open.saveLocation();
+            }
+        }
+    }
+
+    // a grain rolling down from x, y, z with the friction, returns it after the impact with the location it reaches
+    fn rollOntoFullLocation(grainId: u32, x: i32, y: i32, z: i32, rollingFriction: f64, rnd: &mut StdRng) -> (Grain, Location) {
+        let mut grain = Grain::new(grainId);
+        grain.x = x;
+        grain.y = y;
+        grain.z = z;
+        grain.energy = 0;
+        grain.state = GrainState::Rolling;
+        grain.rollWithFriction(rollingFriction);
+        grain.saveGrain();
+        assert_eq!(grain.state, GrainState::Impact);
+
+        let mut location = Location::getLocationByXyz(grain.x, grain.y, grain.z).unwrap();
+        location.incomingGrain(grainId, rnd);
+        location.saveLocation();
+        (Grain::getGrainById(grainId).unwrap(), location)
+    }
+
+    #[test]
+    fn grainOutOfEnergyStopsOnTheSlope() {
+        let mut rnd = StdRng::seed_from_u64(1);
+        buildSlope(5, 5, 6, &mut rnd);
+
+        // friction takes all the energy gained on the roll, the grain stops on top of the full location it hit
+        let (grain, full) = rollOntoFullLocation(4_000_000_000, 5, 5, 6, 10.0, &mut rnd);
+        assert_eq!(grain.state, GrainState::Stationary);
+        assert_eq!(grain.z, 6);
+        assert_eq!(full.grainIds.len(), 1);
+        assert!(Location::getLocationByXyz(grain.x, grain.y, 6).unwrap().grainIds.contains(&grain.id));
+    }
+
+    #[test]
+    fn grainWithEnergyRollsOn() {
+        let mut rnd = StdRng::seed_from_u64(2);
+        buildSlope(15, 15, 6, &mut rnd);
+
+        // without friction the grain keeps the energy of the roll and rolls on past the full location
+        let (grain, full) = rollOntoFullLocation(4_000_000_001, 15, 15, 6, 0.0, &mut rnd);
+        assert_eq!(grain.state, GrainState::Rolling);
+        assert_eq!(grain.z, 5);
+        assert_eq!(full.grainIds.len(), 1);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod avalanche;
+pub mod avalancheSizeRule;
 pub mod grain;
 pub mod location;
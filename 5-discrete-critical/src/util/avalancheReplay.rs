@@ -0,0 +1,354 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// internal models
+use crate::models::avalanche::Avalanche;
+use crate::models::grain::GrainState;
+use crate::util::pileState::{invalidData, readBytes, readVarint, writeVarint};
+use crate::util::runOutput::registerOutput;
+
+// constants
+use crate::util::constants::{REPLAY_MIN_AVALANCHE_GRAINS, X_SIZE, Y_SIZE, Z_SIZE};
+
+
+// identifies an avalanche replay file
+const AVALANCHE_REPLAY_MAGIC: &[u8; 4] = b"SPRP";
+// current version of the avalanche replay format, increase when the layout changes
+pub const AVALANCHE_REPLAY_VERSION: u16 = 1;
+// replay of the recorded avalanches written to the run folder
+pub const AVALANCHE_REPLAY_FILE: &str = "avalanche-replay.bin";
+
+/**
+ * A single state transition of a grain in an avalanche
+ */
+#[derive(Clone, Debug)]
+pub struct ReplayEvent {
+    // time step of the avalanche the transition happened in
+    pub step: usize,
+    pub grainId: u32,
+    // position and state of the grain before and after the transition
+    pub from: (i32, i32, i32),
+    pub to: (i32, i32, i32),
+    pub fromState: GrainState,
+    pub toState: GrainState,
+    // energy of the grain after the transition
+    pub energy: usize,
+    // id of the grain whose impact released this grain, None when the grain was updated itself
+    pub perturbedBy: Option<u32>,
+}
+
+/**
+ * An avalanche read back from a replay file with its events in the order they happened
+ */
+#[derive(Clone, Debug)]
+pub struct ReplayedAvalanche {
+    // index of the grain dropped to start the avalanche
+    pub grainIndex: usize,
+    pub totalGrainsInvolved: usize,
+    pub totalMovement: usize,
+    pub duration: usize,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl ReplayedAvalanche {
+    /**
+     * The events of each time step of the avalanche in order, for stepping through it
+     */
+    pub fn steps(&self) -> impl Iterator<Item = &[ReplayEvent]> {
+        self.events.chunk_by(|a, b| a.step == b.step)
+    }
+}
+
+/**
+ * Writes the events of every avalanche involving at least REPLAY_MIN_AVALANCHE_GRAINS grains
+ * to avalanche-replay.bin, avalanches collect their events while they run when recording is on
+ * and the recorder keeps or drops them once the size of the avalanche is known
+ *
+ * Layout, all fixed width values are little endian, all varints are unsigned LEB128 and
+ * positions are zigzag encoded varints as grains off the pile can be outside it
+ *  magic "SPRP" (4 bytes), version (u16), x size, y size, z size (i32 each)
+ *  one record per avalanche in the order they happened
+ *   grain index, grains involved, total movement, duration, event count (varint each)
+ *   each event is the step and grain id (varint each), from x, y, z and to x, y, z (zigzag varint each),
+ *   from state and to state (u8 each, see stateCode), energy (varint) and the id of the impacting
+ *   grain plus one, 0 when the grain was updated itself (varint)
+ */
+pub struct AvalancheReplayRecorder {
+    // open replay file, None when recording is off
+    writer: Option<BufWriter<File>>,
+    // grains an avalanche has to involve to be written, REPLAY_MIN_AVALANCHE_GRAINS
+    pub minGrains: usize,
+    // number of avalanches written so far
+    pub avalancheCount: usize,
+}
+
+impl AvalancheReplayRecorder {
+    pub fn new(folder_path: &str) -> io::Result<Self> {
+        if !isReplayRecording() {
+            return Ok(AvalancheReplayRecorder { writer: None, minGrains: REPLAY_MIN_AVALANCHE_GRAINS, avalancheCount: 0 });
+        }
+
+        // Create a file and wrap it in a BufWriter for efficient writing
+        let file = File::create(format!("{}/{}", folder_path, AVALANCHE_REPLAY_FILE))?;
+        let mut writer = BufWriter::new(file);
+        registerOutput(folder_path, AVALANCHE_REPLAY_FILE, "binary", "Every grain state transition of the avalanches involving at least REPLAY_MIN_AVALANCHE_GRAINS grains", None);
+
+        writer.write_all(AVALANCHE_REPLAY_MAGIC)?;
+        writer.write_all(&AVALANCHE_REPLAY_VERSION.to_le_bytes())?;
+        writer.write_all(&X_SIZE.to_le_bytes())?;
+        writer.write_all(&Y_SIZE.to_le_bytes())?;
+        writer.write_all(&Z_SIZE.to_le_bytes())?;
+
+        Ok(AvalancheReplayRecorder { writer: Some(writer), minGrains: REPLAY_MIN_AVALANCHE_GRAINS, avalancheCount: 0 })
+    }
+
+    /**
+     * Write the events of a completed avalanche if it was large enough, the events are taken
+     * from the avalanche either way so they do not build up over the run
+     */
+    pub fn recordAvalanche(&mut self, grainIndex: usize, avalanche: &mut Avalanche) -> io::Result<()> {
+        let events = std::mem::take(&mut avalanche.replayEvents);
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if avalanche.totalGrainsInvolved < self.minGrains {
+            return Ok(());
+        }
+
+        writeVarint(writer, grainIndex as u64)?;
+        writeVarint(writer, avalanche.totalGrainsInvolved as u64)?;
+        writeVarint(writer, avalanche.totalMovement as u64)?;
+        writeVarint(writer, avalanche.duration as u64)?;
+        writeVarint(writer, events.len() as u64)?;
+        for event in &events {
+            writeVarint(writer, event.step as u64)?;
+            writeVarint(writer, event.grainId as u64)?;
+            for value in [event.from.0, event.from.1, event.from.2, event.to.0, event.to.1, event.to.2] {
+                writeVarint(writer, zigzag(value))?;
+            }
+            writer.write_all(&[stateCode(&event.fromState), stateCode(&event.toState)])?;
+            writeVarint(writer, event.energy as u64)?;
+            writeVarint(writer, event.perturbedBy.map(|grainId| grainId as u64 + 1).unwrap_or(0))?;
+        }
+        self.avalancheCount += 1;
+
+        Ok(())
+    }
+
+    /**
+     * Flush the replay file at the end of the run
+     */
+    pub fn finish(self) -> io::Result<()> {
+        match self.writer {
+            // flush the writer to ensure all data is written to the file
+            Some(mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/**
+ * Reads the avalanches of a replay file one at a time
+ */
+pub struct AvalancheReplayReader {
+    reader: BufReader<File>,
+    pub version: u16,
+    pub xSize: i32,
+    pub ySize: i32,
+    pub zSize: i32,
+}
+
+impl AvalancheReplayReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != AVALANCHE_REPLAY_MAGIC {
+            return Err(invalidData("not an avalanche replay file".to_string()));
+        }
+
+        let version = u16::from_le_bytes(readBytes(&mut reader)?);
+        if version > AVALANCHE_REPLAY_VERSION {
+            return Err(invalidData(format!("unsupported avalanche replay version {}", version)));
+        }
+
+        let xSize = i32::from_le_bytes(readBytes(&mut reader)?);
+        let ySize = i32::from_le_bytes(readBytes(&mut reader)?);
+        let zSize = i32::from_le_bytes(readBytes(&mut reader)?);
+
+        Ok(AvalancheReplayReader { reader, version, xSize, ySize, zSize })
+    }
+
+    fn readAvalanche(&mut self) -> io::Result<ReplayedAvalanche> {
+        let reader = &mut self.reader;
+        let grainIndex = readVarint(reader)? as usize;
+        let totalGrainsInvolved = readVarint(reader)? as usize;
+        let totalMovement = readVarint(reader)? as usize;
+        let duration = readVarint(reader)? as usize;
+        let eventCount = readVarint(reader)? as usize;
+
+        // the count is read from the file, the events are pushed as they are read rather than preallocated
+        // so a corrupt count ends in an unexpected end of file instead of a capacity overflow
+        let mut events = Vec::new();
+        for _ in 0..eventCount {
+            let step = readVarint(reader)? as usize;
+            let grainId = readVarint(reader)? as u32;
+            let mut position = [0i32; 6];
+            for value in position.iter_mut() {
+                *value = unzigzag(readVarint(reader)?);
+            }
+            let [fromState, toState] = readBytes::<2>(reader)?;
+            let energy = readVarint(reader)? as usize;
+            let perturbedBy = match readVarint(reader)? {
+                0 => None,
+                grainId => Some((grainId - 1) as u32),
+            };
+            events.push(ReplayEvent {
+                step,
+                grainId,
+                from: (position[0], position[1], position[2]),
+                to: (position[3], position[4], position[5]),
+                fromState: stateFromCode(fromState)?,
+                toState: stateFromCode(toState)?,
+                energy,
+                perturbedBy,
+            });
+        }
+
+        Ok(ReplayedAvalanche { grainIndex, totalGrainsInvolved, totalMovement, duration, events })
+    }
+}
+
+impl Iterator for AvalancheReplayReader {
+    type Item = io::Result<ReplayedAvalanche>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the file ends after the last complete avalanche
+        match self.reader.fill_buf() {
+            Ok([]) => None,
+            Ok(_) => Some(self.readAvalanche()),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/**
+ * Whether avalanches collect their events for the replay file
+ */
+pub fn isReplayRecording() -> bool {
+    REPLAY_MIN_AVALANCHE_GRAINS != 0
+}
+
+/**
+ * Code of a grain state in the replay file
+ */
+fn stateCode(state: &GrainState) -> u8 {
+    match state {
+        GrainState::Unknown => 0,
+        GrainState::Falling => 1,
+        GrainState::Impact => 2,
+        GrainState::Rolling => 3,
+        GrainState::Stationary => 4,
+        GrainState::OffPile => 5,
+    }
+}
+
+fn stateFromCode(code: u8) -> io::Result<GrainState> {
+    match code {
+        0 => Ok(GrainState::Unknown),
+        1 => Ok(GrainState::Falling),
+        2 => Ok(GrainState::Impact),
+        3 => Ok(GrainState::Rolling),
+        4 => Ok(GrainState::Stationary),
+        5 => Ok(GrainState::OffPile),
+        _ => Err(invalidData(format!("unknown grain state {}", code))),
+    }
+}
+
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn unzigzag(value: u64) -> i32 {
+    let value = value as u32;
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replayHeader() -> Vec<u8> {
+        let mut bytes = AVALANCHE_REPLAY_MAGIC.to_vec();
+        bytes.extend(AVALANCHE_REPLAY_VERSION.to_le_bytes());
+        for size in [X_SIZE, Y_SIZE, Z_SIZE] {
+            bytes.extend(size.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn event(step: usize, grainId: u32, from: (i32, i32, i32), to: (i32, i32, i32), toState: GrainState, perturbedBy: Option<u32>) -> ReplayEvent {
+        ReplayEvent { step, grainId, from, to, fromState: GrainState::Rolling, toState, energy: step + 1, perturbedBy }
+    }
+
+    #[test]
+    fn replayRoundTrips() {
+        let path = std::env::temp_dir().join(format!("avalanche-replay-{}.bin", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&replayHeader()).unwrap();
+        let mut recorder = AvalancheReplayRecorder { writer: Some(BufWriter::new(file)), minGrains: 2, avalancheCount: 0 };
+
+        let mut small = Avalanche::new(1);
+        small.totalGrainsInvolved = 1;
+        small.replayEvents = vec![event(0, 1, (1, 1, 1), (1, 1, 0), GrainState::Stationary, None)];
+        let mut large = Avalanche::new(2);
+        large.totalGrainsInvolved = 2;
+        large.totalMovement = 3;
+        large.duration = 2;
+        large.replayEvents = vec![
+            event(0, 2, (0, 0, 2), (-1, 0, 1), GrainState::OffPile, None),
+            event(0, 7, (4, 5, 6), (4, 5, 6), GrainState::Rolling, Some(2)),
+            event(1, 7, (4, 5, 6), (5, 5, 5), GrainState::Stationary, None),
+        ];
+        recorder.recordAvalanche(10, &mut small).unwrap();
+        recorder.recordAvalanche(11, &mut large).unwrap();
+        assert_eq!(recorder.avalancheCount, 1);
+        assert!(small.replayEvents.is_empty());
+        recorder.finish().unwrap();
+
+        let avalanches: Vec<ReplayedAvalanche> = AvalancheReplayReader::open(&path).unwrap().collect::<io::Result<_>>().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(avalanches.len(), 1);
+        let avalanche = &avalanches[0];
+        assert_eq!((avalanche.grainIndex, avalanche.totalGrainsInvolved, avalanche.totalMovement, avalanche.duration), (11, 2, 3, 2));
+        assert_eq!(avalanche.steps().map(|step| step.len()).collect::<Vec<_>>(), vec![2, 1]);
+        let offPile = &avalanche.events[0];
+        assert_eq!((offPile.from, offPile.to, offPile.perturbedBy), ((0, 0, 2), (-1, 0, 1), None));
+        assert_eq!(offPile.toState, GrainState::OffPile);
+        let perturbed = &avalanche.events[1];
+        assert_eq!((perturbed.grainId, perturbed.energy, perturbed.perturbedBy), (7, 1, Some(2)));
+    }
+
+    #[test]
+    fn readRejectsTruncatedAvalanche() {
+        // an event count far past the end of the file, then a header cut off part way through the sizes
+        let mut bytes = replayHeader();
+        for value in [5u64, 2, 3, 2, u32::MAX as u64] {
+            writeVarint(&mut bytes, value).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("avalanche-replay-truncated-{}.bin", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let mut reader = AvalancheReplayReader::open(&path).unwrap();
+        let error = reader.next().unwrap().err().unwrap();
+        std::fs::write(&path, &replayHeader()[..8]).unwrap();
+        let truncatedHeader = AvalancheReplayReader::open(&path).err().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(truncatedHeader.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,284 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::{register_font, FontStyle};
+
+
+lazy_static! { // Require the lazy_static crate to handle static Mutex
+    // path of the font registered for chart text, None until a font has been registered
+    static ref CHART_FONT: Mutex<Option<String>> = Mutex::new(None);
+}
+
+// environment variable that can point to a TrueType font to use for chart text
+pub const CHART_FONT_VARIABLE: &str = "SANDPILE_CHART_FONT";
+
+// fonts tried in order when SANDPILE_CHART_FONT is not set, covering common linux, macOS and windows installs
+const CHART_FONT_PATHS: [&str; 7] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/**
+ * File format a chart is drawn to
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChartFormat {
+    Svg,
+    Png,
+}
+
+impl ChartFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChartFormat::Svg => "svg",
+            ChartFormat::Png => "png",
+        }
+    }
+}
+
+/**
+ * Named series of (x, y) points drawn on a chart, as points or as a line
+ */
+#[derive(Clone, Debug)]
+pub struct ChartSeries {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+    pub line: bool,
+}
+
+impl ChartSeries {
+    pub fn points(name: &str, points: Vec<(f64, f64)>) -> Self {
+        ChartSeries { name: name.to_string(), points, line: false }
+    }
+
+    pub fn line(name: &str, points: Vec<(f64, f64)>) -> Self {
+        ChartSeries { name: name.to_string(), points, line: true }
+    }
+}
+
+/**
+ * Load a TrueType font for the text of the charts, the font named by SANDPILE_CHART_FONT or
+ * the first of CHART_FONT_PATHS found, charts are drawn without a display so the font has
+ * to be read from a file rather than asked for from the system
+ */
+pub fn registerChartFont() -> io::Result<()> {
+    let mut chartFont = CHART_FONT.lock().unwrap();
+    if chartFont.is_some() {
+        return Ok(());
+    }
+
+    let mut paths: Vec<String> = CHART_FONT_PATHS.iter().map(|path| path.to_string()).collect();
+    if let Ok(path) = env::var(CHART_FONT_VARIABLE) {
+        paths.insert(0, path);
+    }
+
+    for path in paths {
+        let Ok(bytes) = fs::read(&path) else {
+            continue;
+        };
+        // the font has to live as long as the program to be registered
+        let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+        if register_font("sans-serif", FontStyle::Normal, bytes).is_ok() {
+            *chartFont = Some(path);
+            return Ok(());
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound,
+        format!("no font found for chart text, set {} to the path of a TrueType font", CHART_FONT_VARIABLE)))
+}
+
+/**
+ * Draw the series on log-log axes, used for avalanche size distributions,
+ * points with an x or y of zero or less are left out
+ */
+pub fn displayLogLogChart(file_path: &str, format: ChartFormat, title: &str, xLabel: &str, yLabel: &str, series: &[ChartSeries]) -> io::Result<()> {
+    registerChartFont()?;
+
+    let points = || series.iter().flat_map(|series| series.points.iter()).filter(|&&(x, y)| x > 0.0 && y > 0.0);
+    let xMin = points().map(|&(x, _)| x).fold(f64::MAX, f64::min).min(1.0);
+    let xMax = points().map(|&(x, _)| x).fold(xMin, f64::max);
+    let yMin = points().map(|&(_, y)| y).fold(1.0, f64::min);
+    let yMax = points().map(|&(_, y)| y).fold(yMin, f64::max);
+    let xRange = xMin..xMax * 1.5;
+    let yRange = yMin / 1.5..yMax * 1.5;
+
+    match format {
+        ChartFormat::Svg => drawLogLogChart(SVGBackend::new(file_path, (900, 600)).into_drawing_area(), title, xLabel, yLabel, series, xRange, yRange),
+        ChartFormat::Png => drawLogLogChart(BitMapBackend::new(file_path, (900, 600)).into_drawing_area(), title, xLabel, yLabel, series, xRange, yRange),
+    }
+}
+
+/**
+ * Draw the series on linear axes, used for time series such as avalanche sizes by grain
+ */
+pub fn displayLineChart(file_path: &str, format: ChartFormat, title: &str, xLabel: &str, yLabel: &str, series: &[ChartSeries]) -> io::Result<()> {
+    registerChartFont()?;
+
+    let points = || series.iter().flat_map(|series| series.points.iter());
+    let xMin = points().map(|&(x, _)| x).fold(f64::MAX, f64::min).min(0.0);
+    let xMax = points().map(|&(x, _)| x).fold(xMin + 1.0, f64::max);
+    let yMin = points().map(|&(_, y)| y).fold(f64::MAX, f64::min).min(0.0);
+    let yMax = points().map(|&(_, y)| y).fold(yMin + 1.0, f64::max);
+    let xRange = xMin..xMax;
+    let yRange = yMin..yMax * 1.05;
+
+    match format {
+        ChartFormat::Svg => drawLineChart(SVGBackend::new(file_path, (1200, 500)).into_drawing_area(), title, xLabel, yLabel, series, xRange, yRange),
+        ChartFormat::Png => drawLineChart(BitMapBackend::new(file_path, (1200, 500)).into_drawing_area(), title, xLabel, yLabel, series, xRange, yRange),
+    }
+}
+
+/**
+ * Draw a grid of values as colored cells, grid[x][y], used for the height map of the pile
+ */
+pub fn displayHeatMap(file_path: &str, format: ChartFormat, title: &str, valueLabel: &str, grid: &[Vec<f64>]) -> io::Result<()> {
+    registerChartFont()?;
+
+    match format {
+        ChartFormat::Svg => drawHeatMap(SVGBackend::new(file_path, (760, 700)).into_drawing_area(), title, valueLabel, grid),
+        ChartFormat::Png => drawHeatMap(BitMapBackend::new(file_path, (760, 700)).into_drawing_area(), title, valueLabel, grid),
+    }
+}
+
+fn drawLogLogChart<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, xLabel: &str, yLabel: &str, series: &[ChartSeries], xRange: Range<f64>, yRange: Range<f64>) -> io::Result<()>
+where DB::ErrorType: 'static {
+    root.fill(&WHITE).map_err(chartError)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(45)
+        .y_label_area_size(70)
+        .build_cartesian_2d(xRange.log_scale(), yRange.log_scale())
+        .map_err(chartError)?;
+
+    chart.configure_mesh()
+        .x_desc(xLabel)
+        .y_desc(yLabel)
+        .x_label_formatter(&|x| logAxisLabel(*x))
+        .y_label_formatter(&|y| logAxisLabel(*y))
+        .draw()
+        .map_err(chartError)?;
+
+    for (i, series) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        let points: Vec<(f64, f64)> = series.points.iter().cloned().filter(|&(x, y)| x > 0.0 && y > 0.0).collect();
+        if series.line {
+            chart.draw_series(LineSeries::new(points, color.stroke_width(2)))
+                .map_err(chartError)?
+                .label(series.name.clone())
+                .legend(move |(x, y)| PathElement::new(vec![(x - 8, y), (x + 8, y)], color.stroke_width(2)));
+        } else {
+            chart.draw_series(points.into_iter().map(|(x, y)| Circle::new((x, y), 2, color.filled())))
+                .map_err(chartError)?
+                .label(series.name.clone())
+                .legend(move |(x, y)| Circle::new((x, y), 4, color.filled()));
+        }
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(chartError)?;
+
+    root.present().map_err(chartError)
+}
+
+fn drawLineChart<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, xLabel: &str, yLabel: &str, series: &[ChartSeries], xRange: Range<f64>, yRange: Range<f64>) -> io::Result<()>
+where DB::ErrorType: 'static {
+    root.fill(&WHITE).map_err(chartError)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(45)
+        .y_label_area_size(70)
+        .build_cartesian_2d(xRange, yRange)
+        .map_err(chartError)?;
+
+    chart.configure_mesh()
+        .x_desc(xLabel)
+        .y_desc(yLabel)
+        .draw()
+        .map_err(chartError)?;
+
+    for (i, series) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        chart.draw_series(LineSeries::new(series.points.iter().cloned(), color.stroke_width(1)))
+            .map_err(chartError)?
+            .label(series.name.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x - 8, y), (x + 8, y)], color.stroke_width(2)));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(chartError)?;
+
+    root.present().map_err(chartError)
+}
+
+fn drawHeatMap<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, valueLabel: &str, grid: &[Vec<f64>]) -> io::Result<()>
+where DB::ErrorType: 'static {
+    root.fill(&WHITE).map_err(chartError)?;
+
+    let xSize = grid.len();
+    let ySize = grid.first().map(|column| column.len()).unwrap_or(0);
+    let largest = grid.iter().flatten().cloned().fold(0.0, f64::max);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("{} ({} 0 to {})", title, valueLabel, largest), ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0..xSize as i32, 0..ySize as i32)
+        .map_err(chartError)?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_desc("x")
+        .y_desc("y")
+        .draw()
+        .map_err(chartError)?;
+
+    // empty columns are white, the tallest columns dark red
+    chart.draw_series(grid.iter().enumerate().flat_map(|(x, column)| {
+        column.iter().enumerate().map(move |(y, &value)| {
+            let shade = if largest > 0.0 { value / largest } else { 0.0 };
+            let color = RGBColor(255, (255.0 * (1.0 - shade)) as u8, (255.0 * (1.0 - shade).powi(2)) as u8);
+            Rectangle::new([(x as i32, y as i32), (x as i32 + 1, y as i32 + 1)], color.filled())
+        })
+    })).map_err(chartError)?;
+
+    root.present().map_err(chartError)
+}
+
+/**
+ * Short label for a tick on a log axis, whole numbers as they are and anything else in scientific notation
+ */
+fn logAxisLabel(value: f64) -> String {
+    if value >= 1.0 && value.fract().abs() < 1e-9 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.0e}", value)
+    }
+}
+
+fn chartError(e: impl std::fmt::Display) -> io::Error {
+    io::Error::other(e.to_string())
+}
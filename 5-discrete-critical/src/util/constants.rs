@@ -1,3 +1,6 @@
+use crate::util::paramDistribution::{ParamDistribution, ControlKind, controlRun};
+use crate::analysis::distributions::AVALANCHE_MEASURES;
+
 /**
  * Master Debug flag
  */
@@ -24,13 +27,23 @@ pub const ALPHA_MAIN: f64 = 1.6;
 pub const ALPHA_LANDING: f64 = 1.4;
 // Amount of additional energy added to the grains current energy in impact
 pub const ALPHA_EXTRA_ENERGY: f64 = 0.8;
-// Amount of additional grains to add to an avalanche in addition to the base size as determined by the avalanche method (see BASE_AVALANCHE_METHOD)
+// Amount of additional grains to add to an avalanche in addition to the base size as determined by the avalanche size rule (see AVALANCHE_SIZE_RULE)
 pub const ALPHA_AVALANCHE_SIZE: f64 = 1.2;
 // Additional possible capacity of location
 pub const ALPHA_LOCATION_EXTRA_CAPACITY: f64 = 2.2;
 // Additional possible resilience of location
 pub const ALPHA_LOCATION_EXTRA_RESILIENCE: f64 = 0.8;
 
+// Parameter distributions
+// distribution each random parameter is drawn from (see ParamDistribution for the kinds), the defaults take
+// the order of magnitude of a Pareto sample with the ALPHA_* exponents above
+// e.g. ParamDistribution::Exponential { mean: 1.0 } or ParamDistribution::Empirical { path: "./energies.csv" }
+pub const LANDING_DISTRIBUTION: ParamDistribution = ParamDistribution::ParetoOrderOfMagnitude { alpha: ALPHA_LANDING };
+pub const EXTRA_ENERGY_DISTRIBUTION: ParamDistribution = ParamDistribution::ParetoOrderOfMagnitude { alpha: ALPHA_EXTRA_ENERGY };
+pub const AVALANCHE_SIZE_DISTRIBUTION: ParamDistribution = ParamDistribution::ParetoOrderOfMagnitude { alpha: ALPHA_AVALANCHE_SIZE };
+pub const EXTRA_CAPACITY_DISTRIBUTION: ParamDistribution = ParamDistribution::ParetoOrderOfMagnitude { alpha: ALPHA_LOCATION_EXTRA_CAPACITY };
+pub const EXTRA_RESILIENCE_DISTRIBUTION: ParamDistribution = ParamDistribution::ParetoOrderOfMagnitude { alpha: ALPHA_LOCATION_EXTRA_RESILIENCE };
+
 // total allowed dimensions of the pile
 pub const X_SIZE: i32 = 21;
 pub const Y_SIZE: i32 = 21;
@@ -40,12 +53,285 @@ pub const Z_SIZE: i32 = 16;
 pub const TERMINAL_FREE_FALL_SPEED: usize = 3;
 pub const BASE_RESILIENCE: usize = 3;
 pub const BASE_CAPACITY: usize = 4;
-// chose between size or percent for the avalanche size
-pub const BASE_AVALANCHE_METHOD: usize = 2; // 1 = size, 2 = percent
-// IF BASE_AVALANCHE_METHOD=1: base size of avalanche (will have result of ALPHA_AVALANCHE_SIZE added to it)
+
+// Avalanche size rules
+// rule deciding how many grains a perturbed location releases, one of AVALANCHE_SIZE_RULE_NAMES
+//  fixed - BASE_AVALANCHE_SIZE, percent - BASE_AVALANCHE_SIZE_PERCENT of the grains at the location,
+//  energy - AVALANCHE_GRAINS_PER_ENERGY for each unit of energy beyond the resilience (fixed, percent and energy have
+//  the result of ALPHA_AVALANCHE_SIZE added), threshold - the grains above CRITICAL_OCCUPANCY,
+//  table - looked up in AVALANCHE_SIZE_TABLE by the energy beyond the resilience
+pub const AVALANCHE_SIZE_RULE: &str = "percent";
+pub const AVALANCHE_SIZE_RULE_NAMES: [&str; 5] = ["fixed", "percent", "energy", "threshold", "table"];
+// fixed rule: base size of avalanche
 pub const BASE_AVALANCHE_SIZE: usize = 2;
-// IF BASE_AVALANCHE_METHOD=2: base percent of grains in the avalanche from location (will have result of ALPHA_AVALANCHE_SIZE added to it)
+// percent rule: base percent of grains in the avalanche from location
 pub const BASE_AVALANCHE_SIZE_PERCENT: f64 = 0.75;
+// energy rule: grains released for each unit of energy beyond the resilience of the location
+pub const AVALANCHE_GRAINS_PER_ENERGY: f64 = 0.5;
+// threshold rule: grains a location keeps, every grain above it is released
+pub const CRITICAL_OCCUPANCY: usize = 4;
+// table rule: (smallest energy beyond the resilience, grains released) in increasing energy order
+pub const AVALANCHE_SIZE_TABLE: [(usize, usize); 4] = [(1, 1), (3, 2), (6, 4), (10, 8)];
+
+// Grain momentum
+// bias of a rolling grain toward the horizontal direction it is already moving in (0 = off, a random lower neighbor as before)
+// each lower neighbor is weighted by e^(GRAIN_INERTIA * cos(angle between the neighbor and the grains direction))
+pub const GRAIN_INERTIA: f64 = 0.0;
+
+// Energy radiation
+// distance in locations the energy of an impact radiates to in every direction, including up and down
+// opt in, radiation releases neighboring grains on every impact and changes the avalanche statistics (0 = off)
+pub const ENERGY_RADIATION_RANGE: i32 = 0;
+// fraction of the impact energy that reaches a location for each location of distance from the impact
+pub const ENERGY_RADIATION_ATTENUATION: f64 = 0.5;
+
+// Friction and restitution
+// energy a rolling grain loses for each location it rolls over, multiplied by the roughness of the location (0 = no friction, one unit gained per roll as before)
+pub const ROLLING_FRICTION: f64 = 0.0;
+// fraction of its energy a grain keeps when it hits a full location and rolls on (1 = no energy lost on impact)
+pub const RESTITUTION_COEFFICIENT: f64 = 1.0;
+// most energy a grain keeps when it hits a full location and rolls on (1 = clamped to one unit as before)
+pub const MAX_REBOUND_ENERGY: usize = 1;
+// the roughness of each location is drawn uniformly between the min and max roughness
+pub const MIN_LOCATION_ROUGHNESS: f64 = 0.5;
+pub const MAX_LOCATION_ROUGHNESS: f64 = 1.5;
+
+// Location dynamics
+// impacts a location takes each time it is compacted (0 = locations are never compacted)
+pub const COMPACTION_IMPACTS: usize = 0;
+// capacity and resilience a location gains each time it is compacted
+pub const COMPACTION_CAPACITY_GAIN: usize = 1;
+pub const COMPACTION_RESILIENCE_GAIN: usize = 1;
+// most times a single location can be compacted
+pub const MAX_COMPACTIONS: usize = 5;
+// resilience a location loses each time it releases grains (0 = locations are not weakened by avalanches)
+pub const AVALANCHE_WEAKENING: usize = 0;
+// grains dropped between each unit of resilience a weakened location regains (0 = weakened locations do not heal)
+pub const HEALING_INTERVAL: usize = 0;
+
+// Grain types
+// mixture of the grains dropped on the pile as (name, fraction of the grains, size, mass, cohesion)
+// size - capacity of a location the grain takes up, mass - multiplier of the energy the grain delivers on impact
+// cohesion - energy needed on top of the resilience of a location to release the grain
+pub const GRAIN_TYPES: [(&str, f64, usize, f64, usize); 1] = [
+    ("sand", 1.0, 1, 1.0, 0),
+];
 
 // Total gains to be introduced into the system
 pub const TOTAL_GRAINS: usize = 100000;
+
+// Seed for the random number generator, the same seed and configuration reproduce a run (0 = random seed for each run)
+pub const RNG_SEED: u64 = 0;
+
+
+// Stationarity detection
+// number of grains dropped between samples of the pile observables
+pub const STATIONARITY_SAMPLE_INTERVAL: usize = 1000;
+// number of samples in the rolling window used to decide if the pile is stationary
+pub const STATIONARITY_WINDOW: usize = 20;
+// maximum relative drift of the grains in the pile and the pile slope between the two halves of the window
+pub const STATIONARITY_TOLERANCE: f64 = 0.01;
+// minimum ratio of grains leaving the pile to grains dropped over the window
+pub const STATIONARITY_MIN_OUTFLOW_RATIO: f64 = 0.9;
+// stop the run after this many avalanches once the pile is stationary (0 = run all TOTAL_GRAINS)
+pub const STATIONARY_AVALANCHE_LIMIT: usize = 0;
+
+// Pile observables
+// number of grains dropped between exports of the height map, slope and radial profile (0 = only at the end of the run)
+pub const OBSERVABLES_INTERVAL: usize = 10000;
+
+// Pile snapshots
+// number of grains dropped between snapshots of the pile state (0 = no snapshots)
+pub const SNAPSHOT_INTERVAL: usize = 25000;
+
+// Grain trajectory tracing, traced grains have every step of their path written to grain-trace.csv
+// ids of grains to trace
+pub const TRACE_GRAIN_IDS: [u32; 0] = [];
+// trace every grain with an id that is a multiple of this (0 = none)
+pub const TRACE_EVERY_NTH_GRAIN: usize = 0;
+// grain indexes of avalanches to trace every grain moved by, from then on for the rest of the run
+pub const TRACE_AVALANCHES: [usize; 0] = [];
+
+// Residence times
+// record every rest, re-mobilization and exit of each grain for the residence time exports, off by default as
+// the bookkeeping of every grain grows with the run
+pub const RESIDENCE_RECORDING: bool = false;
+
+// Avalanche replays
+// record every grain state transition of avalanches involving at least this many grains to avalanche-replay.bin (0 = off)
+pub const REPLAY_MIN_AVALANCHE_GRAINS: usize = 0;
+
+// Causal cascades
+// keep and export the causal tree of avalanches involving at least this many grains (0 = none, the generation sizes are always kept)
+pub const CASCADE_MIN_AVALANCHE_GRAINS: usize = 10;
+
+// Distribution exports
+// number of logarithmic bins per decade of avalanche size in the probability density exports
+pub const LOG_BINS_PER_DECADE: usize = 10;
+
+// Control runs
+// samples drawn from each power-law distribution to find the mean and variance its replacement is matched to
+pub const CONTROL_MATCH_SAMPLES: usize = 100000;
+
+// Analysis of finished runs
+// minimum number of observations in the tail of a distribution for a power-law fit
+pub const POWER_LAW_MIN_TAIL: u64 = 50;
+// smallest ratio between consecutive xMin values tried when fitting a power law, keeps the xMin scan fast on long tails
+pub const POWER_LAW_XMIN_RATIO: f64 = 1.05;
+// standard normal quantile of the confidence intervals reported for fitted exponents (1.96 = 95%)
+pub const POWER_LAW_CONFIDENCE_Z: f64 = 1.96;
+
+// Criticality
+// number of avalanches in each window the branching ratio is estimated over
+pub const BRANCHING_WINDOW: usize = 10000;
+// generations of each causal tree the branching ratio is estimated over, from the dropped grain
+pub const BRANCHING_GENERATIONS: usize = 4;
+// distance from a branching ratio of 1 still counted as critical
+pub const CRITICALITY_TOLERANCE: f64 = 0.05;
+
+// Temporal statistics
+// avalanche measure of AVALANCHE_MEASURES the waiting times, autocorrelation, DFA and aftershocks are computed on
+pub const TEMPORAL_MEASURE: &str = "movement";
+// sizes an avalanche has to reach to count as an event for the waiting times between events
+pub const WAITING_TIME_THRESHOLDS: [usize; 4] = [1, 10, 100, 1000];
+// largest lag in avalanches of the size autocorrelation
+pub const AUTOCORRELATION_MAX_LAG: usize = 100;
+// smallest window in avalanches of the detrended fluctuation analysis, windows go up to a quarter of the series
+pub const DFA_MIN_SCALE: usize = 8;
+// quantiles of the avalanche size a mainshock and an aftershock have to reach in the Omori analysis
+pub const OMORI_MAINSHOCK_QUANTILE: f64 = 0.99;
+pub const OMORI_AFTERSHOCK_QUANTILE: f64 = 0.9;
+// largest lag in avalanches after a mainshock aftershocks are counted at
+pub const OMORI_MAX_LAG: usize = 1000;
+
+// Pre-drop pile state
+// grains dropped between records of the pile state just before the drop (0 = off, 1 = before every drop)
+// opt in, each record scans the whole pile, before every drop this about doubles the run time
+pub const PRE_DROP_INTERVAL: usize = 0;
+// columns either side of the drop site counted in the local occupancy
+pub const PRE_DROP_RADIUS: i32 = 1;
+// grains short of its capacity an occupied location can be and still count as near its threshold
+pub const NEAR_THRESHOLD_MARGIN: usize = 1;
+// quantile of the avalanche size a large avalanche reaches in the predictability score
+pub const PREDICTABILITY_LARGE_QUANTILE: f64 = 0.9;
+// fraction of the drops, the earliest, the combined fit is made on, it is scored on the drops after them
+pub const PREDICTABILITY_TRAINING_FRACTION: f64 = 0.5;
+
+
+/**
+ * Configuration of the run as name, value pairs, recorded with the pile state and in the run manifest
+ */
+pub fn configurationEntries() -> Vec<(String, f64)> {
+    let mut entries = vec![
+        ("TOTAL_GRAINS".to_string(), TOTAL_GRAINS as f64),
+        ("X_SIZE".to_string(), X_SIZE as f64),
+        ("Y_SIZE".to_string(), Y_SIZE as f64),
+        ("Z_SIZE".to_string(), Z_SIZE as f64),
+        ("X_MIN".to_string(), X_MIN),
+        ("TERMINAL_FREE_FALL_SPEED".to_string(), TERMINAL_FREE_FALL_SPEED as f64),
+        ("BASE_RESILIENCE".to_string(), BASE_RESILIENCE as f64),
+        ("BASE_CAPACITY".to_string(), BASE_CAPACITY as f64),
+        // index of the rule in AVALANCHE_SIZE_RULE_NAMES, the rule is named in the run manifest
+        ("AVALANCHE_SIZE_RULE".to_string(), AVALANCHE_SIZE_RULE_NAMES.iter().position(|name| *name == AVALANCHE_SIZE_RULE).map(|i| i as f64).unwrap_or(-1.0)),
+        ("BASE_AVALANCHE_SIZE".to_string(), BASE_AVALANCHE_SIZE as f64),
+        ("BASE_AVALANCHE_SIZE_PERCENT".to_string(), BASE_AVALANCHE_SIZE_PERCENT),
+        ("AVALANCHE_GRAINS_PER_ENERGY".to_string(), AVALANCHE_GRAINS_PER_ENERGY),
+        ("CRITICAL_OCCUPANCY".to_string(), CRITICAL_OCCUPANCY as f64),
+        ("GRAIN_INERTIA".to_string(), GRAIN_INERTIA),
+        ("ENERGY_RADIATION_RANGE".to_string(), ENERGY_RADIATION_RANGE as f64),
+        ("ENERGY_RADIATION_ATTENUATION".to_string(), ENERGY_RADIATION_ATTENUATION),
+        ("ROLLING_FRICTION".to_string(), ROLLING_FRICTION),
+        ("RESTITUTION_COEFFICIENT".to_string(), RESTITUTION_COEFFICIENT),
+        ("MAX_REBOUND_ENERGY".to_string(), MAX_REBOUND_ENERGY as f64),
+        ("MIN_LOCATION_ROUGHNESS".to_string(), MIN_LOCATION_ROUGHNESS),
+        ("MAX_LOCATION_ROUGHNESS".to_string(), MAX_LOCATION_ROUGHNESS),
+        ("COMPACTION_IMPACTS".to_string(), COMPACTION_IMPACTS as f64),
+        ("COMPACTION_CAPACITY_GAIN".to_string(), COMPACTION_CAPACITY_GAIN as f64),
+        ("COMPACTION_RESILIENCE_GAIN".to_string(), COMPACTION_RESILIENCE_GAIN as f64),
+        ("MAX_COMPACTIONS".to_string(), MAX_COMPACTIONS as f64),
+        ("AVALANCHE_WEAKENING".to_string(), AVALANCHE_WEAKENING as f64),
+        ("HEALING_INTERVAL".to_string(), HEALING_INTERVAL as f64),
+        ("ALPHA_MAIN".to_string(), ALPHA_MAIN),
+        ("ALPHA_LANDING".to_string(), ALPHA_LANDING),
+        ("ALPHA_EXTRA_ENERGY".to_string(), ALPHA_EXTRA_ENERGY),
+        ("ALPHA_AVALANCHE_SIZE".to_string(), ALPHA_AVALANCHE_SIZE),
+        ("ALPHA_LOCATION_EXTRA_CAPACITY".to_string(), ALPHA_LOCATION_EXTRA_CAPACITY),
+        ("ALPHA_LOCATION_EXTRA_RESILIENCE".to_string(), ALPHA_LOCATION_EXTRA_RESILIENCE),
+        ("RNG_SEED".to_string(), RNG_SEED as f64),
+        ("STATIONARITY_SAMPLE_INTERVAL".to_string(), STATIONARITY_SAMPLE_INTERVAL as f64),
+        ("STATIONARITY_WINDOW".to_string(), STATIONARITY_WINDOW as f64),
+        ("STATIONARITY_TOLERANCE".to_string(), STATIONARITY_TOLERANCE),
+        ("STATIONARITY_MIN_OUTFLOW_RATIO".to_string(), STATIONARITY_MIN_OUTFLOW_RATIO),
+        ("STATIONARY_AVALANCHE_LIMIT".to_string(), STATIONARY_AVALANCHE_LIMIT as f64),
+        ("OBSERVABLES_INTERVAL".to_string(), OBSERVABLES_INTERVAL as f64),
+        ("SNAPSHOT_INTERVAL".to_string(), SNAPSHOT_INTERVAL as f64),
+        ("LOG_BINS_PER_DECADE".to_string(), LOG_BINS_PER_DECADE as f64),
+        ("TRACE_EVERY_NTH_GRAIN".to_string(), TRACE_EVERY_NTH_GRAIN as f64),
+        // 1 when the residence times are recorded
+        ("RESIDENCE_RECORDING".to_string(), if RESIDENCE_RECORDING { 1.0 } else { 0.0 }),
+        ("REPLAY_MIN_AVALANCHE_GRAINS".to_string(), REPLAY_MIN_AVALANCHE_GRAINS as f64),
+        ("CASCADE_MIN_AVALANCHE_GRAINS".to_string(), CASCADE_MIN_AVALANCHE_GRAINS as f64),
+        ("BRANCHING_WINDOW".to_string(), BRANCHING_WINDOW as f64),
+        ("BRANCHING_GENERATIONS".to_string(), BRANCHING_GENERATIONS as f64),
+        ("CRITICALITY_TOLERANCE".to_string(), CRITICALITY_TOLERANCE),
+        // index of the measure in AVALANCHE_MEASURES
+        ("TEMPORAL_MEASURE".to_string(), AVALANCHE_MEASURES.iter().position(|(name, _, _)| *name == TEMPORAL_MEASURE).map(|i| i as f64).unwrap_or(-1.0)),
+        ("AUTOCORRELATION_MAX_LAG".to_string(), AUTOCORRELATION_MAX_LAG as f64),
+        ("DFA_MIN_SCALE".to_string(), DFA_MIN_SCALE as f64),
+        ("OMORI_MAINSHOCK_QUANTILE".to_string(), OMORI_MAINSHOCK_QUANTILE),
+        ("OMORI_AFTERSHOCK_QUANTILE".to_string(), OMORI_AFTERSHOCK_QUANTILE),
+        ("OMORI_MAX_LAG".to_string(), OMORI_MAX_LAG as f64),
+        ("PRE_DROP_INTERVAL".to_string(), PRE_DROP_INTERVAL as f64),
+        ("PRE_DROP_RADIUS".to_string(), PRE_DROP_RADIUS as f64),
+        ("NEAR_THRESHOLD_MARGIN".to_string(), NEAR_THRESHOLD_MARGIN as f64),
+        ("CONTROL_MATCH_SAMPLES".to_string(), CONTROL_MATCH_SAMPLES as f64),
+        // 0 for a normal run, 1 for an exponential and 2 for a constant control run
+        ("CONTROL_RUN".to_string(), match controlRun() { None => 0.0, Some((ControlKind::Exponential, _)) => 1.0, Some((ControlKind::Constant, _)) => 2.0 }),
+    ];
+
+    for (name, distribution) in parameterDistributions() {
+        entries.extend(distribution.configurationEntries(name));
+    }
+
+    // table rows are numbered from 1
+    for (i, (energy, grains)) in AVALANCHE_SIZE_TABLE.iter().enumerate() {
+        entries.push((format!("AVALANCHE_SIZE_TABLE_{}_ENERGY", i + 1), *energy as f64));
+        entries.push((format!("AVALANCHE_SIZE_TABLE_{}_GRAINS", i + 1), *grains as f64));
+    }
+
+    // traced grains and avalanches are numbered from 1
+    for (i, grainId) in TRACE_GRAIN_IDS.iter().enumerate() {
+        entries.push((format!("TRACE_GRAIN_ID_{}", i + 1), *grainId as f64));
+    }
+    for (i, grainIndex) in TRACE_AVALANCHES.iter().enumerate() {
+        entries.push((format!("TRACE_AVALANCHE_{}", i + 1), *grainIndex as f64));
+    }
+
+    // waiting time thresholds are numbered from 1
+    for (i, threshold) in WAITING_TIME_THRESHOLDS.iter().enumerate() {
+        entries.push((format!("WAITING_TIME_THRESHOLD_{}", i + 1), *threshold as f64));
+    }
+
+    // grain types are numbered from 1 in the order of GRAIN_TYPES
+    for (i, (_, fraction, size, mass, cohesion)) in GRAIN_TYPES.iter().enumerate() {
+        entries.push((format!("GRAIN_TYPE_{}_FRACTION", i + 1), *fraction));
+        entries.push((format!("GRAIN_TYPE_{}_SIZE", i + 1), *size as f64));
+        entries.push((format!("GRAIN_TYPE_{}_MASS", i + 1), *mass));
+        entries.push((format!("GRAIN_TYPE_{}_COHESION", i + 1), *cohesion as f64));
+    }
+
+    entries
+}
+
+/**
+ * Distribution of each random parameter by its configuration name
+ */
+pub fn parameterDistributions() -> [(&'static str, ParamDistribution); 5] {
+    [
+        ("LANDING_DISTRIBUTION", LANDING_DISTRIBUTION),
+        ("EXTRA_ENERGY_DISTRIBUTION", EXTRA_ENERGY_DISTRIBUTION),
+        ("AVALANCHE_SIZE_DISTRIBUTION", AVALANCHE_SIZE_DISTRIBUTION),
+        ("EXTRA_CAPACITY_DISTRIBUTION", EXTRA_CAPACITY_DISTRIBUTION),
+        ("EXTRA_RESILIENCE_DISTRIBUTION", EXTRA_RESILIENCE_DISTRIBUTION),
+    ]
+}
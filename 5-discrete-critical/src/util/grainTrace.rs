@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::io;
+
+// internal models
+use crate::models::grain::Grain;
+use crate::models::location::Location;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter};
+
+// constants
+use crate::util::constants::{TRACE_GRAIN_IDS, TRACE_EVERY_NTH_GRAIN, TRACE_AVALANCHES, Z_SIZE};
+
+
+// trace of the selected grains written to the run folder
+pub const GRAIN_TRACE_FILE: &str = "grain-trace.csv";
+
+/**
+ * Records the complete path of selected grains to grain-trace.csv, one row each time a traced
+ * grain is updated in an avalanche and one row with its resting place when an avalanche first
+ * moves it, so the time a grain spent at rest and how deeply it was buried can be read from the
+ * rows either side of each re-mobilization
+ *
+ * Grains are traced when their id is in TRACE_GRAIN_IDS, their id is a multiple of
+ * TRACE_EVERY_NTH_GRAIN or they are moved by one of the TRACE_AVALANCHES, once traced a
+ * grain is followed for the rest of the run
+ */
+pub struct GrainTraceRecorder {
+    // ids of the grains moved by one of TRACE_AVALANCHES
+    avalancheGrains: HashSet<u32>,
+    // grain index of the last avalanche each traced grain was recorded in
+    lastAvalanche: Vec<Option<usize>>,
+    // open trace file, None when no grains are traced
+    writer: Option<CsvWriter>,
+    // number of rows written so far
+    pub rowCount: usize,
+}
+
+impl GrainTraceRecorder {
+    pub fn new(totalGrains: usize, folder_path: &str) -> io::Result<Self> {
+        let tracing = !TRACE_GRAIN_IDS.is_empty() || TRACE_EVERY_NTH_GRAIN != 0 || !TRACE_AVALANCHES.is_empty();
+
+        let writer = if tracing {
+            let schema = CsvSchema::new("grain-trace", GRAIN_TRACE_FILE, "Path of each traced grain through the pile, one row per update", vec![
+                CsvColumn::new("grain_id", "integer", "grain id", "Id of the traced grain"),
+                CsvColumn::new("grain_index", "integer", "grain index", "Index of the grain dropped to start the avalanche moving the grain"),
+                CsvColumn::new("step", "integer", "steps", "Time step of the avalanche, 0 for the place the grain was at when the avalanche reached it"),
+                CsvColumn::new("x", "integer", "location", "x of the grain after the step"),
+                CsvColumn::new("y", "integer", "location", "y of the grain after the step"),
+                CsvColumn::new("z", "integer", "location", "z of the grain after the step"),
+                CsvColumn::new("state", "string", "", "State of the grain after the step"),
+                CsvColumn::new("energy", "integer", "energy", "Energy of the grain after the step"),
+                CsvColumn::new("burial_depth", "integer", "grains", "Grains resting in the locations above the location of the grain"),
+            ]);
+            Some(CsvWriter::create(folder_path, GRAIN_TRACE_FILE, &schema)?)
+        } else {
+            None
+        };
+
+        Ok(GrainTraceRecorder {
+            avalancheGrains: HashSet::new(),
+            lastAvalanche: vec![None; totalGrains],
+            writer,
+            rowCount: 0,
+        })
+    }
+
+    /**
+     * Whether the grain is traced, tagging it when it is moved by one of TRACE_AVALANCHES
+     */
+    pub fn isTraced(&mut self, grainId: u32, grainIndex: usize) -> bool {
+        if self.writer.is_none() {
+            return false;
+        }
+        if TRACE_AVALANCHES.contains(&grainIndex) {
+            self.avalancheGrains.insert(grainId);
+        }
+        TRACE_GRAIN_IDS.contains(&grainId)
+            || (TRACE_EVERY_NTH_GRAIN != 0 && (grainId as usize).is_multiple_of(TRACE_EVERY_NTH_GRAIN))
+            || self.avalancheGrains.contains(&grainId)
+    }
+
+    /**
+     * Record where a traced grain was before the update, once per avalanche
+     */
+    pub fn recordStart(&mut self, grainId: u32, grainIndex: usize) -> io::Result<()> {
+        if !self.isTraced(grainId, grainIndex) {
+            return Ok(());
+        }
+        match self.lastAvalanche.get_mut(grainId as usize) {
+            Some(lastAvalanche) if *lastAvalanche != Some(grainIndex) => *lastAvalanche = Some(grainIndex),
+            _ => return Ok(()),
+        }
+        self.writeGrain(grainId, grainIndex, 0)
+    }
+
+    /**
+     * Record a traced grain after it was updated in step of the avalanche
+     */
+    pub fn recordStep(&mut self, grainId: u32, grainIndex: usize, step: usize) -> io::Result<()> {
+        if !self.isTraced(grainId, grainIndex) {
+            return Ok(());
+        }
+        self.writeGrain(grainId, grainIndex, step)
+    }
+
+    fn writeGrain(&mut self, grainId: u32, grainIndex: usize, step: usize) -> io::Result<()> {
+        let Some(grain) = Grain::getGrainById(grainId) else {
+            return Ok(());
+        };
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        writer.writeRow(&[
+            grainId.to_string(),
+            grainIndex.to_string(),
+            step.to_string(),
+            grain.x.to_string(),
+            grain.y.to_string(),
+            grain.z.to_string(),
+            format!("{:?}", grain.state),
+            grain.energy.to_string(),
+            burialDepth(&grain).to_string(),
+        ])?;
+        self.rowCount += 1;
+
+        Ok(())
+    }
+
+    /**
+     * Flush the trace file at the end of the run
+     */
+    pub fn finish(self) -> io::Result<()> {
+        match self.writer {
+            Some(writer) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/**
+ * Grains resting in the locations above the location of the grain, the same whether the grain
+ * is still resting there or has just been released by an avalanche
+ */
+fn burialDepth(grain: &Grain) -> usize {
+    (grain.z + 1..Z_SIZE)
+        .filter_map(|z| Location::getLocationByXyz(grain.x, grain.y, z))
+        .map(|above| above.grainIds.len())
+        .sum()
+}
@@ -1,2 +1,11 @@
 pub mod sandpileUtil;
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod paramDistribution;
+pub mod pileSnapshot;
+pub mod grainTrace;
+pub mod avalancheReplay;
+pub mod pileState;
+pub mod runOutput;
+pub mod runManifest;
+pub mod runFolder;
+pub mod chart;
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+// internal utilities
+use crate::util::sandpileUtil::normalizedPowerLawByOrdersOfMagnitudeWithAlpha;
+
+// constants
+use crate::util::constants::{parameterDistributions, CONTROL_MATCH_SAMPLES};
+
+
+lazy_static! { // Require the lazy_static crate to handle static Mutex
+    // values of each empirical distribution by file path, read at startup by loadEmpiricalDistributions
+    static ref EMPIRICAL_VALUES: Mutex<HashMap<&'static str, Vec<f64>>> = Mutex::new(HashMap::new());
+
+    // kind of control run and the distribution sampled in place of each power-law distribution, empty for a normal run
+    static ref CONTROL: Mutex<Option<(ControlKind, Vec<ControlReplacement>)>> = Mutex::new(None);
+}
+
+// names of the kinds of distribution, the index of a kind is recorded in the run configuration
+pub const PARAM_DISTRIBUTION_KINDS: [&str; 7] = ["pareto", "pareto-magnitude", "exponential", "lognormal", "uniform", "constant", "empirical"];
+
+// environment variable that makes a run a control run, set to exponential or constant by the control command
+pub const CONTROL_VARIABLE: &str = "SANDPILE_CONTROL";
+
+/**
+ * Kind of distribution that replaces the power-law distributions in a control run
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlKind {
+    // exponential whose floored values have the variance of the floored power-law distribution
+    Exponential,
+    // the mean of the power-law distribution
+    Constant,
+}
+
+impl ControlKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "exponential" => Some(ControlKind::Exponential),
+            "constant" => Some(ControlKind::Constant),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlKind::Exponential => "exponential",
+            ControlKind::Constant => "constant",
+        }
+    }
+}
+
+/**
+ * A power-law distribution of a control run with the distribution sampled in its place
+ */
+#[derive(Clone, Debug)]
+pub struct ControlReplacement {
+    // configuration name of the parameter, e.g. EXTRA_ENERGY_DISTRIBUTION
+    pub name: &'static str,
+    pub original: ParamDistribution,
+    pub replacement: ParamDistribution,
+    // mean and variance of the values the model uses from the original distribution
+    pub mean: f64,
+    pub variance: f64,
+}
+
+/**
+ * Distribution a random parameter of the model is drawn from
+ *
+ * The model was built on the order of magnitude of a Pareto sample, floor(log10(x)), which is
+ * almost always 0 or 1, the other kinds let the power-law inputs be swapped out to test whether
+ * they are what produces power-law avalanches
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamDistribution {
+    // Pareto sample x >= xMin with density proportional to x^-alpha
+    Pareto { alpha: f64, xMin: f64 },
+    // order of magnitude floor(log10(x)) of a Pareto sample with an x min of X_MIN
+    ParetoOrderOfMagnitude { alpha: f64 },
+    // exponential sample with the given mean
+    Exponential { mean: f64 },
+    // e^y where y is normally distributed with mean mu and standard deviation sigma
+    LogNormal { mu: f64, sigma: f64 },
+    // whole number from min to max inclusive, each equally likely
+    UniformInt { min: i64, max: i64 },
+    // always the same value
+    Constant { value: f64 },
+    // one of the values in a file, one value per line, lines that are not numbers are skipped
+    Empirical { path: &'static str },
+}
+
+impl ParamDistribution {
+    /**
+     * Draw a value from the distribution
+     */
+    pub fn sample(&self, rnd: &mut impl Rng) -> f64 {
+        if self.isPowerLaw() {
+            if let Some(replacement) = controlReplacement(self) {
+                return replacement.sample(rnd);
+            }
+        }
+
+        match *self {
+            ParamDistribution::Pareto { alpha, xMin } => {
+                xMin * (1.0 - rnd.gen::<f64>()).powf(-1.0 / (alpha - 1.0))
+            },
+            ParamDistribution::ParetoOrderOfMagnitude { alpha } => {
+                normalizedPowerLawByOrdersOfMagnitudeWithAlpha(alpha, rnd)
+            },
+            ParamDistribution::Exponential { mean } => {
+                -mean * (1.0 - rnd.gen::<f64>()).ln()
+            },
+            ParamDistribution::LogNormal { mu, sigma } => {
+                // Box-Muller transform of two uniform samples to a standard normal sample
+                let u1 = 1.0 - rnd.gen::<f64>();
+                let u2 = rnd.gen::<f64>();
+                let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                (mu + sigma * normal).exp()
+            },
+            ParamDistribution::UniformInt { min, max } => {
+                rnd.gen_range(min..=max.max(min)) as f64
+            },
+            ParamDistribution::Constant { value } => value,
+            ParamDistribution::Empirical { path } => {
+                let empiricalValues = EMPIRICAL_VALUES.lock().unwrap();
+                let values = empiricalValues.get(path)
+                    .unwrap_or_else(|| panic!("empirical distribution {} was not loaded, call loadEmpiricalDistributions at startup", path));
+                values[rnd.gen_range(0..values.len())]
+            },
+        }
+    }
+
+    /**
+     * Whether the distribution is one of the power-law kinds replaced in a control run
+     */
+    pub fn isPowerLaw(&self) -> bool {
+        matches!(self, ParamDistribution::Pareto { .. } | ParamDistribution::ParetoOrderOfMagnitude { .. })
+    }
+
+    /**
+     * Name of the kind of distribution, one of PARAM_DISTRIBUTION_KINDS
+     */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParamDistribution::Pareto { .. } => "pareto",
+            ParamDistribution::ParetoOrderOfMagnitude { .. } => "pareto-magnitude",
+            ParamDistribution::Exponential { .. } => "exponential",
+            ParamDistribution::LogNormal { .. } => "lognormal",
+            ParamDistribution::UniformInt { .. } => "uniform",
+            ParamDistribution::Constant { .. } => "constant",
+            ParamDistribution::Empirical { .. } => "empirical",
+        }
+    }
+
+    /**
+     * Kind and parameters of the distribution for the run configuration and manifest
+     */
+    pub fn describe(&self) -> String {
+        match *self {
+            ParamDistribution::Pareto { alpha, xMin } => format!("pareto alpha {} x min {}", alpha, xMin),
+            ParamDistribution::ParetoOrderOfMagnitude { alpha } => format!("order of magnitude of pareto alpha {}", alpha),
+            ParamDistribution::Exponential { mean } => format!("exponential mean {}", mean),
+            ParamDistribution::LogNormal { mu, sigma } => format!("lognormal mu {} sigma {}", mu, sigma),
+            ParamDistribution::UniformInt { min, max } => format!("uniform integer {} to {}", min, max),
+            ParamDistribution::Constant { value } => format!("constant {}", value),
+            ParamDistribution::Empirical { path } => format!("empirical from {}", path),
+        }
+    }
+
+    /**
+     * Configuration entries of the distribution, the kind as its index in PARAM_DISTRIBUTION_KINDS
+     * under the name and each parameter under the name followed by the parameter, e.g. EXTRA_ENERGY_DISTRIBUTION_MEAN
+     */
+    pub fn configurationEntries(&self, name: &str) -> Vec<(String, f64)> {
+        let kind = PARAM_DISTRIBUTION_KINDS.iter().position(|kind| *kind == self.kind()).unwrap_or(0) as f64;
+        let parameters: Vec<(&str, f64)> = match *self {
+            ParamDistribution::Pareto { alpha, xMin } => vec![("ALPHA", alpha), ("X_MIN", xMin)],
+            ParamDistribution::ParetoOrderOfMagnitude { alpha } => vec![("ALPHA", alpha)],
+            ParamDistribution::Exponential { mean } => vec![("MEAN", mean)],
+            ParamDistribution::LogNormal { mu, sigma } => vec![("MU", mu), ("SIGMA", sigma)],
+            ParamDistribution::UniformInt { min, max } => vec![("MIN", min as f64), ("MAX", max as f64)],
+            ParamDistribution::Constant { value } => vec![("VALUE", value)],
+            ParamDistribution::Empirical { .. } => vec![],
+        };
+
+        let mut entries = vec![(name.to_string(), kind)];
+        for (parameter, value) in parameters {
+            entries.push((format!("{}_{}", name, parameter), value));
+        }
+        entries
+    }
+}
+
+/**
+ * Make the run a control run when CONTROL_VARIABLE is set, every power-law parameter distribution is replaced
+ * by an exponential matching the variance of the whole values the model uses or a constant at their mean,
+ * returns the kind of control run
+ */
+pub fn initializeControl() -> io::Result<Option<ControlKind>> {
+    let Ok(name) = env::var(CONTROL_VARIABLE) else {
+        return Ok(None);
+    };
+    let kind = ControlKind::parse(&name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} must be exponential or constant, not {}", CONTROL_VARIABLE, name)))?;
+
+    let mut replacements = Vec::new();
+    for (name, original) in parameterDistributions() {
+        if !original.isPowerLaw() {
+            continue;
+        }
+        let (mean, variance) = usedValueMoments(&original);
+        let replacement = match kind {
+            ControlKind::Exponential => ParamDistribution::Exponential { mean: flooredExponentialMean(variance) },
+            ControlKind::Constant => ParamDistribution::Constant { value: mean },
+        };
+        replacements.push(ControlReplacement { name, original, replacement, mean, variance });
+    }
+
+    *CONTROL.lock().unwrap() = Some((kind, replacements));
+    Ok(Some(kind))
+}
+
+/**
+ * Kind of control run and its replacements, None for a normal run
+ */
+pub fn controlRun() -> Option<(ControlKind, Vec<ControlReplacement>)> {
+    CONTROL.lock().unwrap().clone()
+}
+
+fn controlReplacement(distribution: &ParamDistribution) -> Option<ParamDistribution> {
+    let control = CONTROL.lock().unwrap();
+    let (_, replacements) = control.as_ref()?;
+    replacements.iter().find(|replacement| replacement.original == *distribution).map(|replacement| replacement.replacement)
+}
+
+/**
+ * Mean of the exponential whose floored samples have the variance, the model floors the replacement as well
+ * and floor(x) of an exponential is geometric, P(k) = (1 - q) q^k with q = e^(-1/mean) and variance q / (1 - q)^2
+ */
+fn flooredExponentialMean(variance: f64) -> f64 {
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    let q = (2.0 * variance + 1.0 - (4.0 * variance + 1.0).sqrt()) / (2.0 * variance);
+    -1.0 / q.ln()
+}
+
+/**
+ * Mean and variance of the values the model uses from a distribution, estimated from CONTROL_MATCH_SAMPLES
+ * samples drawn with a fixed seed so they do not take from the run generator, the model only uses whole
+ * values of zero or more so samples are floored and negative samples count as zero
+ */
+fn usedValueMoments(distribution: &ParamDistribution) -> (f64, f64) {
+    let mut rnd = StdRng::seed_from_u64(0);
+    let values: Vec<f64> = (0..CONTROL_MATCH_SAMPLES.max(1)).map(|_| distribution.sample(&mut rnd).max(0.0).floor()).collect();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    (mean, variance)
+}
+
+/**
+ * Read the values of every empirical parameter distribution before the run starts,
+ * a missing file or one without values is an error rather than a panic part way through the run
+ */
+pub fn loadEmpiricalDistributions() -> io::Result<()> {
+    for (name, distribution) in parameterDistributions() {
+        if let ParamDistribution::Empirical { path } = distribution {
+            let values = readEmpiricalValues(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+            EMPIRICAL_VALUES.lock().unwrap().insert(path, values);
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Read the values of an empirical distribution, the first comma separated field of each line that is a number
+ */
+fn readEmpiricalValues(path: &str) -> io::Result<Vec<f64>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("could not read empirical distribution {}: {}", path, e)))?;
+    let values: Vec<f64> = contents.lines().filter_map(|line| line.split(',').next()?.trim().parse::<f64>().ok()).collect();
+    if values.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("empirical distribution {} has no values", path)));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampleMean(distribution: ParamDistribution, seed: u64) -> f64 {
+        let mut rnd = StdRng::seed_from_u64(seed);
+        let samples = 100_000;
+        (0..samples).map(|_| distribution.sample(&mut rnd)).sum::<f64>() / samples as f64
+    }
+
+    #[test]
+    fn sampleMeansMatchTheParameters() {
+        assert!((sampleMean(ParamDistribution::Exponential { mean: 3.0 }, 1) - 3.0).abs() < 0.05);
+        assert!((sampleMean(ParamDistribution::UniformInt { min: 2, max: 6 }, 2) - 4.0).abs() < 0.05);
+        assert_eq!(sampleMean(ParamDistribution::Constant { value: 1.5 }, 3), 1.5);
+
+        let mut rnd = StdRng::seed_from_u64(4);
+        assert!((0..1000).all(|_| {
+            let value = ParamDistribution::UniformInt { min: 2, max: 6 }.sample(&mut rnd);
+            value.fract() == 0.0 && (2.0..=6.0).contains(&value)
+        }));
+    }
+
+    #[test]
+    fn configurationEntriesNameEveryParameter() {
+        let entries = ParamDistribution::LogNormal { mu: 0.5, sigma: 2.0 }.configurationEntries("EXTRA_ENERGY_DISTRIBUTION");
+        assert_eq!(entries, vec![
+            ("EXTRA_ENERGY_DISTRIBUTION".to_string(), 3.0),
+            ("EXTRA_ENERGY_DISTRIBUTION_MU".to_string(), 0.5),
+            ("EXTRA_ENERGY_DISTRIBUTION_SIGMA".to_string(), 2.0),
+        ]);
+
+        let entries = ParamDistribution::Empirical { path: "values.csv" }.configurationEntries("RESILIENCE_DISTRIBUTION");
+        assert_eq!(entries, vec![("RESILIENCE_DISTRIBUTION".to_string(), 6.0)]);
+    }
+
+    #[test]
+    fn readEmpiricalValuesRejectsMissingAndEmptyFiles() {
+        let path = env::temp_dir().join(format!("empirical-values-{}.csv", std::process::id()));
+        let missing = readEmpiricalValues(path.to_str().unwrap());
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        fs::write(&path, "value\nnot a number\n").unwrap();
+        let empty = readEmpiricalValues(path.to_str().unwrap());
+        fs::write(&path, "value\n1.5,first\n2\n").unwrap();
+        let values = readEmpiricalValues(path.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(empty.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(values.unwrap(), vec![1.5, 2.0]);
+    }
+
+    #[test]
+    fn flooredExponentialHasTheVariance() {
+        for variance in [0.1, 1.0, 2.5, 40.0] {
+            let q = (-1.0 / flooredExponentialMean(variance)).exp();
+            assert!((q / (1.0 - q).powi(2) - variance).abs() < 1e-9 * variance.max(1.0));
+        }
+        assert_eq!(flooredExponentialMean(0.0), 0.0);
+    }
+
+    #[test]
+    fn controlKindParsesItsName() {
+        for kind in [ControlKind::Exponential, ControlKind::Constant] {
+            assert_eq!(ControlKind::parse(kind.name()), Some(kind));
+        }
+        assert_eq!(ControlKind::parse("pareto"), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::io;
+
+// internal models
+use crate::models::location::Location;
+use crate::util::pileState::PileState;
+use crate::util::runOutput::{CsvColumn, CsvSchema, CsvWriter, registerOutput};
+
+// constants
+use crate::util::constants::SNAPSHOT_INTERVAL;
+
+
+/**
+ * Writes a numbered series of snapshots of the pile state to the snapshots folder of the run
+ * every SNAPSHOT_INTERVAL grains, each snapshot is made up of
+ *  occupancy-NNNN.txt - the occupancy grid of the pile in the same layout as display-pile.txt
+ *  locations-NNNN.csv - capacity, resilience, roughness, compaction and grain ids of every pile location
+ *  pile-state-NNNN.bin - the full pile state in the binary pile state format (see PileState)
+ * snapshots/index.csv maps each snapshot number to the grain index it was taken after
+ */
+pub struct PileSnapshotRecorder {
+    // number of snapshots written so far
+    pub snapshotCount: usize,
+    // grain index and grains in the pile for each snapshot written so far
+    grainIndexes: Vec<(usize, usize)>,
+}
+
+impl Default for PileSnapshotRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PileSnapshotRecorder {
+    pub fn new() -> Self {
+        PileSnapshotRecorder {
+            snapshotCount: 0,
+            grainIndexes: Vec::new(),
+        }
+    }
+
+    /**
+     * Record a completed avalanche, writing a snapshot on each interval
+     */
+    pub fn recordAvalanche(&mut self, grainIndex: usize, folder_path: String) -> io::Result<()> {
+        if SNAPSHOT_INTERVAL == 0 || !(grainIndex + 1).is_multiple_of(SNAPSHOT_INTERVAL) {
+            return Ok(());
+        }
+        self.takeSnapshot(grainIndex, folder_path)
+    }
+
+    /**
+     * Write the next numbered snapshot of the pile state
+     */
+    pub fn takeSnapshot(&mut self, grainIndex: usize, folder_path: String) -> io::Result<()> {
+        self.snapshotCount += 1;
+
+        let snapshotFolder = folder_path.clone() + "/snapshots";
+        fs::create_dir_all(&snapshotFolder)?;
+
+        Location::displayPileToFile(format!("{}/occupancy-{:04}.txt", snapshotFolder, self.snapshotCount))?;
+        registerOutput(&folder_path, "snapshots/occupancy-<n>.txt", "text", "Grains at each location in the same layout as display-pile.txt", None);
+
+        Location::displayLocationStates(&folder_path, &format!("snapshots/locations-{:04}.csv", self.snapshotCount), "snapshots/locations-<n>.csv")?;
+
+        PileState::capture(grainIndex as u64 + 1).write(format!("{}/pile-state-{:04}.bin", snapshotFolder, self.snapshotCount))?;
+        registerOutput(&folder_path, "snapshots/pile-state-<n>.bin", "binary", "Full pile state in the binary pile state format", None);
+
+        // the index is rewritten with each snapshot so it is complete if the run is stopped
+        self.grainIndexes.push((grainIndex, Location::getTotalGrainsInPile()));
+
+        let schema = CsvSchema::new("index", "snapshots/index.csv", "Grain index each numbered snapshot was taken after", vec![
+            CsvColumn::new("snapshot", "integer", "snapshot number", "Number in the snapshot file names"),
+            CsvColumn::new("grain_index", "integer", "grain index", "Index of the last grain dropped before the snapshot"),
+            CsvColumn::new("grains_in_pile", "integer", "grains", "Total grains resting in the pile"),
+        ]);
+        let mut writer = CsvWriter::create(&folder_path, "snapshots/index.csv", &schema)?;
+
+        for (i, (grainIndex, grainsInPile)) in self.grainIndexes.iter().enumerate() {
+            writer.writeRow(&[(i + 1).to_string(), grainIndex.to_string(), grainsInPile.to_string()])?;
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.finish()?;
+
+        Ok(())
+    }
+}
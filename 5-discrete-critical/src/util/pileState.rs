@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+// internal models
+use crate::models::grain::{Grain, GrainState};
+use crate::models::location::Location;
+
+// constants
+use crate::util::constants::{X_SIZE, Y_SIZE, Z_SIZE, configurationEntries};
+
+
+// identifies a pile state file
+const PILE_STATE_MAGIC: &[u8; 4] = b"SPST";
+// current version of the pile state format, increase when the layout changes
+pub const PILE_STATE_VERSION: u16 = 3;
+// roughness given to locations read from version 1 pile states, which did not record it
+const DEFAULT_LOCATION_ROUGHNESS: f64 = 1.0;
+
+/**
+ * State of a single location in a pile state file
+ */
+#[derive(Clone, Debug)]
+pub struct LocationState {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub capacity: usize,
+    pub resilience: usize,
+    pub roughness: f64,
+    pub impacts: usize,
+    pub compactions: usize,
+    pub intactResilience: usize,
+    pub grainIds: Vec<u32>,
+}
+
+/**
+ * Compact, versioned binary representation of the pile
+ *
+ * Layout, all fixed width values are little endian and all varints are unsigned LEB128
+ *  magic "SPST" (4 bytes), version (u16)
+ *  x size, y size, z size (i32 each), grains dropped (u64)
+ *  config entry count (u16), each entry is a name length (u8), the name (utf-8) and a value (f64)
+ *  one record per location ordered by z, y, x covering the full x size * y size * z size grid
+ *   capacity (varint), resilience (varint), roughness (f64, from version 2),
+ *   impacts, compactions and intact resilience (varint each, from version 3), grain count (varint), grain ids (varint each)
+ */
+#[derive(Clone, Debug)]
+pub struct PileState {
+    pub version: u16,
+    pub xSize: i32,
+    pub ySize: i32,
+    pub zSize: i32,
+    // number of grains dropped on the pile when the state was captured
+    pub grainsDropped: u64,
+    // run configuration the pile was built with
+    pub config: Vec<(String, f64)>,
+    // locations ordered by z, y, x
+    pub locations: Vec<LocationState>,
+}
+
+impl PileState {
+    /**
+     * Capture the current state of all the locations in the pile
+     */
+    pub fn capture(grainsDropped: u64) -> Self {
+        let locations = Location::getAllLocations().into_iter()
+            .map(|location| LocationState {
+                x: location.x,
+                y: location.y,
+                z: location.z,
+                capacity: location.capacity,
+                resilience: location.resilience,
+                roughness: location.roughness,
+                impacts: location.impacts,
+                compactions: location.compactions,
+                intactResilience: location.intactResilience,
+                grainIds: location.grainIds,
+            })
+            .collect();
+
+        PileState {
+            version: PILE_STATE_VERSION,
+            xSize: X_SIZE,
+            ySize: Y_SIZE,
+            zSize: Z_SIZE,
+            grainsDropped,
+            config: configurationEntries(),
+            locations,
+        }
+    }
+
+    /**
+     * Write the pile state to a file
+     */
+    pub fn write(&self, file_path: String) -> io::Result<()> {
+        // Create a file and wrap it in a BufWriter for efficient writing
+        let file = File::create(file_path)?;
+        let mut writer = BufWriter::new(file);
+
+        writer.write_all(PILE_STATE_MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.xSize.to_le_bytes())?;
+        writer.write_all(&self.ySize.to_le_bytes())?;
+        writer.write_all(&self.zSize.to_le_bytes())?;
+        writer.write_all(&self.grainsDropped.to_le_bytes())?;
+
+        writer.write_all(&(self.config.len() as u16).to_le_bytes())?;
+        for (name, value) in &self.config {
+            writer.write_all(&[name.len() as u8])?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        for location in &self.locations {
+            writeVarint(&mut writer, location.capacity as u64)?;
+            writeVarint(&mut writer, location.resilience as u64)?;
+            writer.write_all(&location.roughness.to_le_bytes())?;
+            writeVarint(&mut writer, location.impacts as u64)?;
+            writeVarint(&mut writer, location.compactions as u64)?;
+            writeVarint(&mut writer, location.intactResilience as u64)?;
+            writeVarint(&mut writer, location.grainIds.len() as u64)?;
+            for grainId in &location.grainIds {
+                writeVarint(&mut writer, *grainId as u64)?;
+            }
+        }
+
+        // flush the writer to ensure all data is written to the file
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /**
+     * Read a pile state from a file
+     */
+    pub fn read(file_path: String) -> io::Result<Self> {
+        let file = File::open(file_path)?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != PILE_STATE_MAGIC {
+            return Err(invalidData("not a pile state file".to_string()));
+        }
+
+        let version = u16::from_le_bytes(readBytes(&mut reader)?);
+        // versions start at 1, any other version was never written by this format
+        if version == 0 || version > PILE_STATE_VERSION {
+            return Err(invalidData(format!("unsupported pile state version {}", version)));
+        }
+
+        let xSize = i32::from_le_bytes(readBytes(&mut reader)?);
+        let ySize = i32::from_le_bytes(readBytes(&mut reader)?);
+        let zSize = i32::from_le_bytes(readBytes(&mut reader)?);
+        let grainsDropped = u64::from_le_bytes(readBytes(&mut reader)?);
+        if xSize < 0 || ySize < 0 || zSize < 0 {
+            return Err(invalidData(format!("invalid pile size {} x {} x {}", xSize, ySize, zSize)));
+        }
+
+        let configCount = u16::from_le_bytes(readBytes(&mut reader)?);
+        let mut config = Vec::with_capacity(configCount as usize);
+        for _ in 0..configCount {
+            let [nameLength] = readBytes::<1>(&mut reader)?;
+            let mut name = vec![0u8; nameLength as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|e| invalidData(e.to_string()))?;
+            let value = f64::from_le_bytes(readBytes(&mut reader)?);
+            config.push((name, value));
+        }
+
+        // the sizes and counts come from the file so nothing is allocated from them up front
+        if xSize.checked_mul(ySize).and_then(|area| area.checked_mul(zSize)).is_none() {
+            return Err(invalidData(format!("pile size {} x {} x {} is too large", xSize, ySize, zSize)));
+        }
+        let mut locations = Vec::new();
+        for z in 0..zSize {
+            for y in 0..ySize {
+                for x in 0..xSize {
+                    let capacity = readVarint(&mut reader)? as usize;
+                    let resilience = readVarint(&mut reader)? as usize;
+                    let roughness = if version >= 2 { f64::from_le_bytes(readBytes(&mut reader)?) } else { DEFAULT_LOCATION_ROUGHNESS };
+                    // locations from before version 3 have not been compacted or weakened
+                    let (impacts, compactions, intactResilience) = if version >= 3 {
+                        (readVarint(&mut reader)? as usize, readVarint(&mut reader)? as usize, readVarint(&mut reader)? as usize)
+                    } else {
+                        (0, 0, resilience)
+                    };
+                    let grainCount = readVarint(&mut reader)? as usize;
+                    let mut grainIds = Vec::new();
+                    for _ in 0..grainCount {
+                        grainIds.push(readVarint(&mut reader)? as u32);
+                    }
+                    locations.push(LocationState { x, y, z, capacity, resilience, roughness, impacts, compactions, intactResilience, grainIds });
+                }
+            }
+        }
+
+        Ok(PileState {
+            version,
+            xSize,
+            ySize,
+            zSize,
+            grainsDropped,
+            config,
+            locations,
+        })
+    }
+
+    /**
+     * Load the pile state back into the locations and grains of the running sandpile,
+     * grains held by a location are placed at that location as stationary grains, dropped
+     * grains held by none have left the pile and grains not yet dropped are reset to be dropped
+     */
+    pub fn restore(&self) -> io::Result<()> {
+        if self.xSize != X_SIZE || self.ySize != Y_SIZE || self.zSize != Z_SIZE {
+            return Err(invalidData(format!("pile state size {} x {} x {} does not match the pile size {} x {} x {}",
+                self.xSize, self.ySize, self.zSize, X_SIZE, Y_SIZE, Z_SIZE)));
+        }
+
+        for state in &self.locations {
+            // location ids follow the x, y, z order used by Location::initializeLocations
+            let id = ((state.x * Y_SIZE + state.y) * Z_SIZE + state.z) as u32;
+            let mut location = Location {
+                id,
+                x: state.x,
+                y: state.y,
+                z: state.z,
+                capacity: state.capacity,
+                grainIds: state.grainIds.clone(),
+                resilience: state.resilience,
+                roughness: state.roughness,
+                impacts: state.impacts,
+                compactions: state.compactions,
+                intactResilience: state.intactResilience,
+            };
+            location.saveLocation();
+
+            for grainId in &state.grainIds {
+                let mut grain = Grain::getGrainById(*grainId).unwrap_or_else(|| Grain::new(*grainId));
+                grain.x = state.x;
+                grain.y = state.y;
+                grain.z = state.z;
+                // remove the grains energy and momentum, as incomingGrain does for a grain coming to rest
+                grain.energy = 0;
+                grain.velocity = (0, 0, 0);
+                grain.state = GrainState::Stationary;
+                grain.saveGrain();
+            }
+        }
+
+        let heldGrainIds: HashSet<u32> = self.locations.iter().flat_map(|state| state.grainIds.iter().cloned()).collect();
+        for grainId in Grain::getAllGrainIds() {
+            if heldGrainIds.contains(&grainId) {
+                continue;
+            }
+            let Some(mut grain) = Grain::getGrainById(grainId) else {
+                continue;
+            };
+            if (grainId as u64) < self.grainsDropped {
+                grain.energy = 0;
+                grain.velocity = (0, 0, 0);
+                grain.state = GrainState::OffPile;
+                grain.saveGrain();
+            } else if grain.state != GrainState::Unknown {
+                Grain::new(grainId).saveGrain();
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Get a configuration value by name
+     */
+    pub fn getConfig(&self, name: &str) -> Option<f64> {
+        self.config.iter().find(|(key, _)| key == name).map(|(_, value)| *value)
+    }
+
+    /**
+     * Get the state of the location at x, y, z
+     */
+    pub fn getLocation(&self, x: i32, y: i32, z: i32) -> Option<&LocationState> {
+        if x < 0 || y < 0 || z < 0 || x >= self.xSize || y >= self.ySize || z >= self.zSize {
+            return None;
+        }
+        self.locations.get(((z * self.ySize + y) * self.xSize + x) as usize)
+    }
+}
+
+pub fn invalidData(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn readBytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn writeVarint(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            writer.write_all(&[byte])?;
+            return Ok(());
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub fn readVarint(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let [byte] = readBytes::<1>(reader)?;
+        if shift >= 64 {
+            return Err(invalidData("varint is too long".to_string()));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writeAndReadRoundTrip() {
+        let state = PileState {
+            version: PILE_STATE_VERSION,
+            xSize: 2,
+            ySize: 1,
+            zSize: 1,
+            grainsDropped: 300,
+            config: vec![("TOTAL_GRAINS".to_string(), 300.0), ("ALPHA_EXTRA_ENERGY".to_string(), 1.2)],
+            locations: vec![
+                LocationState { x: 0, y: 0, z: 0, capacity: 5, resilience: 3, roughness: 1.25, impacts: 7, compactions: 1, intactResilience: 4, grainIds: vec![0, 200, 129] },
+                LocationState { x: 1, y: 0, z: 0, capacity: 4, resilience: 2, roughness: 0.5, impacts: 0, compactions: 0, intactResilience: 2, grainIds: vec![] },
+            ],
+        };
+        let path = std::env::temp_dir().join(format!("pile-state-round-trip-{}.bin", std::process::id()));
+        state.write(path.to_string_lossy().to_string()).unwrap();
+        let read = PileState::read(path.to_string_lossy().to_string()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(read.version, PILE_STATE_VERSION);
+        assert_eq!((read.xSize, read.ySize, read.zSize), (2, 1, 1));
+        assert_eq!(read.grainsDropped, 300);
+        assert_eq!(read.config, state.config);
+        assert_eq!(read.locations.len(), 2);
+        for (read, written) in read.locations.iter().zip(&state.locations) {
+            assert_eq!((read.x, read.y, read.z), (written.x, written.y, written.z));
+            assert_eq!((read.capacity, read.resilience, read.roughness), (written.capacity, written.resilience, written.roughness));
+            assert_eq!((read.impacts, read.compactions, read.intactResilience), (written.impacts, written.compactions, written.intactResilience));
+            assert_eq!(read.grainIds, written.grainIds);
+        }
+    }
+
+    #[test]
+    fn readRejectsOversizedPile() {
+        let mut bytes = PILE_STATE_MAGIC.to_vec();
+        bytes.extend(PILE_STATE_VERSION.to_le_bytes());
+        for size in [100000i32, 100000, 100000] {
+            bytes.extend(size.to_le_bytes());
+        }
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        let path = std::env::temp_dir().join(format!("pile-state-oversized-{}.bin", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let error = PileState::read(path.to_string_lossy().to_string()).err().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn readRejectsUnwrittenVersion() {
+        let mut bytes = PILE_STATE_MAGIC.to_vec();
+        bytes.extend(0u16.to_le_bytes());
+        let path = std::env::temp_dir().join(format!("pile-state-version-{}.bin", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let error = PileState::read(path.to_string_lossy().to_string()).err().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+
+// internal models
+use crate::analysis::distributions::{AVALANCHE_SERIES_FILE, AvalancheSeries};
+use crate::analysis::predictability::{PRE_DROP_STATE_FILE, PreDropSeries};
+use crate::util::runManifest::RUN_MANIFEST_FILE;
+
+
+// avalanche distributions written to every run folder as histograms of (value, number of avalanches)
+pub const RUN_DISTRIBUTIONS: [(&str, &str); 3] = [
+    ("movement", "avalanche-movement-stats.csv"),
+    ("grains", "grain-stats.csv"),
+    ("magnitude", "avalanche-total-magnitude.csv"),
+];
+
+/**
+ * A finished run folder in the data directory with the configuration it was run with
+ *
+ * The configuration is read from manifest.json when the run has one, otherwise from
+ * run-configuration.txt, which older runs wrote as "Label (note): value" lines, and finally
+ * from the folder name, which always holds the total grains and the pile size
+ */
+#[derive(Clone, Debug)]
+pub struct RunFolder {
+    pub path: PathBuf,
+    // folder name of the run
+    pub runId: String,
+    // timestamp at the start of the folder name
+    pub startTime: Option<String>,
+    // free text added to the end of the folder name, e.g. extra-energy-2.0-and-Resilience-1.8
+    pub label: Option<String>,
+    // manifest, run-configuration or folder-name
+    pub configSource: &'static str,
+    // configuration constants by name, e.g. ALPHA_EXTRA_ENERGY
+    pub config: BTreeMap<String, f64>,
+    // seed the run was started with, runs before seeding was added have none
+    pub seed: Option<u64>,
+    // mean branching ratio and its standard error from the manifest, runs before it was estimated have none
+    pub branchingRatio: Option<(f64, f64)>,
+}
+
+impl RunFolder {
+    /**
+     * Load the run in a folder
+     */
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let runId = path.file_name().and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a run folder", path.display())))?
+            .to_string();
+
+        let (startTime, label, mut config) = parseFolderName(&runId);
+        let mut configSource = "folder-name";
+        let mut seed = None;
+        let mut branchingRatio = None;
+
+        let manifestPath = path.join(RUN_MANIFEST_FILE);
+        let configurationPath = path.join("run-configuration.txt");
+        if manifestPath.is_file() {
+            let manifest: Value = serde_json::from_reader(File::open(manifestPath)?)?;
+            if let Some(entries) = manifest["config"].as_object() {
+                for (name, value) in entries {
+                    if let Some(value) = value.as_f64() {
+                        config.insert(name.clone(), value);
+                    }
+                }
+            }
+            seed = manifest["seed"].as_u64();
+            if let (Some(sigma), Some(sigmaError)) = (manifest["criticality"]["sigma"].as_f64(), manifest["criticality"]["sigmaError"].as_f64()) {
+                branchingRatio = Some((sigma, sigmaError));
+            }
+            configSource = "manifest";
+        } else if configurationPath.is_file() {
+            config.extend(parseRunConfiguration(&configurationPath)?);
+            seed = config.get("RNG_SEED").map(|seed| *seed as u64);
+            configSource = "run-configuration";
+        }
+
+        Ok(RunFolder {
+            path: path.to_path_buf(),
+            runId,
+            startTime,
+            label,
+            configSource,
+            config,
+            seed,
+            branchingRatio,
+        })
+    }
+
+    /**
+     * Load every run folder in the data directory, oldest first, folders that are not runs are skipped
+     */
+    pub fn scan(dataPath: &Path) -> io::Result<Vec<Self>> {
+        let mut runs = Vec::new();
+        for entry in fs::read_dir(dataPath)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let run = Self::load(&entry.path())?;
+            if run.startTime.is_some() || run.configSource != "folder-name" {
+                runs.push(run);
+            }
+        }
+        runs.sort_by(|a, b| a.runId.cmp(&b.runId));
+        Ok(runs)
+    }
+
+    /**
+     * Get a configuration value by name, names are not case sensitive
+     */
+    pub fn getConfig(&self, name: &str) -> Option<f64> {
+        self.config.get(&name.to_uppercase()).cloned()
+    }
+
+    /**
+     * Read each column of avalanches.csv by name, runs from before the avalanche series was written have none
+     */
+    pub fn readAvalancheSeries(&self) -> io::Result<Option<AvalancheSeries>> {
+        let path = self.path.join(AVALANCHE_SERIES_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut lines = BufReader::new(File::open(&path)?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let mut series: AvalancheSeries = header.split(',').map(|name| (name.to_string(), Vec::new())).collect();
+        for line in lines {
+            let line = line?;
+            for ((_, values), value) in series.iter_mut().zip(line.split(',')) {
+                values.push(value.parse::<u64>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid row \"{}\" in {}", line, path.display())))?);
+            }
+        }
+        Ok(Some(series))
+    }
+
+    /**
+     * Read each column of pre-drop-state.csv by name, runs without PRE_DROP_INTERVAL set have none
+     */
+    pub fn readPreDropState(&self) -> io::Result<Option<PreDropSeries>> {
+        let path = self.path.join(PRE_DROP_STATE_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut lines = BufReader::new(File::open(&path)?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let mut series: PreDropSeries = header.split(',').map(|name| (name.to_string(), Vec::new())).collect();
+        for line in lines {
+            let line = line?;
+            for ((_, values), value) in series.iter_mut().zip(line.split(',')) {
+                values.push(value.parse::<f64>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("invalid row \"{}\" in {}", line, path.display())))?);
+            }
+        }
+        Ok(Some(series))
+    }
+
+    /**
+     * Read the histogram of one of the RUN_DISTRIBUTIONS (movement, grains or magnitude)
+     */
+    pub fn readDistribution(&self, distribution: &str) -> io::Result<Vec<(u64, u64)>> {
+        let fileName = RUN_DISTRIBUTIONS.iter().find(|(name, _)| *name == distribution).map(|(_, fileName)| *fileName)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown distribution {}", distribution)))?;
+        readHistogram(&self.path.join(fileName))
+    }
+}
+
+/**
+ * Read a histogram csv of (value, count) rows after a header line, both the current
+ * "5,125" rows and the older "5, 125" rows are read
+ */
+pub fn readHistogram(path: &Path) -> io::Result<Vec<(u64, u64)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut histogram = Vec::new();
+    for line in reader.lines().skip(1) {
+        let line = line?;
+        let mut values = line.split(',').map(|value| value.trim());
+        let (Some(value), Some(count)) = (values.next(), values.next()) else {
+            continue;
+        };
+        match (value.parse::<u64>(), count.parse::<u64>()) {
+            (Ok(value), Ok(count)) => histogram.push((value, count)),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid histogram row \"{}\" in {}", line, path.display()))),
+        }
+    }
+    Ok(histogram)
+}
+
+/**
+ * Start time, label and configuration held in a folder name such as
+ * 2024-05-03_00-10-07-gs-100000-ps-21-21-16-extra-energy-2.0-and-Resilience-1.8
+ */
+fn parseFolderName(runId: &str) -> (Option<String>, Option<String>, BTreeMap<String, f64>) {
+    let mut config = BTreeMap::new();
+
+    let Some((startTime, rest)) = runId.split_once("-gs-") else {
+        return (None, None, config);
+    };
+
+    let parts: Vec<&str> = rest.split('-').collect();
+    if let Some(totalGrains) = parts.first().and_then(|value| value.parse::<f64>().ok()) {
+        config.insert("TOTAL_GRAINS".to_string(), totalGrains);
+    }
+
+    let mut label = None;
+    if parts.len() >= 5 && parts[1] == "ps" {
+        for (name, value) in ["X_SIZE", "Y_SIZE", "Z_SIZE"].iter().zip(&parts[2..5]) {
+            if let Ok(value) = value.parse::<f64>() {
+                config.insert(name.to_string(), value);
+            }
+        }
+        if parts.len() > 5 {
+            label = Some(parts[5..].join("-"));
+        }
+    }
+
+    (Some(startTime.to_string()), label, config)
+}
+
+/**
+ * Configuration in a run-configuration.txt, each "Label (note): value" line is stored under the
+ * name of its constant, e.g. "Alpha Extra Energy (amount of ...): 1.2" as ALPHA_EXTRA_ENERGY
+ */
+fn parseRunConfiguration(path: &Path) -> io::Result<BTreeMap<String, f64>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut config = BTreeMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let Some((label, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        // the label without any note in parentheses
+        let label = label.split('(').next().unwrap_or("").trim();
+        let name = label.split_whitespace().map(|word| word.to_uppercase()).collect::<Vec<String>>().join("_");
+
+        if name == "PILE_SIZE" {
+            for (name, size) in ["X_SIZE", "Y_SIZE", "Z_SIZE"].iter().zip(value.split('x')) {
+                if let Ok(size) = size.trim().parse::<f64>() {
+                    config.insert(name.to_string(), size);
+                }
+            }
+        } else if let Some(value) = value.split_whitespace().last().and_then(|value| value.parse::<f64>().ok()) {
+            // notes in the value come before the value itself, e.g. "(base for avalanche method=1) 2"
+            config.insert(name, value);
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parseLegacyFolderName() {
+        let (startTime, label, config) = parseFolderName("2024-05-03_00-10-07-gs-100000-ps-21-21-16-extra-energy-2.0-and-Resilience-1.8");
+
+        assert_eq!(startTime.as_deref(), Some("2024-05-03_00-10-07"));
+        assert_eq!(label.as_deref(), Some("extra-energy-2.0-and-Resilience-1.8"));
+        assert_eq!(config.get("TOTAL_GRAINS"), Some(&100000.0));
+        assert_eq!(config.get("X_SIZE"), Some(&21.0));
+        assert_eq!(config.get("Y_SIZE"), Some(&21.0));
+        assert_eq!(config.get("Z_SIZE"), Some(&16.0));
+    }
+
+    #[test]
+    fn parseLegacyRunConfiguration() {
+        let contents = "Run configuration
+---------------------------------------------------------------------------------------------------
+Total Grains: 100000
+Pile Size: 21 x 21 x 16
+Base Avalanche Size: (base for avalanche method=1) 2
+Alpha Extra Energy (amount of additional energy added to impact): 2
+Alpha Location Extra Resilience (additional resilience of location): 1.8
+---------------------------------------------------------------------------------------------------
+";
+        let path = std::env::temp_dir().join(format!("run-configuration-{}.txt", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let config = parseRunConfiguration(&path);
+        let _ = fs::remove_file(&path);
+        let config = config.unwrap();
+
+        assert_eq!(config.get("TOTAL_GRAINS"), Some(&100000.0));
+        assert_eq!(config.get("X_SIZE"), Some(&21.0));
+        assert_eq!(config.get("Z_SIZE"), Some(&16.0));
+        assert_eq!(config.get("BASE_AVALANCHE_SIZE"), Some(&2.0));
+        assert_eq!(config.get("ALPHA_EXTRA_ENERGY"), Some(&2.0));
+        assert_eq!(config.get("ALPHA_LOCATION_EXTRA_RESILIENCE"), Some(&1.8));
+        assert_eq!(config.len(), 7);
+    }
+}
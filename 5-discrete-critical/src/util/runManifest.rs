@@ -0,0 +1,207 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use chrono::{DateTime, Local};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+// constants
+use crate::util::constants::{configurationEntries, parameterDistributions, ALPHA_LANDING, LANDING_DISTRIBUTION, X_SIZE, Y_SIZE, Z_SIZE};
+use crate::util::runOutput::RUN_LAYOUT_FILE;
+use crate::util::paramDistribution::controlRun;
+use crate::analysis::branching::BranchingEstimate;
+use crate::models::avalancheSizeRule::configuredAvalancheSizeRule;
+
+
+// name of the machine readable manifest written to every run folder
+pub const RUN_MANIFEST_FILE: &str = "manifest.json";
+// current version of the manifest layout, increase when fields are renamed or removed
+pub const RUN_MANIFEST_VERSION: u32 = 1;
+
+/**
+ * Results of a run recorded in the manifest
+ */
+pub struct RunSummary {
+    // seed the random number generator was initialized with
+    pub seed: u64,
+    pub startTime: DateTime<Local>,
+    pub endTime: DateTime<Local>,
+    pub grainsDropped: usize,
+    pub grainsInPile: usize,
+    pub grainsOffPile: usize,
+    // grain index where the pile became stationary, if it did
+    pub stationaryAtGrain: Option<usize>,
+    // mean branching ratio of the avalanches once the pile is stationary, see analysis::branching
+    pub branching: Option<BranchingEstimate>,
+}
+
+/**
+ * Write manifest.json for the run folder with the full configuration, provenance of the
+ * build and machine that produced it, timing, totals and a checksum of every output file
+ * this should be the last file written to the run folder
+ */
+pub fn displayRunManifest(folder_path: String, summary: &RunSummary) -> io::Result<()> {
+
+    let mut config = Map::new();
+    for (name, value) in configurationEntries() {
+        config.insert(name, json!(value));
+    }
+
+    let mut distributions = Map::new();
+    for (name, distribution) in parameterDistributions() {
+        distributions.insert(name.to_string(), json!({
+            "kind": distribution.kind(),
+            "description": distribution.describe(),
+        }));
+    }
+
+    // the power-law distributions of a control run with what was sampled in their place
+    let control = controlRun().map(|(kind, replacements)| json!({
+        "kind": kind.name(),
+        "replacements": replacements.iter().map(|replacement| json!({
+            "name": replacement.name,
+            "original": replacement.original.describe(),
+            "replacement": replacement.replacement.describe(),
+            "mean": replacement.mean,
+            "variance": replacement.variance,
+        })).collect::<Vec<Value>>(),
+    }));
+
+    let wallTime = summary.endTime.signed_duration_since(summary.startTime);
+
+    let manifest = json!({
+        "manifestVersion": RUN_MANIFEST_VERSION,
+        "runId": Path::new(&folder_path).file_name().and_then(|name| name.to_str()),
+        "crate": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "git": gitProvenance(),
+        "hostname": hostname(),
+        "seed": summary.seed,
+        "config": config,
+        "distributions": distributions,
+        "control": control,
+        "avalancheSizeRule": {
+            "name": configuredAvalancheSizeRule().name(),
+            "description": configuredAvalancheSizeRule().describe(),
+        },
+        "grainDropScheme": {
+            "method": "single grain dropped per avalanche at the center of the top layer, offset in x and y by samples of the landing distribution",
+            "dropX": X_SIZE / 2,
+            "dropY": Y_SIZE / 2,
+            "dropZ": Z_SIZE - 1,
+            "alphaLanding": ALPHA_LANDING,
+            "landingDistribution": LANDING_DISTRIBUTION.describe(),
+            "grainsPerDrop": 1,
+        },
+        "timing": {
+            "start": summary.startTime.to_rfc3339(),
+            "end": summary.endTime.to_rfc3339(),
+            "wallTimeSeconds": wallTime.num_milliseconds() as f64 / 1000.0,
+        },
+        "peakMemoryKb": peakMemoryKb(),
+        "totals": {
+            "grainsDropped": summary.grainsDropped,
+            "grainsInPile": summary.grainsInPile,
+            "grainsOffPile": summary.grainsOffPile,
+            "stationaryAtGrain": summary.stationaryAtGrain,
+        },
+        "criticality": summary.branching.map(|estimate| json!({
+            "sigma": estimate.sigma,
+            "sigmaError": estimate.sigmaError,
+            "regime": estimate.regime(),
+            "nodes": estimate.nodes,
+            "children": estimate.children,
+            "fromGrain": summary.stationaryAtGrain.unwrap_or(0),
+        })),
+        "layout": RUN_LAYOUT_FILE,
+        "files": fileChecksums(&folder_path)?,
+    });
+
+    let file = File::create(format!("{}/{}", folder_path, RUN_MANIFEST_FILE))?;
+    serde_json::to_writer_pretty(file, &manifest)?;
+
+    Ok(())
+}
+
+/**
+ * Commit of the source tree the binary was built from and whether it had uncommitted changes,
+ * captured by build.rs so the run records the build rather than the directory it started in
+ */
+fn gitProvenance() -> Value {
+    let commit = option_env!("SANDPILE_GIT_COMMIT");
+    let dirty = option_env!("SANDPILE_GIT_DIRTY").map(|dirty| dirty == "true");
+
+    json!({
+        "commit": commit,
+        "dirty": dirty,
+    })
+}
+
+fn hostname() -> Option<String> {
+    nix::unistd::gethostname().ok().and_then(|name| name.into_string().ok())
+}
+
+/**
+ * Peak resident memory of the process in kilobytes
+ */
+fn peakMemoryKb() -> Option<i64> {
+    let usage = nix::sys::resource::getrusage(nix::sys::resource::UsageWho::RUSAGE_SELF).ok()?;
+    // macOS reports the maximum resident set size in bytes, linux in kilobytes
+    if cfg!(target_os = "macos") {
+        Some(usage.max_rss() / 1024)
+    } else {
+        Some(usage.max_rss())
+    }
+}
+
+/**
+ * Size and sha256 of every file in the run folder, paths relative to the run folder
+ */
+fn fileChecksums(folder_path: &str) -> io::Result<Vec<Value>> {
+    let mut paths = Vec::new();
+    collectFiles(Path::new(folder_path), Path::new(""), &mut paths)?;
+    paths.sort();
+
+    let mut files = Vec::with_capacity(paths.len());
+    for relativePath in paths {
+        if relativePath == RUN_MANIFEST_FILE {
+            continue;
+        }
+
+        let mut file = File::open(Path::new(folder_path).join(&relativePath))?;
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+        let mut bytes: u64 = 0;
+        loop {
+            let count = file.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+            bytes += count as u64;
+        }
+
+        let digest: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        files.push(json!({
+            "path": relativePath,
+            "bytes": bytes,
+            "sha256": digest,
+        }));
+    }
+    Ok(files)
+}
+
+fn collectFiles(root: &Path, relative: &Path, paths: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collectFiles(root, &path, paths)?;
+        } else if let Some(path) = path.to_str() {
+            paths.push(path.replace('\\', "/"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+
+
+lazy_static! { // Require the lazy_static crate to handle static Mutex
+    // files written to each run folder, keyed by run folder then by path pattern, used to document the run folder layout
+    static ref RUN_OUTPUTS: Mutex<HashMap<String, Vec<RunOutput>>> = Mutex::new(HashMap::new());
+}
+
+// name of the file documenting the layout of a run folder
+pub const RUN_LAYOUT_FILE: &str = "run-layout.json";
+
+/**
+ * A file, or numbered series of files, written to a run folder
+ */
+#[derive(Clone, Debug)]
+pub struct RunOutput {
+    // path relative to the run folder, series use <n> in place of the number
+    pub pathPattern: String,
+    // csv, text or binary
+    pub format: String,
+    pub description: String,
+    // schema sidecar relative to the run folder, csv files only
+    pub schema: Option<String>,
+}
+
+/**
+ * Description of a single column in a csv output
+ */
+#[derive(Clone, Debug)]
+pub struct CsvColumn {
+    pub name: String,
+    // integer, float or string
+    pub dataType: &'static str,
+    pub unit: &'static str,
+    pub description: String,
+}
+
+impl CsvColumn {
+    pub fn new(name: &str, dataType: &'static str, unit: &'static str, description: &str) -> Self {
+        CsvColumn {
+            name: name.to_string(),
+            dataType,
+            unit,
+            description: description.to_string(),
+        }
+    }
+}
+
+/**
+ * Schema of a csv output, written next to the csv as <name>.schema.json
+ */
+#[derive(Clone, Debug)]
+pub struct CsvSchema {
+    // name of the output, used for the schema sidecar file name
+    pub name: String,
+    // path relative to the run folder, series use <n> in place of the number
+    pub pathPattern: String,
+    pub description: String,
+    pub columns: Vec<CsvColumn>,
+}
+
+impl CsvSchema {
+    pub fn new(name: &str, pathPattern: &str, description: &str, columns: Vec<CsvColumn>) -> Self {
+        CsvSchema {
+            name: name.to_string(),
+            pathPattern: pathPattern.to_string(),
+            description: description.to_string(),
+            columns,
+        }
+    }
+
+    /**
+     * Path of the schema sidecar relative to the run folder, in the same folder as the csv
+     */
+    pub fn sidecarPath(&self) -> String {
+        match Path::new(&self.pathPattern).parent().and_then(|parent| parent.to_str()) {
+            Some(parent) if !parent.is_empty() => format!("{}/{}.schema.json", parent, self.name),
+            _ => format!("{}.schema.json", self.name),
+        }
+    }
+
+    pub fn toJson(&self) -> Value {
+        json!({
+            "name": self.name,
+            "path": self.pathPattern,
+            "description": self.description,
+            "format": "csv",
+            "delimiter": ",",
+            "header": true,
+            "columns": self.columns.iter().map(|column| json!({
+                "name": column.name,
+                "type": column.dataType,
+                "unit": column.unit,
+                "description": column.description,
+            })).collect::<Vec<Value>>(),
+        })
+    }
+}
+
+/**
+ * Common writer for all csv outputs of a run
+ * Writes the header from the schema, checks each row against it and documents the
+ * file in the run layout with a schema sidecar the first time the schema is written
+ */
+pub struct CsvWriter {
+    writer: BufWriter<File>,
+    columnCount: usize,
+}
+
+impl CsvWriter {
+    /**
+     * Create the csv at relativePath inside the run folder
+     */
+    pub fn create(folder_path: &str, relativePath: &str, schema: &CsvSchema) -> io::Result<Self> {
+        let file = File::create(format!("{}/{}", folder_path, relativePath))?;
+        let mut writer = BufWriter::new(file);
+
+        let header: Vec<&str> = schema.columns.iter().map(|column| column.name.as_str()).collect();
+        writeln!( writer, "{}", header.join(","))?;
+
+        // document the schema the first time it is written to this run folder
+        if registerOutput(folder_path, &schema.pathPattern, "csv", &schema.description, Some(schema.sidecarPath())) {
+            let sidecar = File::create(format!("{}/{}", folder_path, schema.sidecarPath()))?;
+            serde_json::to_writer_pretty(sidecar, &schema.toJson())?;
+        }
+
+        Ok(CsvWriter {
+            writer,
+            columnCount: schema.columns.len(),
+        })
+    }
+
+    /**
+     * Write a single row, the number of values must match the columns of the schema
+     */
+    pub fn writeRow(&mut self, values: &[String]) -> io::Result<()> {
+        if values.len() != self.columnCount {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("csv row has {} values but the schema has {} columns", values.len(), self.columnCount)));
+        }
+        writeln!( self.writer, "{}", values.join(","))
+    }
+
+    /**
+     * Flush the writer to ensure all data is written to the file
+     */
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/**
+ * Note a file written to the run folder so it is included in the run layout
+ * returns true if the path pattern was not already registered for the run folder
+ */
+pub fn registerOutput(folder_path: &str, pathPattern: &str, format: &str, description: &str, schema: Option<String>) -> bool {
+    let mut runOutputs = RUN_OUTPUTS.lock().unwrap();
+    let outputs = runOutputs.entry(folder_path.to_string()).or_default();
+
+    if outputs.iter().any(|output| output.pathPattern == pathPattern) {
+        return false;
+    }
+    outputs.push(RunOutput {
+        pathPattern: pathPattern.to_string(),
+        format: format.to_string(),
+        description: description.to_string(),
+        schema,
+    });
+    true
+}
+
+/**
+ * Get the files registered for a run folder
+ */
+pub fn getRunOutputs(folder_path: &str) -> Vec<RunOutput> {
+    let runOutputs = RUN_OUTPUTS.lock().unwrap();
+    runOutputs.get(folder_path).cloned().unwrap_or_default()
+}
+
+/**
+ * Document the layout of the run folder, every file written to it with its format, description and schema
+ */
+pub fn displayRunLayout(folder_path: String) -> io::Result<()> {
+    let outputs = getRunOutputs(&folder_path);
+
+    let layout = json!({
+        "description": "Layout of a sandpile run folder, series of files use <n> in place of the number in the file name",
+        "files": outputs.iter().map(|output| json!({
+            "path": output.pathPattern,
+            "format": output.format,
+            "description": output.description,
+            "schema": output.schema,
+        })).collect::<Vec<Value>>(),
+    });
+
+    let file = File::create(format!("{}/{}", folder_path, RUN_LAYOUT_FILE))?;
+    serde_json::to_writer_pretty(file, &layout)?;
+
+    Ok(())
+}
@@ -1,7 +1,10 @@
 // external modules
 extern crate rand;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use std::vec::Vec;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
 
 // internal model
 use crate::models::location::Location;
@@ -9,9 +12,35 @@ use crate::models::grain::Grain;
 use crate::models::avalanche::Avalanche;
 
 // constants
-use crate::util::constants::{ALPHA_MAIN, ALPHA_AVALANCHE_SIZE, X_SIZE, Y_SIZE, Z_SIZE, X_MIN};
+use crate::util::constants::{ALPHA_MAIN, ALPHA_AVALANCHE_SIZE, X_SIZE, Y_SIZE, Z_SIZE, X_MIN, RNG_SEED};
 
 
+lazy_static! { // Require the lazy_static crate to handle static Mutex
+    // source of all randomness in the run, seeded once so a run can be reproduced from its seed
+    static ref RNG: Mutex<StdRng> = Mutex::new(StdRng::seed_from_u64(0));
+}
+
+// Random number generation
+
+/**
+ * Seed the random number generator for the run with RNG_SEED, or with a random
+ * seed if RNG_SEED is 0, returns the seed that was used
+ */
+pub fn initializeRng() -> u64 {
+    let seed = if RNG_SEED == 0 { rand::thread_rng().gen::<u64>() } else { RNG_SEED };
+    *RNG.lock().unwrap() = StdRng::seed_from_u64(seed);
+    seed
+}
+
+/**
+ * Get a random number generator drawn from the seeded run generator
+ * the run generator is not held while the returned generator is in use
+ */
+pub fn seededRng() -> StdRng {
+    let seed = RNG.lock().unwrap().gen::<u64>();
+    StdRng::seed_from_u64(seed)
+}
+
 
 
 /**
@@ -30,6 +59,18 @@ fn powerLaw(alpha: f64, rnd: &mut impl Rng) -> f64 {
     X_MIN * (1.0 - uniform_rand).powf(-1.0 / (alpha - 1.0))
 }
 
+/**
+ * Round a value to one of the whole numbers either side of it, rounding up with a probability
+ * of its fractional part so the expected result is the value itself, negative values give 0
+ */
+pub fn stochasticRound(value: f64, rnd: &mut impl Rng) -> usize {
+    if value <= 0.0 {
+        return 0;
+    }
+    let whole = value.floor();
+    if rnd.gen::<f64>() < value - whole { whole as usize + 1 } else { whole as usize }
+}
+
 /**
  * 
  */
@@ -8,7 +8,9 @@ import matplotlib.pyplot as plt
 np.seterr(divide='ignore', invalid='ignore')
 
 
-data = np.genfromtxt('py-powerlaw-import.txt')
+# count column of the dense movement histogram, runs before the csv outputs wrote py-powerlaw-import.txt
+# with only the counts, read those with np.genfromtxt('py-powerlaw-import.txt')
+data = np.genfromtxt('py-powerlaw-import.csv', delimiter=',', skip_header=1, usecols=1)
 # results = powerlaw.Fit(data, discrete=True)
 # print(results.power_law.alpha)
 # print(results.power_law.xmin)